@@ -0,0 +1,286 @@
+//! Role-based access control shared by the marketplace contracts.
+//!
+//! Every contract stores its own role assignments and implements the
+//! `AccessControl` messages, so roles are granted per contract. The checks
+//! live in the functions below, which work on the contract's own mappings;
+//! contracts only delegate to them and emit the events. The owner holds every
+//! role after deployment and can never lose `Admin`; ownership moves in two
+//! steps so it cannot be handed to a mistyped address, and the new owner
+//! takes over every role the old one held.
+//!
+//! A `Pauser` can also stop individual operations when a bug is found. Only
+//! operations that take in funds or pay them out to providers can be paused;
+//! refunds and withdrawals never can, so users are always able to get their
+//! funds back.
+
+use ink::primitives::AccountId;
+use ink::storage::Mapping;
+use ink::storage::traits::{SpreadLayout, PackedLayout, StorageLayout};
+
+/// Permission to call a group of privileged messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, SpreadLayout, PackedLayout)]
+#[cfg_attr(feature = "std", derive(StorageLayout))]
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+pub enum Role {
+    Admin,            // Grants and revokes roles, sets contract parameters
+    FeeManager,       // Sets fees, stakes and deposits
+    ValidatorManager, // Adds and removes validators
+    Arbiter,          // Settles disputes and challenges
+    Pauser,           // Pauses and unpauses the contract
+}
+
+/// Every role, in declaration order
+pub const ALL_ROLES: [Role; 5] = [
+    Role::Admin,
+    Role::FeeManager,
+    Role::ValidatorManager,
+    Role::Arbiter,
+    Role::Pauser,
+];
+
+/// Group of messages a `Pauser` can stop
+#[derive(Debug, Clone, Copy, PartialEq, Eq, SpreadLayout, PackedLayout)]
+#[cfg_attr(feature = "std", derive(StorageLayout))]
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+pub enum Operation {
+    Registration,    // New datasets
+    Payments,        // New payments, public or private
+    Payouts,         // Escrow releases to dataset owners
+    ProofSubmission, // New proofs, single or aggregated
+    Verification,    // Proof verification by validators
+}
+
+/// Errors
+#[derive(Debug, PartialEq, Eq)]
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+pub enum AccessControlError {
+    MissingRole,
+    RoleAlreadyGranted,
+    RoleNotGranted,
+    OwnerMustBeAdmin,
+    NotOwner,
+    NotPendingOwner,
+    NotPausable,
+    AlreadyPaused,
+    NotPaused,
+}
+
+pub type AccessControlResult<T> = core::result::Result<T, AccessControlError>;
+
+/// Role assignments of one contract
+pub type Roles = Mapping<(Role, AccountId), bool>;
+
+/// Paused operations of one contract
+pub type PausedOperations = Mapping<Operation, bool>;
+
+/// Role assignments for a newly deployed contract: `owner` holds every role
+pub fn owner_roles(owner: AccountId) -> Roles {
+    let mut roles = Mapping::default();
+    grant_owner_roles(&mut roles, owner);
+    roles
+}
+
+/// Give `owner` every role, for storage written before roles existed
+pub fn grant_owner_roles(roles: &mut Roles, owner: AccountId) {
+    for role in ALL_ROLES {
+        roles.insert((role, owner), &true);
+    }
+}
+
+/// Check if account holds role
+pub fn has_role(roles: &Roles, role: Role, account: AccountId) -> bool {
+    roles.get((role, account)).unwrap_or(false)
+}
+
+/// Give account a role on behalf of `sender`, who must be Admin
+pub fn grant_role(
+    roles: &mut Roles,
+    sender: AccountId,
+    role: Role,
+    account: AccountId,
+) -> AccessControlResult<()> {
+    if !has_role(roles, Role::Admin, sender) {
+        return Err(AccessControlError::MissingRole);
+    }
+    if has_role(roles, role, account) {
+        return Err(AccessControlError::RoleAlreadyGranted);
+    }
+    roles.insert((role, account), &true);
+    Ok(())
+}
+
+/// Take a role away from account on behalf of `sender`, who must be Admin
+/// or the account itself. The owner never loses `Admin`.
+pub fn revoke_role(
+    roles: &mut Roles,
+    owner: AccountId,
+    sender: AccountId,
+    role: Role,
+    account: AccountId,
+) -> AccessControlResult<()> {
+    if sender != account && !has_role(roles, Role::Admin, sender) {
+        return Err(AccessControlError::MissingRole);
+    }
+    if role == Role::Admin && account == owner {
+        return Err(AccessControlError::OwnerMustBeAdmin);
+    }
+    if !has_role(roles, role, account) {
+        return Err(AccessControlError::RoleNotGranted);
+    }
+    roles.remove((role, account));
+    Ok(())
+}
+
+/// Check `sender` owns the contract
+pub fn ensure_owner(owner: AccountId, sender: AccountId) -> AccessControlResult<()> {
+    if sender != owner {
+        return Err(AccessControlError::NotOwner);
+    }
+    Ok(())
+}
+
+/// Let the pending owner `sender` take over, moving every role the old
+/// owner holds to them
+pub fn accept_ownership(
+    roles: &mut Roles,
+    owner: AccountId,
+    pending_owner: Option<AccountId>,
+    sender: AccountId,
+) -> AccessControlResult<()> {
+    if pending_owner != Some(sender) {
+        return Err(AccessControlError::NotPendingOwner);
+    }
+    for role in ALL_ROLES {
+        if has_role(roles, role, owner) {
+            roles.remove((role, owner));
+            roles.insert((role, sender), &true);
+        }
+    }
+    Ok(())
+}
+
+/// Check if an operation is paused
+pub fn is_paused(paused: &PausedOperations, operation: Operation) -> bool {
+    paused.get(operation).unwrap_or(false)
+}
+
+/// Stop one of the `pausable` operations on behalf of `sender`, who must be Pauser
+pub fn pause(
+    roles: &Roles,
+    paused: &mut PausedOperations,
+    pausable: &[Operation],
+    sender: AccountId,
+    operation: Operation,
+) -> AccessControlResult<()> {
+    if !has_role(roles, Role::Pauser, sender) {
+        return Err(AccessControlError::MissingRole);
+    }
+    if !pausable.contains(&operation) {
+        return Err(AccessControlError::NotPausable);
+    }
+    if is_paused(paused, operation) {
+        return Err(AccessControlError::AlreadyPaused);
+    }
+    paused.insert(operation, &true);
+    Ok(())
+}
+
+/// Resume a paused operation on behalf of `sender`, who must be Pauser
+pub fn unpause(
+    roles: &Roles,
+    paused: &mut PausedOperations,
+    sender: AccountId,
+    operation: Operation,
+) -> AccessControlResult<()> {
+    if !has_role(roles, Role::Pauser, sender) {
+        return Err(AccessControlError::MissingRole);
+    }
+    if !is_paused(paused, operation) {
+        return Err(AccessControlError::NotPaused);
+    }
+    paused.remove(operation);
+    Ok(())
+}
+
+/// Role management messages every marketplace contract exposes
+#[ink::trait_definition]
+pub trait AccessControl {
+    /// Check if account holds role
+    #[ink(message)]
+    fn has_role(&self, role: Role, account: AccountId) -> bool;
+
+    /// Give account a role (Admin only)
+    #[ink(message)]
+    fn grant_role(&mut self, role: Role, account: AccountId) -> AccessControlResult<()>;
+
+    /// Take a role away from account (Admin only)
+    #[ink(message)]
+    fn revoke_role(&mut self, role: Role, account: AccountId) -> AccessControlResult<()>;
+
+    /// Give up one of the caller's own roles
+    #[ink(message)]
+    fn renounce_role(&mut self, role: Role) -> AccessControlResult<()>;
+
+    /// Get the contract owner
+    #[ink(message)]
+    fn owner(&self) -> AccountId;
+
+    /// Offer ownership to another account (owner only)
+    #[ink(message)]
+    fn transfer_ownership(&mut self, new_owner: AccountId) -> AccessControlResult<()>;
+
+    /// Take over an offered ownership, along with every role the old owner holds
+    #[ink(message)]
+    fn accept_ownership(&mut self) -> AccessControlResult<()>;
+}
+
+/// Circuit breaker messages every marketplace contract exposes
+#[ink::trait_definition]
+pub trait Pausable {
+    /// Check if an operation is paused
+    #[ink(message)]
+    fn is_paused(&self, operation: Operation) -> bool;
+
+    /// Stop an operation this contract performs (Pauser only)
+    #[ink(message)]
+    fn pause(&mut self, operation: Operation) -> AccessControlResult<()>;
+
+    /// Resume a paused operation (Pauser only)
+    #[ink(message)]
+    fn unpause(&mut self, operation: Operation) -> AccessControlResult<()>;
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[ink::test]
+    fn test_accept_ownership_moves_every_role() {
+        let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+        let mut roles = owner_roles(accounts.alice);
+        revoke_role(&mut roles, accounts.alice, accounts.alice, Role::Arbiter, accounts.alice).unwrap();
+        grant_role(&mut roles, accounts.alice, Role::Pauser, accounts.charlie).unwrap();
+
+        assert_eq!(
+            accept_ownership(&mut roles, accounts.alice, Some(accounts.bob), accounts.charlie),
+            Err(AccessControlError::NotPendingOwner)
+        );
+        accept_ownership(&mut roles, accounts.alice, Some(accounts.bob), accounts.bob).unwrap();
+
+        // Roles the old owner gave up or others hold are left alone
+        for role in ALL_ROLES {
+            assert!(!has_role(&roles, role, accounts.alice));
+            assert_eq!(has_role(&roles, role, accounts.bob), role != Role::Arbiter);
+        }
+        assert!(has_role(&roles, Role::Pauser, accounts.charlie));
+        assert_eq!(
+            revoke_role(&mut roles, accounts.bob, accounts.charlie, Role::Admin, accounts.bob),
+            Err(AccessControlError::MissingRole)
+        );
+        assert_eq!(
+            revoke_role(&mut roles, accounts.bob, accounts.bob, Role::Admin, accounts.bob),
+            Err(AccessControlError::OwnerMustBeAdmin)
+        );
+    }
+}
@@ -1,12 +1,188 @@
 #![cfg_attr(not(feature = "std"), no_std, no_main)]
 
 #[ink::contract]
-mod dataset_registry {
-    use ink::storage::{Mapping};
-    use ink::storage::traits::{SpreadLayout, PackedLayout, StorageLayout};
+pub mod dataset_registry {
+    use ink::storage::{Lazy, Mapping};
+    use ink::storage::traits::{ManualKey, SpreadLayout, PackedLayout, StorageLayout};
+    use ink::primitives::KeyComposer;
     use ink::prelude::vec::Vec;
     use ink::prelude::string::String;
-    
+    use ink::env::call::{build_call, ExecutionInput, Selector};
+    use ink::env::DefaultEnvironment;
+    use crate::vector_math::{Metric, MAX_DIMENSION};
+    use crate::payment_manager::payment_manager::{PaymentReceipt, PaymentStatus};
+    use crate::oracle_connector::oracle_connector::is_valid_currency;
+    use crate::timelock::{
+        self, ParameterChange, QueuedChange, Timelock, TimelockError, TimelockResult,
+        DEFAULT_TIMELOCK_DELAY,
+    };
+    use crate::access_control::{
+        self, owner_roles, AccessControl, AccessControlResult, Operation, Pausable, Role,
+    };
+
+    /// Operations a Pauser can stop in this contract
+    const PAUSABLE_OPERATIONS: [Operation; 1] = [Operation::Registration];
+
+    /// Storage layout version written by this code
+    const STORAGE_VERSION: u32 = 2;
+    /// Key storage version 1 kept datasets under
+    const DATASETS_V1_KEY: u32 = KeyComposer::from_str("DatasetRegistry::datasets");
+    /// Key datasets are kept under since storage version 2
+    const DATASETS_KEY: u32 = KeyComposer::from_str("DatasetRegistry::datasets_v2");
+    /// Keys of the root fields added since storage version 1. Each lives in a
+    /// cell of its own so a version 1 root, which lacks them, still decodes.
+    const STORAGE_VERSION_KEY: u32 = KeyComposer::from_str("DatasetRegistry::storage_version");
+    const MIGRATION_CURSOR_KEY: u32 = KeyComposer::from_str("DatasetRegistry::migration_cursor");
+    const PENDING_OWNER_KEY: u32 = KeyComposer::from_str("DatasetRegistry::pending_owner");
+    const TIMELOCK_DELAY_KEY: u32 = KeyComposer::from_str("DatasetRegistry::timelock_delay");
+    const NEXT_CHANGE_ID_KEY: u32 = KeyComposer::from_str("DatasetRegistry::next_change_id");
+    const PAYMENT_MANAGER_KEY: u32 = KeyComposer::from_str("DatasetRegistry::payment_manager");
+
+    /// Longest embedding model identifier accepted, in bytes
+    pub const MAX_MODEL_ID_LENGTH: usize = 64;
+    /// Most tags a dataset can carry
+    pub const MAX_TAGS: usize = 8;
+    /// Longest tag or category accepted, in bytes
+    pub const MAX_LABEL_LENGTH: usize = 32;
+    /// Most datasets returned by one listing call
+    pub const MAX_PAGE_SIZE: usize = 100;
+    /// Most dataset IDs one listing call looks at before handing back a cursor
+    const MAX_PAGE_SCAN: usize = 1000;
+    /// Highest rating a review can give
+    pub const MAX_RATING: u8 = 5;
+
+    /// Number format the embeddings were produced in, before fixed-point encoding
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub enum Quantization {
+        Float32,
+        Float16,
+        Int8,
+        Binary,
+    }
+
+    /// Kind of content the embeddings were computed from
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub enum Modality {
+        Text,
+        Image,
+        Audio,
+        Video,
+        Multimodal,
+    }
+
+    /// Machine-readable description of a dataset's embeddings
+    #[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct DatasetSchema {
+        pub model_id: String, // Embedding model, e.g. "openai/text-embedding-3-small"
+        pub dimension: u32,
+        pub metric: Metric,
+        pub vector_count: u64, // Leaves in the embedding tree
+        pub quantization: Quantization,
+        pub modality: Modality,
+    }
+
+    /// Lifecycle state of a dataset
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub enum DatasetStatus {
+        Active,     // Sells queries
+        Paused,     // Owner stopped sales for now; pending queries are still proven
+        Deprecated, // Retired for good; pending queries are still proven
+        Delisted,   // Removed by the registry owner; pending queries can be reclaimed at once
+        Deleted,    // Removed by its owner, kept as an ID tombstone; pending queries can be reclaimed at once
+    }
+
+    impl DatasetStatus {
+        /// New payments are accepted
+        pub fn accepts_payments(&self) -> bool {
+            *self == DatasetStatus::Active
+        }
+
+        /// Proofs for pending payments are accepted
+        pub fn accepts_proofs(&self) -> bool {
+            matches!(self, DatasetStatus::Active | DatasetStatus::Paused | DatasetStatus::Deprecated)
+        }
+    }
+
+    /// Who may buy queries against a dataset
+    #[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub enum Visibility {
+        Public,
+        Allowlist,             // Only accounts on the dataset allowlist
+        TokenGated(AccountId), // Only holders of a token from this PSP34 contract
+    }
+
+    /// Review left by a buyer for one completed query
+    #[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct Review {
+        pub query_id: u64,
+        pub dataset_id: u64,
+        pub reviewer: AccountId,
+        pub rating: u8,                       // 1 to MAX_RATING
+        pub content_hash: [u8; 32],           // IPFS hash or similar of the review text
+        pub created_at: Timestamp,
+        pub response_hash: Option<[u8; 32]>, // Dataset owner's reply
+    }
+
+    /// Aggregate of all reviews of a dataset
+    #[derive(Debug, Clone, Default, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct RatingSummary {
+        pub review_count: u32,
+        pub rating_total: u64,
+    }
+
+    /// How a paid query ended, as reported by the payment manager
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub enum QuerySettlement {
+        Completed,
+        Refunded,
+        Released(Balance), // Escrow paid out; amount the dataset owner received
+    }
+
+    /// Usage counters of a dataset, next to `Dataset::total_queries`
+    #[derive(Debug, Clone, Default, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct QueryStats {
+        pub completed_queries: u64,
+        pub refunded_queries: u64,
+        pub total_revenue: Balance,
+    }
+
+    /// Criteria for `list_datasets`; `None` fields match every dataset
+    #[derive(Debug, Clone, PartialEq, Eq, Default)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct DatasetFilter {
+        pub owner: Option<AccountId>,
+        pub category: Option<String>,
+        pub tag: Option<String>,
+        pub metric: Option<Metric>,
+        pub max_price: Option<Balance>,
+        pub active_only: bool,
+    }
+
+    /// One page of a dataset listing
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct DatasetPage {
+        pub datasets: Vec<Dataset>,
+        pub next_cursor: Option<u64>, // Pass back as `cursor` to continue, `None` at the end
+    }
+
     /// Dataset information structure
     #[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
     #[cfg_attr(feature = "std", derive(StorageLayout))]
@@ -19,23 +195,130 @@ mod dataset_registry {
         pub embedding_root: [u8; 32], // Merkle root of embeddings
         pub metadata_hash: [u8; 32],  // IPFS hash or similar
         pub price_per_query: Balance,
+        pub status: DatasetStatus,
+        pub created_at: Timestamp,
+        pub total_queries: u64,
+        pub validator_nodes: Vec<AccountId>,
+        pub allowed_verification_keys: Vec<[u8; 32]>, // Key hashes accepted for proofs
+        pub visibility: Visibility,
+        pub schema: DatasetSchema,
+        pub category: String,  // Lowercase, empty if uncategorized
+        pub tags: Vec<String>, // Lowercase
+    }
+
+    /// Dataset as stored by storage version 1
+    #[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct DatasetV1 {
+        pub id: u64,
+        pub owner: AccountId,
+        pub name: String,
+        pub description: String,
+        pub embedding_root: [u8; 32],
+        pub metadata_hash: [u8; 32],
+        pub price_per_query: Balance,
         pub is_active: bool,
         pub created_at: Timestamp,
         pub total_queries: u64,
         pub validator_nodes: Vec<AccountId>,
     }
 
+    impl From<DatasetV1> for Dataset {
+        /// Version 1 recorded no schema, so migrated datasets come back paused
+        /// with an empty schema until their owner calls `set_schema`.
+        fn from(dataset: DatasetV1) -> Self {
+            Self {
+                id: dataset.id,
+                owner: dataset.owner,
+                name: dataset.name,
+                description: dataset.description,
+                embedding_root: dataset.embedding_root,
+                metadata_hash: dataset.metadata_hash,
+                price_per_query: dataset.price_per_query,
+                status: DatasetStatus::Paused,
+                created_at: dataset.created_at,
+                total_queries: dataset.total_queries,
+                validator_nodes: dataset.validator_nodes,
+                allowed_verification_keys: Vec::new(),
+                visibility: Visibility::Public,
+                schema: DatasetSchema {
+                    model_id: String::new(),
+                    dimension: 0,
+                    metric: Metric::Cosine,
+                    vector_count: 0,
+                    quantization: Quantization::Float32,
+                    modality: Modality::Text,
+                },
+                category: String::new(),
+                tags: Vec::new(),
+            }
+        }
+    }
+
+    /// Query price quoted in a reference currency, converted to native
+    /// tokens at payment time
+    #[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct FiatPrice {
+        pub currency: String, // Oracle currency code, e.g. "USD"
+        pub amount: u128,     // Millionths of the currency, see oracle_connector::FIAT_UNIT
+    }
+
     /// Dataset registry contract
     #[ink(storage)]
     pub struct DatasetRegistry {
         /// Mapping from dataset ID to dataset info
-        datasets: Mapping<u64, Dataset>,
+        datasets: Mapping<u64, Dataset, ManualKey<DATASETS_KEY>>,
+        /// Datasets not yet migrated from storage version 1
+        legacy_datasets: Mapping<u64, DatasetV1, ManualKey<DATASETS_V1_KEY>>,
         /// Mapping from owner to their dataset IDs
         owner_datasets: Mapping<AccountId, Vec<u64>>,
+        /// Datasets carrying each tag
+        tag_datasets: Mapping<(String, u64), ()>,
+        /// Number of datasets carrying each tag
+        tag_counts: Mapping<String, u32>,
+        /// Datasets in each category
+        category_datasets: Mapping<(String, u64), ()>,
+        /// Number of datasets in each category
+        category_counts: Mapping<String, u32>,
+        /// Mapping from query ID to its review
+        reviews: Mapping<u64, Review>,
+        /// Mapping from dataset ID to the query IDs of its reviews
+        dataset_reviews: Mapping<u64, Vec<u64>>,
+        /// Mapping from dataset ID to its rating aggregate
+        ratings: Mapping<u64, RatingSummary>,
+        /// Mapping from dataset ID to its usage counters
+        query_stats: Mapping<u64, QueryStats>,
+        /// Payment manager contract address, used to check purchases
+        payment_manager: Lazy<AccountId, ManualKey<PAYMENT_MANAGER_KEY>>,
+        /// Accounts allowed to buy queries against allowlist datasets
+        allowlists: Mapping<(u64, AccountId), bool>,
+        /// Mapping from dataset ID to its reference currency price
+        fiat_prices: Mapping<u64, FiatPrice>,
         /// Next available dataset ID
         next_dataset_id: u64,
         /// Contract owner
         owner: AccountId,
+        /// Account ownership was offered to
+        pending_owner: Lazy<Option<AccountId>, ManualKey<PENDING_OWNER_KEY>>,
+        /// Role assignments
+        roles: Mapping<(Role, AccountId), bool>,
+        /// Operations stopped by a Pauser
+        paused: Mapping<Operation, bool>,
+        /// Storage layout version, behind `STORAGE_VERSION` until `migrate`
+        /// finishes; unset in version 1 storage
+        storage_version: Lazy<u32, ManualKey<STORAGE_VERSION_KEY>>,
+        /// Next ID `migrate` looks at, unset until it first runs
+        migration_cursor: Lazy<u64, ManualKey<MIGRATION_CURSOR_KEY>>,
+        /// Time between queueing and executing a parameter change,
+        /// `DEFAULT_TIMELOCK_DELAY` until changed
+        timelock_delay: Lazy<u64, ManualKey<TIMELOCK_DELAY_KEY>>,
+        /// Parameter changes waiting for their ETA
+        queued_changes: Mapping<u64, QueuedChange>,
+        /// Next parameter change ID, 1 until a change is queued
+        next_change_id: Lazy<u64, ManualKey<NEXT_CHANGE_ID_KEY>>,
         /// Registration fee
         registration_fee: Balance,
     }
@@ -56,7 +339,16 @@ mod dataset_registry {
         #[ink(topic)]
         dataset_id: u64,
         price_per_query: Balance,
-        is_active: bool,
+    }
+
+    #[ink(event)]
+    pub struct DatasetStatusChanged {
+        #[ink(topic)]
+        dataset_id: u64,
+        #[ink(topic)]
+        changed_by: AccountId,
+        old_status: DatasetStatus,
+        new_status: DatasetStatus,
     }
 
     #[ink(event)]
@@ -66,6 +358,160 @@ mod dataset_registry {
         validator: AccountId,
     }
 
+    #[ink(event)]
+    pub struct CategoryUpdated {
+        #[ink(topic)]
+        dataset_id: u64,
+        category: String,
+    }
+
+    #[ink(event)]
+    pub struct TagsUpdated {
+        #[ink(topic)]
+        dataset_id: u64,
+        tags: Vec<String>,
+    }
+
+    #[ink(event)]
+    pub struct FiatPriceChanged {
+        #[ink(topic)]
+        dataset_id: u64,
+        price: Option<FiatPrice>,
+    }
+
+    #[ink(event)]
+    pub struct VisibilityChanged {
+        #[ink(topic)]
+        dataset_id: u64,
+        visibility: Visibility,
+    }
+
+    #[ink(event)]
+    pub struct AllowlistUpdated {
+        #[ink(topic)]
+        dataset_id: u64,
+        #[ink(topic)]
+        account: AccountId,
+        allowed: bool,
+    }
+
+    #[ink(event)]
+    pub struct ReviewSubmitted {
+        #[ink(topic)]
+        dataset_id: u64,
+        #[ink(topic)]
+        query_id: u64,
+        #[ink(topic)]
+        reviewer: AccountId,
+        rating: u8,
+        content_hash: [u8; 32],
+    }
+
+    #[ink(event)]
+    pub struct ReviewResponded {
+        #[ink(topic)]
+        dataset_id: u64,
+        #[ink(topic)]
+        query_id: u64,
+        response_hash: [u8; 32],
+    }
+
+    #[ink(event)]
+    pub struct VerificationKeyAllowed {
+        #[ink(topic)]
+        dataset_id: u64,
+        #[ink(topic)]
+        key_hash: [u8; 32],
+    }
+
+    #[ink(event)]
+    pub struct VerificationKeyDisallowed {
+        #[ink(topic)]
+        dataset_id: u64,
+        #[ink(topic)]
+        key_hash: [u8; 32],
+    }
+
+    #[ink(event)]
+    pub struct RoleGranted {
+        #[ink(topic)]
+        account: AccountId,
+        #[ink(topic)]
+        sender: AccountId,
+        role: Role,
+    }
+
+    #[ink(event)]
+    pub struct RoleRevoked {
+        #[ink(topic)]
+        account: AccountId,
+        #[ink(topic)]
+        sender: AccountId,
+        role: Role,
+    }
+
+    #[ink(event)]
+    pub struct OwnershipOffered {
+        #[ink(topic)]
+        owner: AccountId,
+        #[ink(topic)]
+        pending_owner: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct OwnershipTransferred {
+        #[ink(topic)]
+        previous_owner: AccountId,
+        #[ink(topic)]
+        new_owner: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct ChangeQueued {
+        #[ink(topic)]
+        change_id: u64,
+        change: ParameterChange,
+        eta: Timestamp,
+    }
+
+    #[ink(event)]
+    pub struct ChangeCancelled {
+        #[ink(topic)]
+        change_id: u64,
+    }
+
+    #[ink(event)]
+    pub struct ChangeExecuted {
+        #[ink(topic)]
+        change_id: u64,
+        change: ParameterChange,
+    }
+
+    #[ink(event)]
+    pub struct Upgraded {
+        #[ink(topic)]
+        code_hash: Hash,
+    }
+
+    #[ink(event)]
+    pub struct StorageMigrated {
+        version: u32,
+    }
+
+    #[ink(event)]
+    pub struct Paused {
+        #[ink(topic)]
+        account: AccountId,
+        operation: Operation,
+    }
+
+    #[ink(event)]
+    pub struct Unpaused {
+        #[ink(topic)]
+        account: AccountId,
+        operation: Operation,
+    }
+
     /// Errors
     #[derive(Debug, PartialEq, Eq)]
     #[ink::scale_derive(Encode, Decode, TypeInfo)]
@@ -77,21 +523,65 @@ mod dataset_registry {
         DatasetInactive,
         ValidatorAlreadyExists,
         InvalidParameters,
+        VerificationKeyAlreadyAllowed,
+        VerificationKeyNotAllowed,
+        AlreadyAllowlisted,
+        NotAllowlisted,
+        InvalidSchema,
+        InvalidLabel,
+        TooManyTags,
+        PaymentManagerNotSet,
+        PaymentNotCompleted,
+        InvalidRating,
+        AlreadyReviewed,
+        ReviewNotFound,
+        ResponseAlreadyPosted,
+        InvalidStatusTransition,
+        OperationPaused,
+        UpgradeFailed,
+        AlreadyMigrated,
+        SchemaAlreadySet,
     }
 
     pub type Result<T> = core::result::Result<T, Error>;
 
     impl DatasetRegistry {
-        /// Constructor
+        /// Constructor. `payment_manager` is the only account allowed to
+        /// update query counters and check purchases; a `PaymentManager`
+        /// change replaces it.
         #[ink(constructor)]
-        pub fn new(registration_fee: Balance) -> Self {
-            Self {
+        pub fn new(registration_fee: Balance, payment_manager: AccountId) -> Self {
+            let owner = Self::env().caller();
+            let mut instance = Self {
                 datasets: Mapping::default(),
+                legacy_datasets: Mapping::default(),
                 owner_datasets: Mapping::default(),
+                tag_datasets: Mapping::default(),
+                tag_counts: Mapping::default(),
+                category_datasets: Mapping::default(),
+                category_counts: Mapping::default(),
+                allowlists: Mapping::default(),
+                fiat_prices: Mapping::default(),
+                reviews: Mapping::default(),
+                dataset_reviews: Mapping::default(),
+                ratings: Mapping::default(),
+                query_stats: Mapping::default(),
+                payment_manager: Lazy::new(),
                 next_dataset_id: 1,
-                owner: Self::env().caller(),
+                owner,
+                pending_owner: Lazy::new(),
+                roles: owner_roles(owner),
+                paused: Mapping::default(),
+                storage_version: Lazy::new(),
+                migration_cursor: Lazy::new(),
+                timelock_delay: Lazy::new(),
+                queued_changes: Mapping::default(),
+                next_change_id: Lazy::new(),
                 registration_fee,
-            }
+            };
+            instance.storage_version.set(&STORAGE_VERSION);
+            instance.payment_manager.set(&payment_manager);
+            instance
         }
 
         /// Register a new dataset
@@ -103,7 +593,10 @@ mod dataset_registry {
             embedding_root: [u8; 32],
             metadata_hash: [u8; 32],
             price_per_query: Balance,
+            schema: DatasetSchema,
         ) -> Result<u64> {
+            self.ensure_not_paused(Operation::Registration)?;
+
             let caller = self.env().caller();
             let value = self.env().transferred_value();
 
@@ -116,6 +609,7 @@ mod dataset_registry {
             if name.is_empty() || price_per_query == 0 {
                 return Err(Error::InvalidParameters);
             }
+            Self::check_schema(&schema)?;
 
             let dataset_id = self.next_dataset_id;
             let now = self.env().block_timestamp();
@@ -128,10 +622,15 @@ mod dataset_registry {
                 embedding_root,
                 metadata_hash,
                 price_per_query,
-                is_active: true,
+                status: DatasetStatus::Active,
                 created_at: now,
                 total_queries: 0,
                 validator_nodes: Vec::new(),
+                allowed_verification_keys: Vec::new(),
+                visibility: Visibility::Public,
+                schema,
+                category: String::new(),
+                tags: Vec::new(),
             };
 
             // Store dataset
@@ -161,7 +660,6 @@ mod dataset_registry {
             &mut self,
             dataset_id: u64,
             price_per_query: Option<Balance>,
-            is_active: Option<bool>,
         ) -> Result<()> {
             let caller = self.env().caller();
             let mut dataset = self.datasets.get(&dataset_id).ok_or(Error::DatasetNotFound)?;
@@ -170,12 +668,15 @@ mod dataset_registry {
                 return Err(Error::NotOwner);
             }
 
-            if let Some(price) = price_per_query {
-                dataset.price_per_query = price;
+            if dataset.status == DatasetStatus::Deleted {
+                return Err(Error::DatasetInactive);
             }
 
-            if let Some(active) = is_active {
-                dataset.is_active = active;
+            if let Some(price) = price_per_query {
+                if price == 0 {
+                    return Err(Error::InvalidParameters);
+                }
+                dataset.price_per_query = price;
             }
 
             self.datasets.insert(dataset_id, &dataset);
@@ -183,12 +684,33 @@ mod dataset_registry {
             self.env().emit_event(DatasetUpdated {
                 dataset_id,
                 price_per_query: dataset.price_per_query,
-                is_active: dataset.is_active,
             });
 
             Ok(())
         }
 
+        /// Move dataset to another lifecycle state. The dataset owner can
+        /// pause and resume it, deprecate it, and delete it once it no longer
+        /// sells. Only an Arbiter can delist a dataset or reinstate a delisted
+        /// one, which comes back paused. Deleted is final. Governance delists
+        /// through a timelocked `Delist` change instead.
+        #[ink(message)]
+        pub fn set_dataset_status(&mut self, dataset_id: u64, status: DatasetStatus) -> Result<()> {
+            use DatasetStatus::*;
+
+            let caller = self.env().caller();
+            let dataset = self.datasets.get(&dataset_id).ok_or(Error::DatasetNotFound)?;
+
+            let governance = matches!((dataset.status, status), (_, Delisted) | (Delisted, Paused));
+            if governance {
+                self.ensure_role(Role::Arbiter)?;
+            } else if caller != dataset.owner {
+                return Err(Error::NotOwner);
+            }
+
+            self.change_status(dataset_id, dataset, status, caller)
+        }
+
         /// Add validator node to dataset
         #[ink(message)]
         pub fn add_validator(
@@ -218,70 +740,1101 @@ mod dataset_registry {
             Ok(())
         }
 
-        /// Get dataset information
+        /// Allow proofs for dataset to use a verification key
         #[ink(message)]
-        pub fn get_dataset(&self, dataset_id: u64) -> Option<Dataset> {
-            self.datasets.get(&dataset_id)
-        }
+        pub fn allow_verification_key(
+            &mut self,
+            dataset_id: u64,
+            key_hash: [u8; 32],
+        ) -> Result<()> {
+            let caller = self.env().caller();
+            let mut dataset = self.datasets.get(&dataset_id).ok_or(Error::DatasetNotFound)?;
 
-        /// Get datasets by owner
-        #[ink(message)]
-        pub fn get_datasets_by_owner(&self, owner: AccountId) -> Vec<u64> {
-            self.owner_datasets.get(&owner).unwrap_or_default()
-        }
+            if dataset.owner != caller {
+                return Err(Error::NotOwner);
+            }
 
-        /// Increment query count (called by payment contract)
-        #[ink(message)]
-        pub fn increment_query_count(&mut self, dataset_id: u64) -> Result<()> {
-            let mut dataset = self.datasets.get(&dataset_id).ok_or(Error::DatasetNotFound)?;
-            dataset.total_queries += 1;
+            if dataset.allowed_verification_keys.contains(&key_hash) {
+                return Err(Error::VerificationKeyAlreadyAllowed);
+            }
+
+            dataset.allowed_verification_keys.push(key_hash);
             self.datasets.insert(dataset_id, &dataset);
+
+            self.env().emit_event(VerificationKeyAllowed {
+                dataset_id,
+                key_hash,
+            });
+
             Ok(())
         }
 
-        /// Check if dataset is active and get price
+        /// Stop accepting a verification key for dataset proofs
         #[ink(message)]
-        pub fn get_query_price(&self, dataset_id: u64) -> Result<Balance> {
-            let dataset = self.datasets.get(&dataset_id).ok_or(Error::DatasetNotFound)?;
-            
-            if !dataset.is_active {
-                return Err(Error::DatasetInactive);
+        pub fn disallow_verification_key(
+            &mut self,
+            dataset_id: u64,
+            key_hash: [u8; 32],
+        ) -> Result<()> {
+            let caller = self.env().caller();
+            let mut dataset = self.datasets.get(&dataset_id).ok_or(Error::DatasetNotFound)?;
+
+            if dataset.owner != caller {
+                return Err(Error::NotOwner);
             }
 
-            Ok(dataset.price_per_query)
-        }
+            let position = dataset.allowed_verification_keys
+                .iter()
+                .position(|hash| *hash == key_hash)
+                .ok_or(Error::VerificationKeyNotAllowed)?;
+            dataset.allowed_verification_keys.remove(position);
+            self.datasets.insert(dataset_id, &dataset);
 
-        /// Get validator nodes for dataset
-        #[ink(message)]
-        pub fn get_validators(&self, dataset_id: u64) -> Result<Vec<AccountId>> {
-            let dataset = self.datasets.get(&dataset_id).ok_or(Error::DatasetNotFound)?;
-            Ok(dataset.validator_nodes)
-        }
+            self.env().emit_event(VerificationKeyDisallowed {
+                dataset_id,
+                key_hash,
+            });
 
-        /// Admin functions
-        #[ink(message)]
-        pub fn set_registration_fee(&mut self, fee: Balance) -> Result<()> {
-            if self.env().caller() != self.owner {
-                return Err(Error::NotAuthorized);
-            }
-            self.registration_fee = fee;
             Ok(())
         }
 
+        /// Check if dataset accepts proofs under a verification key
+        #[ink(message)]
+        pub fn is_verification_key_allowed(&self, dataset_id: u64, key_hash: [u8; 32]) -> bool {
+            self.datasets
+                .get(&dataset_id)
+                .map_or(false, |dataset| dataset.allowed_verification_keys.contains(&key_hash))
+        }
+
+        /// Set dataset category; an empty category removes it
+        #[ink(message)]
+        pub fn set_category(&mut self, dataset_id: u64, category: String) -> Result<()> {
+            let caller = self.env().caller();
+            let mut dataset = self.datasets.get(&dataset_id).ok_or(Error::DatasetNotFound)?;
+
+            if dataset.owner != caller {
+                return Err(Error::NotOwner);
+            }
+
+            if dataset.status == DatasetStatus::Deleted {
+                return Err(Error::DatasetInactive);
+            }
+
+            let category = if category.is_empty() {
+                category
+            } else {
+                Self::normalize_label(&category)?
+            };
+
+            if !dataset.category.is_empty() {
+                Self::index_remove(
+                    &mut self.category_datasets,
+                    &mut self.category_counts,
+                    &dataset.category,
+                    dataset_id,
+                );
+            }
+            if !category.is_empty() {
+                Self::index_insert(
+                    &mut self.category_datasets,
+                    &mut self.category_counts,
+                    &category,
+                    dataset_id,
+                );
+            }
+
+            dataset.category = category.clone();
+            self.datasets.insert(dataset_id, &dataset);
+
+            self.env().emit_event(CategoryUpdated {
+                dataset_id,
+                category,
+            });
+
+            Ok(())
+        }
+
+        /// Replace dataset tags. Tags are lowercased and duplicates dropped.
+        #[ink(message)]
+        pub fn set_tags(&mut self, dataset_id: u64, tags: Vec<String>) -> Result<()> {
+            let caller = self.env().caller();
+            let mut dataset = self.datasets.get(&dataset_id).ok_or(Error::DatasetNotFound)?;
+
+            if dataset.owner != caller {
+                return Err(Error::NotOwner);
+            }
+
+            if dataset.status == DatasetStatus::Deleted {
+                return Err(Error::DatasetInactive);
+            }
+
+            let mut normalized: Vec<String> = Vec::new();
+            for tag in &tags {
+                let tag = Self::normalize_label(tag)?;
+                if !normalized.contains(&tag) {
+                    normalized.push(tag);
+                }
+            }
+            if normalized.len() > MAX_TAGS {
+                return Err(Error::TooManyTags);
+            }
+
+            for tag in &dataset.tags {
+                Self::index_remove(&mut self.tag_datasets, &mut self.tag_counts, tag, dataset_id);
+            }
+            for tag in &normalized {
+                Self::index_insert(&mut self.tag_datasets, &mut self.tag_counts, tag, dataset_id);
+            }
+
+            dataset.tags = normalized.clone();
+            self.datasets.insert(dataset_id, &dataset);
+
+            self.env().emit_event(TagsUpdated {
+                dataset_id,
+                tags: normalized,
+            });
+
+            Ok(())
+        }
+
+        /// Quote dataset's query price in a reference currency, or go back to
+        /// `price_per_query` with `None`. The payment manager converts a
+        /// quoted price at the oracle rate when a query is paid.
+        #[ink(message)]
+        pub fn set_fiat_price(&mut self, dataset_id: u64, price: Option<FiatPrice>) -> Result<()> {
+            self.ensure_dataset_owner(dataset_id)?;
+
+            match &price {
+                Some(fiat_price) => {
+                    if fiat_price.amount == 0 || !is_valid_currency(&fiat_price.currency) {
+                        return Err(Error::InvalidParameters);
+                    }
+                    self.fiat_prices.insert(dataset_id, fiat_price);
+                }
+                None => self.fiat_prices.remove(&dataset_id),
+            }
+
+            self.env().emit_event(FiatPriceChanged {
+                dataset_id,
+                price,
+            });
+
+            Ok(())
+        }
+
+        /// Get the reference currency price of dataset, if it has one
+        #[ink(message)]
+        pub fn get_fiat_price(&self, dataset_id: u64) -> Option<FiatPrice> {
+            self.fiat_prices.get(&dataset_id)
+        }
+
+        /// Change who may buy queries against dataset
+        #[ink(message)]
+        pub fn set_visibility(&mut self, dataset_id: u64, visibility: Visibility) -> Result<()> {
+            let caller = self.env().caller();
+            let mut dataset = self.datasets.get(&dataset_id).ok_or(Error::DatasetNotFound)?;
+
+            if dataset.owner != caller {
+                return Err(Error::NotOwner);
+            }
+
+            dataset.visibility = visibility.clone();
+            self.datasets.insert(dataset_id, &dataset);
+
+            self.env().emit_event(VisibilityChanged {
+                dataset_id,
+                visibility,
+            });
+
+            Ok(())
+        }
+
+        /// Add account to dataset allowlist
+        #[ink(message)]
+        pub fn add_to_allowlist(&mut self, dataset_id: u64, account: AccountId) -> Result<()> {
+            self.ensure_dataset_owner(dataset_id)?;
+
+            if self.allowlists.contains(&(dataset_id, account)) {
+                return Err(Error::AlreadyAllowlisted);
+            }
+
+            self.allowlists.insert((dataset_id, account), &true);
+
+            self.env().emit_event(AllowlistUpdated {
+                dataset_id,
+                account,
+                allowed: true,
+            });
+
+            Ok(())
+        }
+
+        /// Remove account from dataset allowlist
+        #[ink(message)]
+        pub fn remove_from_allowlist(&mut self, dataset_id: u64, account: AccountId) -> Result<()> {
+            self.ensure_dataset_owner(dataset_id)?;
+
+            if !self.allowlists.contains(&(dataset_id, account)) {
+                return Err(Error::NotAllowlisted);
+            }
+
+            self.allowlists.remove(&(dataset_id, account));
+
+            self.env().emit_event(AllowlistUpdated {
+                dataset_id,
+                account,
+                allowed: false,
+            });
+
+            Ok(())
+        }
+
+        /// Check if account is on dataset allowlist
+        #[ink(message)]
+        pub fn is_allowlisted(&self, dataset_id: u64, account: AccountId) -> bool {
+            self.allowlists.get(&(dataset_id, account)).unwrap_or(false)
+        }
+
+        /// Check if account may buy queries against dataset. The dataset
+        /// owner always can.
+        #[ink(message)]
+        pub fn is_authorized_buyer(&self, dataset_id: u64, account: AccountId) -> bool {
+            let dataset = match self.datasets.get(&dataset_id) {
+                Some(dataset) => dataset,
+                None => return false,
+            };
+
+            if account == dataset.owner {
+                return true;
+            }
+
+            match dataset.visibility {
+                Visibility::Public => true,
+                Visibility::Allowlist => self.is_allowlisted(dataset_id, account),
+                Visibility::TokenGated(token) => self.token_balance(token, account) > 0,
+            }
+        }
+
+        /// Get dataset information
+        #[ink(message)]
+        pub fn get_dataset(&self, dataset_id: u64) -> Option<Dataset> {
+            self.datasets.get(&dataset_id)
+        }
+
+        /// Get the embedding schema of dataset
+        #[ink(message)]
+        pub fn get_schema(&self, dataset_id: u64) -> Option<DatasetSchema> {
+            self.datasets.get(&dataset_id).map(|dataset| dataset.schema)
+        }
+
+        /// List datasets matching `filter` in ascending ID order, starting at
+        /// ID `cursor`. Returns at most `limit` datasets (at least 1, capped at
+        /// `MAX_PAGE_SIZE`); a page can come back short with a cursor when many
+        /// datasets in a row do not match.
+        #[ink(message)]
+        pub fn list_datasets(&self, cursor: u64, limit: u32, filter: DatasetFilter) -> DatasetPage {
+            let start = cursor.max(1);
+            let filter = DatasetFilter {
+                category: filter.category.map(|category| category.to_ascii_lowercase()),
+                tag: filter.tag.map(|tag| tag.to_ascii_lowercase()),
+                ..filter
+            };
+            self.collect_page(start..self.next_dataset_id, limit, &filter)
+        }
+
+        /// List datasets carrying a tag, see `list_datasets`
+        #[ink(message)]
+        pub fn list_by_tag(&self, tag: String, cursor: u64, limit: u32) -> DatasetPage {
+            let filter = DatasetFilter {
+                tag: Some(tag),
+                ..Default::default()
+            };
+            self.list_datasets(cursor, limit, filter)
+        }
+
+        /// Get the number of datasets carrying a tag
+        #[ink(message)]
+        pub fn get_tag_count(&self, tag: String) -> u32 {
+            self.tag_counts.get(&tag.to_ascii_lowercase()).unwrap_or(0)
+        }
+
+        /// Get the number of datasets in a category
+        #[ink(message)]
+        pub fn get_category_count(&self, category: String) -> u32 {
+            self.category_counts.get(&category.to_ascii_lowercase()).unwrap_or(0)
+        }
+
+        /// Review a dataset. Only the user of a completed payment can review,
+        /// once per query, and never their own dataset.
+        #[ink(message)]
+        pub fn submit_review(
+            &mut self,
+            query_id: u64,
+            rating: u8,
+            content_hash: [u8; 32],
+        ) -> Result<()> {
+            let caller = self.env().caller();
+            let now = self.env().block_timestamp();
+
+            if rating == 0 || rating > MAX_RATING {
+                return Err(Error::InvalidRating);
+            }
+
+            if self.reviews.contains(&query_id) {
+                return Err(Error::AlreadyReviewed);
+            }
+
+            let purchase = self.get_purchase(query_id)?.ok_or(Error::PaymentNotCompleted)?;
+            if purchase.status != PaymentStatus::Completed {
+                return Err(Error::PaymentNotCompleted);
+            }
+            if purchase.user != caller {
+                return Err(Error::NotAuthorized);
+            }
+
+            let dataset_id = purchase.dataset_id;
+            let dataset = self.datasets.get(&dataset_id).ok_or(Error::DatasetNotFound)?;
+            if dataset.owner == caller {
+                return Err(Error::NotAuthorized);
+            }
+
+            let review = Review {
+                query_id,
+                dataset_id,
+                reviewer: caller,
+                rating,
+                content_hash,
+                created_at: now,
+                response_hash: None,
+            };
+            self.reviews.insert(query_id, &review);
+
+            let mut review_list = self.dataset_reviews.get(&dataset_id).unwrap_or_default();
+            review_list.push(query_id);
+            self.dataset_reviews.insert(dataset_id, &review_list);
+
+            let mut summary = self.ratings.get(&dataset_id).unwrap_or_default();
+            summary.review_count += 1;
+            summary.rating_total += rating as u64;
+            self.ratings.insert(dataset_id, &summary);
+
+            self.env().emit_event(ReviewSubmitted {
+                dataset_id,
+                query_id,
+                reviewer: caller,
+                rating,
+                content_hash,
+            });
+
+            Ok(())
+        }
+
+        /// Reply to a review (dataset owner only, once per review)
+        #[ink(message)]
+        pub fn respond_to_review(&mut self, query_id: u64, response_hash: [u8; 32]) -> Result<()> {
+            let mut review = self.reviews.get(&query_id).ok_or(Error::ReviewNotFound)?;
+            self.ensure_dataset_owner(review.dataset_id)?;
+
+            if review.response_hash.is_some() {
+                return Err(Error::ResponseAlreadyPosted);
+            }
+
+            review.response_hash = Some(response_hash);
+            self.reviews.insert(query_id, &review);
+
+            self.env().emit_event(ReviewResponded {
+                dataset_id: review.dataset_id,
+                query_id,
+                response_hash,
+            });
+
+            Ok(())
+        }
+
+        /// Get the review left for a query
+        #[ink(message)]
+        pub fn get_review(&self, query_id: u64) -> Option<Review> {
+            self.reviews.get(&query_id)
+        }
+
+        /// Get the query IDs of a dataset's reviews
+        #[ink(message)]
+        pub fn get_dataset_reviews(&self, dataset_id: u64) -> Vec<u64> {
+            self.dataset_reviews.get(&dataset_id).unwrap_or_default()
+        }
+
+        /// Get review count and rating total for dataset
+        #[ink(message)]
+        pub fn get_rating_summary(&self, dataset_id: u64) -> RatingSummary {
+            self.ratings.get(&dataset_id).unwrap_or_default()
+        }
+
+        /// Get average rating in hundredths (450 is 4.5), `None` without reviews
+        #[ink(message)]
+        pub fn get_average_rating(&self, dataset_id: u64) -> Option<u32> {
+            let summary = self.ratings.get(&dataset_id)?;
+            if summary.review_count == 0 {
+                return None;
+            }
+            Some((summary.rating_total * 100 / summary.review_count as u64) as u32)
+        }
+
+        /// Get datasets by owner
+        #[ink(message)]
+        pub fn get_datasets_by_owner(&self, owner: AccountId) -> Vec<u64> {
+            self.owner_datasets.get(&owner).unwrap_or_default()
+        }
+
+        /// Increment query count (payment manager only, when a query is paid)
+        #[ink(message)]
+        pub fn increment_query_count(&mut self, dataset_id: u64) -> Result<()> {
+            self.ensure_payment_manager()?;
+            let mut dataset = self.datasets.get(&dataset_id).ok_or(Error::DatasetNotFound)?;
+            dataset.total_queries += 1;
+            self.datasets.insert(dataset_id, &dataset);
+            Ok(())
+        }
+
+        /// Count how a paid query ended (payment manager only)
+        #[ink(message)]
+        pub fn record_query_settlement(
+            &mut self,
+            dataset_id: u64,
+            settlement: QuerySettlement,
+        ) -> Result<()> {
+            self.ensure_payment_manager()?;
+            if !self.datasets.contains(&dataset_id) {
+                return Err(Error::DatasetNotFound);
+            }
+
+            let mut stats = self.query_stats.get(&dataset_id).unwrap_or_default();
+            match settlement {
+                QuerySettlement::Completed => stats.completed_queries += 1,
+                QuerySettlement::Refunded => stats.refunded_queries += 1,
+                QuerySettlement::Released(amount) => {
+                    stats.total_revenue = stats.total_revenue.saturating_add(amount)
+                }
+            }
+            self.query_stats.insert(dataset_id, &stats);
+            Ok(())
+        }
+
+        /// Get completed and refunded query counts and revenue of dataset
+        #[ink(message)]
+        pub fn get_query_stats(&self, dataset_id: u64) -> QueryStats {
+            self.query_stats.get(&dataset_id).unwrap_or_default()
+        }
+
+        /// Check if dataset accepts new payments and get price
+        #[ink(message)]
+        pub fn get_query_price(&self, dataset_id: u64) -> Result<Balance> {
+            let dataset = self.datasets.get(&dataset_id).ok_or(Error::DatasetNotFound)?;
+            
+            if !dataset.status.accepts_payments() {
+                return Err(Error::DatasetInactive);
+            }
+
+            Ok(dataset.price_per_query)
+        }
+
+        /// Get validator nodes for dataset
+        #[ink(message)]
+        pub fn get_validators(&self, dataset_id: u64) -> Result<Vec<AccountId>> {
+            let dataset = self.datasets.get(&dataset_id).ok_or(Error::DatasetNotFound)?;
+            Ok(dataset.validator_nodes)
+        }
+
+        /// Admin functions. The registration fee is changed through `Timelock`.
         #[ink(message)]
         pub fn get_registration_fee(&self) -> Balance {
             self.registration_fee
         }
+
+        /// Get the payment manager, set by the constructor or a `PaymentManager`
+        /// change. Storage migrated from version 1 has none until that change.
+        #[ink(message)]
+        pub fn get_payment_manager(&self) -> Option<AccountId> {
+            self.payment_manager.get()
+        }
+
+        /// Set the schema of a dataset migrated from storage version 1, which
+        /// has none (dataset owner only). A schema cannot be changed once set.
+        #[ink(message)]
+        pub fn set_schema(&mut self, dataset_id: u64, schema: DatasetSchema) -> Result<()> {
+            self.ensure_dataset_owner(dataset_id)?;
+            let mut dataset = self.datasets.get(&dataset_id).ok_or(Error::DatasetNotFound)?;
+
+            if dataset.schema.dimension != 0 {
+                return Err(Error::SchemaAlreadySet);
+            }
+            Self::check_schema(&schema)?;
+
+            dataset.schema = schema;
+            self.datasets.insert(dataset_id, &dataset);
+
+            Ok(())
+        }
+
+        /// Convert datasets left in the storage version 1 layout (owner only,
+        /// as version 1 storage has no roles yet). Looks at up to `limit` IDs
+        /// per call so large stores can be migrated over several blocks, and
+        /// returns how many records were converted. The storage version is
+        /// bumped once every ID has been looked at.
+        #[ink(message)]
+        pub fn migrate(&mut self, limit: u32) -> Result<u32> {
+            if self.env().caller() != self.owner {
+                return Err(Error::NotAuthorized);
+            }
+            if self.get_storage_version() >= STORAGE_VERSION {
+                return Err(Error::AlreadyMigrated);
+            }
+
+            // Version 1 had no roles; the owner takes all of them, as on deployment
+            let mut id = match self.migration_cursor.get() {
+                Some(cursor) => cursor,
+                None => {
+                    access_control::grant_owner_roles(&mut self.roles, self.owner);
+                    0
+                }
+            };
+            let end = id.saturating_add(limit as u64).min(self.next_dataset_id);
+            let mut migrated = 0;
+            while id < end {
+                if let Some(record) = self.legacy_datasets.take(&id) {
+                    self.datasets.insert(id, &Dataset::from(record));
+                    migrated += 1;
+                }
+                id += 1;
+            }
+            self.migration_cursor.set(&id);
+
+            if id >= self.next_dataset_id {
+                self.storage_version.set(&STORAGE_VERSION);
+                self.env().emit_event(StorageMigrated { version: STORAGE_VERSION });
+            }
+
+            Ok(migrated)
+        }
+
+        #[ink(message)]
+        pub fn get_storage_version(&self) -> u32 {
+            // Version 1 storage has no version cell
+            self.storage_version.get().unwrap_or(1)
+        }
+
+        /// Internal helper functions
+        pub(crate) fn supports_change(change: &ParameterChange) -> bool {
+            matches!(
+                change,
+                ParameterChange::RegistrationFee(_)
+                    | ParameterChange::PaymentManager(_)
+                    | ParameterChange::TimelockDelay(_)
+                    | ParameterChange::CodeHash(_)
+                    | ParameterChange::Delist(_)
+            )
+        }
+
+        fn upgrade(&mut self, code_hash: Hash) -> TimelockResult<()> {
+            self.env().set_code_hash(&code_hash).map_err(|_| TimelockError::UpgradeFailed)?;
+
+            self.env().emit_event(Upgraded { code_hash });
+
+            Ok(())
+        }
+
+        fn apply_change(&mut self, change: ParameterChange) -> TimelockResult<()> {
+            match change {
+                ParameterChange::RegistrationFee(fee) => self.registration_fee = fee,
+                ParameterChange::TimelockDelay(delay) => self.timelock_delay.set(&delay),
+                ParameterChange::CodeHash(code_hash) => self.upgrade(code_hash)?,
+                ParameterChange::PaymentManager(payment_manager) => {
+                    self.payment_manager.set(&payment_manager);
+                }
+                ParameterChange::Delist(dataset_id) => {
+                    let dataset = self.datasets.get(&dataset_id).ok_or(TimelockError::ChangeNotApplicable)?;
+                    let changed_by = self.env().caller();
+                    self.change_status(dataset_id, dataset, DatasetStatus::Delisted, changed_by)
+                        .map_err(|_| TimelockError::ChangeNotApplicable)?;
+                }
+                _ => {}
+            }
+            Ok(())
+        }
+
+        /// Move a dataset to `status` once the caller is authorized for it
+        fn change_status(
+            &mut self,
+            dataset_id: u64,
+            mut dataset: Dataset,
+            status: DatasetStatus,
+            changed_by: AccountId,
+        ) -> Result<()> {
+            use DatasetStatus::*;
+
+            let old_status = dataset.status;
+            let allowed = match (old_status, status) {
+                (Active, Paused) | (Paused, Active) => true,
+                (Active, Deprecated) | (Paused, Deprecated) => true,
+                (Active | Paused | Deprecated, Delisted) => true,
+                (Delisted, Paused) => true,
+                (Paused | Deprecated | Delisted, Deleted) => true,
+                _ => false,
+            };
+            if !allowed {
+                return Err(Error::InvalidStatusTransition);
+            }
+
+            // Datasets migrated without a schema cannot be queried yet
+            if status == Active && dataset.schema.dimension == 0 {
+                return Err(Error::InvalidSchema);
+            }
+
+            // A deleted dataset disappears from discovery
+            if status == Deleted {
+                if !dataset.category.is_empty() {
+                    Self::index_remove(
+                        &mut self.category_datasets,
+                        &mut self.category_counts,
+                        &dataset.category,
+                        dataset_id,
+                    );
+                }
+                for tag in &dataset.tags {
+                    Self::index_remove(&mut self.tag_datasets, &mut self.tag_counts, tag, dataset_id);
+                }
+                dataset.category = String::new();
+                dataset.tags = Vec::new();
+            }
+
+            dataset.status = status;
+            self.datasets.insert(dataset_id, &dataset);
+
+            self.env().emit_event(DatasetStatusChanged {
+                dataset_id,
+                changed_by,
+                old_status,
+                new_status: status,
+            });
+
+            Ok(())
+        }
+
+        fn ensure_not_paused(&self, operation: Operation) -> Result<()> {
+            if self.is_paused(operation) {
+                return Err(Error::OperationPaused);
+            }
+            Ok(())
+        }
+
+        fn ensure_role(&self, role: Role) -> Result<()> {
+            if !self.has_role(role, self.env().caller()) {
+                return Err(Error::NotAuthorized);
+            }
+            Ok(())
+        }
+
+        fn check_schema(schema: &DatasetSchema) -> Result<()> {
+            if schema.model_id.is_empty() || schema.model_id.len() > MAX_MODEL_ID_LENGTH {
+                return Err(Error::InvalidSchema);
+            }
+            // Proofs and fraud proofs can only score vectors the on-chain math accepts
+            if schema.dimension == 0 || schema.dimension as usize > MAX_DIMENSION {
+                return Err(Error::InvalidSchema);
+            }
+            if schema.vector_count == 0 {
+                return Err(Error::InvalidSchema);
+            }
+            Ok(())
+        }
+
+        /// Lowercase a tag or category and check it is 1 to `MAX_LABEL_LENGTH`
+        /// characters of `a-z`, `0-9` and `-`
+        fn normalize_label(label: &str) -> Result<String> {
+            let label = label.to_ascii_lowercase();
+            let valid_chars = label
+                .bytes()
+                .all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'-');
+            if label.is_empty() || label.len() > MAX_LABEL_LENGTH || !valid_chars {
+                return Err(Error::InvalidLabel);
+            }
+            Ok(label)
+        }
+
+        /// Add a dataset under a tag or category. Each entry has its own
+        /// storage cell, so popular labels cost no more to update than rare ones.
+        fn index_insert(
+            index: &mut Mapping<(String, u64), ()>,
+            counts: &mut Mapping<String, u32>,
+            key: &String,
+            dataset_id: u64,
+        ) {
+            let entry = (key.clone(), dataset_id);
+            if !index.contains(&entry) {
+                index.insert(&entry, &());
+                let count = counts.get(key).unwrap_or(0);
+                counts.insert(key, &(count + 1));
+            }
+        }
+
+        fn index_remove(
+            index: &mut Mapping<(String, u64), ()>,
+            counts: &mut Mapping<String, u32>,
+            key: &String,
+            dataset_id: u64,
+        ) {
+            let entry = (key.clone(), dataset_id);
+            if index.contains(&entry) {
+                index.remove(&entry);
+                let count = counts.get(key).unwrap_or(0).saturating_sub(1);
+                if count == 0 {
+                    counts.remove(key);
+                } else {
+                    counts.insert(key, &count);
+                }
+            }
+        }
+
+        /// Check the tag and category indexes before loading a dataset
+        fn is_indexed(&self, dataset_id: u64, filter: &DatasetFilter) -> bool {
+            let tagged = filter.tag.as_ref().map_or(true, |tag| {
+                self.tag_datasets.contains(&(tag.clone(), dataset_id))
+            });
+            let categorized = filter.category.as_ref().map_or(true, |category| {
+                self.category_datasets.contains(&(category.clone(), dataset_id))
+            });
+            tagged && categorized
+        }
+
+        fn collect_page<I: Iterator<Item = u64>>(
+            &self,
+            candidates: I,
+            limit: u32,
+            filter: &DatasetFilter,
+        ) -> DatasetPage {
+            let limit = (limit as usize).clamp(1, MAX_PAGE_SIZE);
+            let mut datasets = Vec::new();
+            let mut candidates = candidates.peekable();
+            let mut scanned = 0;
+
+            while let Some(&dataset_id) = candidates.peek() {
+                if datasets.len() == limit || scanned == MAX_PAGE_SCAN {
+                    return DatasetPage {
+                        datasets,
+                        next_cursor: Some(dataset_id),
+                    };
+                }
+                candidates.next();
+                scanned += 1;
+
+                if !self.is_indexed(dataset_id, filter) {
+                    continue;
+                }
+                if let Some(dataset) = self.datasets.get(&dataset_id) {
+                    if Self::matches_filter(&dataset, filter) {
+                        datasets.push(dataset);
+                    }
+                }
+            }
+
+            DatasetPage {
+                datasets,
+                next_cursor: None,
+            }
+        }
+
+        fn matches_filter(dataset: &Dataset, filter: &DatasetFilter) -> bool {
+            filter.owner.map_or(true, |owner| dataset.owner == owner)
+                && filter.category.as_ref().map_or(true, |category| dataset.category == *category)
+                && filter.tag.as_ref().map_or(true, |tag| dataset.tags.contains(tag))
+                && filter.metric.map_or(true, |metric| dataset.schema.metric == metric)
+                && filter.max_price.map_or(true, |price| dataset.price_per_query <= price)
+                && (!filter.active_only || dataset.status.accepts_payments())
+        }
+
+        fn ensure_payment_manager(&self) -> Result<()> {
+            let payment_manager = self.payment_manager.get().ok_or(Error::PaymentManagerNotSet)?;
+            if self.env().caller() != payment_manager {
+                return Err(Error::NotAuthorized);
+            }
+            Ok(())
+        }
+
+        fn ensure_dataset_owner(&self, dataset_id: u64) -> Result<()> {
+            let dataset = self.datasets.get(&dataset_id).ok_or(Error::DatasetNotFound)?;
+            if dataset.owner != self.env().caller() {
+                return Err(Error::NotOwner);
+            }
+            Ok(())
+        }
+
+        // Cross-contract call helpers
+        #[cfg(not(test))]
+        fn token_balance(&self, token: AccountId, owner: AccountId) -> u32 {
+            build_call::<DefaultEnvironment>()
+                .call(token)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("PSP34::balance_of")))
+                        .push_arg(owner),
+                )
+                .returns::<u32>()
+                .invoke()
+        }
+
+        /// Payment for a query, live or archived
+        #[cfg(not(test))]
+        fn get_purchase(&self, query_id: u64) -> Result<Option<PaymentReceipt>> {
+            use crate::payment_manager::payment_manager::Payment;
+
+            let payment_manager = self.payment_manager.get().ok_or(Error::PaymentManagerNotSet)?;
+
+            let payment = build_call::<DefaultEnvironment>()
+                .call(payment_manager)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("get_payment")))
+                        .push_arg(query_id),
+                )
+                .returns::<Option<Payment>>()
+                .invoke();
+
+            if let Some(payment) = payment {
+                return Ok(Some(PaymentReceipt {
+                    query_id,
+                    dataset_id: payment.dataset_id,
+                    user: payment.user,
+                    amount: payment.amount,
+                    status: payment.status,
+                    proof_hash: payment.proof_hash,
+                }));
+            }
+
+            Ok(build_call::<DefaultEnvironment>()
+                .call(payment_manager)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("get_payment_receipt")))
+                        .push_arg(query_id),
+                )
+                .returns::<Option<PaymentReceipt>>()
+                .invoke())
+        }
+
+        // The off-chain test environment cannot call other contracts, so tests
+        // see the token contract itself as the only holder, and odd query IDs
+        // as completed purchases of dataset 1 by the caller
+        #[cfg(test)]
+        fn token_balance(&self, token: AccountId, owner: AccountId) -> u32 {
+            if owner == token { 1 } else { 0 }
+        }
+
+        #[cfg(test)]
+        fn get_purchase(&self, query_id: u64) -> Result<Option<PaymentReceipt>> {
+            self.payment_manager.get().ok_or(Error::PaymentManagerNotSet)?;
+
+            Ok(Some(PaymentReceipt {
+                query_id,
+                dataset_id: 1,
+                user: self.env().caller(),
+                amount: 100,
+                status: if query_id % 2 == 1 { PaymentStatus::Completed } else { PaymentStatus::Refunded },
+                proof_hash: None,
+            }))
+        }
+    }
+
+    impl AccessControl for DatasetRegistry {
+        #[ink(message)]
+        fn has_role(&self, role: Role, account: AccountId) -> bool {
+            access_control::has_role(&self.roles, role, account)
+        }
+
+        #[ink(message)]
+        fn grant_role(&mut self, role: Role, account: AccountId) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            access_control::grant_role(&mut self.roles, caller, role, account)?;
+
+            self.env().emit_event(RoleGranted {
+                account,
+                sender: caller,
+                role,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn revoke_role(&mut self, role: Role, account: AccountId) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            access_control::revoke_role(&mut self.roles, self.owner, caller, role, account)?;
+
+            self.env().emit_event(RoleRevoked {
+                account,
+                sender: caller,
+                role,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn renounce_role(&mut self, role: Role) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            self.revoke_role(role, caller)
+        }
+
+        #[ink(message)]
+        fn owner(&self) -> AccountId {
+            self.owner
+        }
+
+        #[ink(message)]
+        fn transfer_ownership(&mut self, new_owner: AccountId) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            access_control::ensure_owner(self.owner, caller)?;
+
+            self.pending_owner.set(&Some(new_owner));
+
+            self.env().emit_event(OwnershipOffered {
+                owner: caller,
+                pending_owner: new_owner,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn accept_ownership(&mut self) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            let previous_owner = self.owner;
+            let pending_owner = self.pending_owner.get_or_default();
+            access_control::accept_ownership(&mut self.roles, previous_owner, pending_owner, caller)?;
+
+            self.owner = caller;
+            self.pending_owner.set(&None);
+
+            self.env().emit_event(OwnershipTransferred {
+                previous_owner,
+                new_owner: caller,
+            });
+
+            Ok(())
+        }
+    }
+
+    impl Pausable for DatasetRegistry {
+        #[ink(message)]
+        fn is_paused(&self, operation: Operation) -> bool {
+            access_control::is_paused(&self.paused, operation)
+        }
+
+        #[ink(message)]
+        fn pause(&mut self, operation: Operation) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            access_control::pause(&self.roles, &mut self.paused, &PAUSABLE_OPERATIONS, caller, operation)?;
+
+            self.env().emit_event(Paused {
+                account: caller,
+                operation,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn unpause(&mut self, operation: Operation) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            access_control::unpause(&self.roles, &mut self.paused, caller, operation)?;
+
+            self.env().emit_event(Unpaused {
+                account: caller,
+                operation,
+            });
+
+            Ok(())
+        }
+    }
+
+    impl Timelock for DatasetRegistry {
+        #[ink(message)]
+        fn queue_change(&mut self, change: ParameterChange) -> TimelockResult<u64> {
+            let change_id = self.next_change_id.get().unwrap_or(1);
+            let supported = Self::supports_change(&change);
+            let queued = QueuedChange {
+                change,
+                queued_by: self.env().caller(),
+                eta: self.env().block_timestamp().saturating_add(self.get_timelock_delay()),
+            };
+            timelock::queue_change(&mut self.queued_changes, &self.roles, change_id, &queued, supported)?;
+            self.next_change_id.set(&(change_id + 1));
+
+            self.env().emit_event(ChangeQueued {
+                change_id,
+                change: queued.change,
+                eta: queued.eta,
+            });
+
+            Ok(change_id)
+        }
+
+        #[ink(message)]
+        fn cancel_change(&mut self, change_id: u64) -> TimelockResult<()> {
+            let caller = self.env().caller();
+            timelock::cancel_change(&mut self.queued_changes, &self.roles, change_id, caller)?;
+
+            self.env().emit_event(ChangeCancelled { change_id });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn execute_change(&mut self, change_id: u64) -> TimelockResult<()> {
+            let now = self.env().block_timestamp();
+            let change = timelock::execute_change(&mut self.queued_changes, change_id, now)?;
+            self.apply_change(change.clone())?;
+
+            self.env().emit_event(ChangeExecuted { change_id, change });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn get_queued_change(&self, change_id: u64) -> Option<QueuedChange> {
+            self.queued_changes.get(&change_id)
+        }
+
+        #[ink(message)]
+        fn get_timelock_delay(&self) -> u64 {
+            self.timelock_delay.get().unwrap_or(DEFAULT_TIMELOCK_DELAY)
+        }
     }
 
     /// Unit tests
     #[cfg(test)]
     mod tests {
         use super::*;
+        use crate::access_control::{AccessControlError, ALL_ROLES};
+
+        fn schema() -> DatasetSchema {
+            DatasetSchema {
+                model_id: "sentence-transformers/all-MiniLM-L6-v2".to_string(),
+                dimension: 384,
+                metric: Metric::L2,
+                vector_count: 10000,
+                quantization: Quantization::Float32,
+                modality: Modality::Text,
+            }
+        }
+
+        /// Queue a change and execute it once the timelock delay has passed
+        fn execute_timelocked(contract: &mut DatasetRegistry, change: ParameterChange) {
+            let change_id = contract.queue_change(change).unwrap();
+            let now = ink::env::block_timestamp::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(now + DEFAULT_TIMELOCK_DELAY);
+            contract.execute_change(change_id).unwrap();
+        }
 
         #[ink::test]
         fn test_register_dataset() {
-            let mut contract = DatasetRegistry::new(1000);
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = DatasetRegistry::new(1000, accounts.django);
             
             let result = contract.register_dataset(
                 "Test Dataset".to_string(),
@@ -289,15 +1842,30 @@ mod dataset_registry {
                 [0u8; 32],
                 [1u8; 32],
                 100,
+                schema(),
             );
             
             assert!(result.is_ok());
             assert_eq!(result.unwrap(), 1);
+            assert_eq!(contract.get_schema(1), Some(schema()));
+
+            let mut too_wide = schema();
+            too_wide.dimension = MAX_DIMENSION as u32 + 1;
+            let result = contract.register_dataset(
+                "Wide Dataset".to_string(),
+                "Description".to_string(),
+                [0u8; 32],
+                [1u8; 32],
+                100,
+                too_wide,
+            );
+            assert_eq!(result, Err(Error::InvalidSchema));
         }
 
         #[ink::test]
         fn test_get_dataset() {
-            let mut contract = DatasetRegistry::new(1000);
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = DatasetRegistry::new(1000, accounts.django);
             
             contract.register_dataset(
                 "Test Dataset".to_string(),
@@ -305,11 +1873,450 @@ mod dataset_registry {
                 [0u8; 32],
                 [1u8; 32],
                 100,
+                schema(),
             ).unwrap();
 
             let dataset = contract.get_dataset(1);
             assert!(dataset.is_some());
             assert_eq!(dataset.unwrap().name, "Test Dataset");
         }
+
+        #[ink::test]
+        fn test_allow_verification_key() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = DatasetRegistry::new(1000, accounts.django);
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            contract.register_dataset(
+                "Test Dataset".to_string(),
+                "Description".to_string(),
+                [0u8; 32],
+                [1u8; 32],
+                100,
+                schema(),
+            ).unwrap();
+
+            assert!(!contract.is_verification_key_allowed(1, [7u8; 32]));
+            contract.allow_verification_key(1, [7u8; 32]).unwrap();
+            assert!(contract.is_verification_key_allowed(1, [7u8; 32]));
+            assert_eq!(
+                contract.allow_verification_key(1, [7u8; 32]),
+                Err(Error::VerificationKeyAlreadyAllowed)
+            );
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(contract.disallow_verification_key(1, [7u8; 32]), Err(Error::NotOwner));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            contract.disallow_verification_key(1, [7u8; 32]).unwrap();
+            assert!(!contract.is_verification_key_allowed(1, [7u8; 32]));
+        }
+
+        #[ink::test]
+        fn test_list_datasets_and_tags() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = DatasetRegistry::new(1000, accounts.django);
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            for price in 1..=5 {
+                contract.register_dataset(
+                    "Test Dataset".to_string(),
+                    "Description".to_string(),
+                    [0u8; 32],
+                    [1u8; 32],
+                    price * 100,
+                    schema(),
+                ).unwrap();
+            }
+
+            contract.set_tags(4, vec!["Legal".to_string(), "en".to_string(), "legal".to_string()]).unwrap();
+            contract.set_tags(2, vec!["legal".to_string()]).unwrap();
+            contract.set_category(2, "law".to_string()).unwrap();
+            assert_eq!(contract.get_dataset(4).unwrap().tags, vec!["legal".to_string(), "en".to_string()]);
+            assert_eq!(contract.set_tags(1, vec!["no spaces".to_string()]), Err(Error::InvalidLabel));
+
+            let page = contract.list_by_tag("LEGAL".to_string(), 0, 10);
+            let ids: Vec<u64> = page.datasets.iter().map(|dataset| dataset.id).collect();
+            assert_eq!(ids, vec![2, 4]);
+            assert_eq!(page.next_cursor, None);
+
+            // Pages follow the cursor
+            let page = contract.list_datasets(0, 2, DatasetFilter::default());
+            assert_eq!(page.datasets.len(), 2);
+            assert_eq!(page.next_cursor, Some(3));
+            let page = contract.list_datasets(3, 10, DatasetFilter::default());
+            assert_eq!(page.datasets.len(), 3);
+            assert_eq!(page.next_cursor, None);
+
+            let filter = DatasetFilter {
+                max_price: Some(300),
+                category: Some("law".to_string()),
+                ..Default::default()
+            };
+            let page = contract.list_datasets(0, 10, filter);
+            assert_eq!(page.datasets.len(), 1);
+            assert_eq!(page.datasets[0].id, 2);
+
+            // Retagging updates the index
+            assert_eq!(contract.get_tag_count("Legal".to_string()), 2);
+            contract.set_tags(2, vec![]).unwrap();
+            assert_eq!(contract.list_by_tag("legal".to_string(), 0, 10).datasets.len(), 1);
+            assert_eq!(contract.get_tag_count("legal".to_string()), 1);
+            assert_eq!(contract.get_category_count("law".to_string()), 1);
+
+            // An empty page size still makes progress
+            let page = contract.list_datasets(0, 0, DatasetFilter::default());
+            assert_eq!(page.datasets.len(), 1);
+            assert_eq!(page.next_cursor, Some(2));
+        }
+
+        #[ink::test]
+        fn test_reviews_require_completed_payment() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = DatasetRegistry::new(1000, accounts.django);
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            contract.register_dataset(
+                "Test Dataset".to_string(),
+                "Description".to_string(),
+                [0u8; 32],
+                [1u8; 32],
+                100,
+                schema(),
+            ).unwrap();
+
+            // The owner cannot review their own dataset
+            assert_eq!(contract.submit_review(1, 5, [2u8; 32]), Err(Error::NotAuthorized));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(contract.submit_review(1, 6, [2u8; 32]), Err(Error::InvalidRating));
+            assert_eq!(contract.submit_review(2, 5, [2u8; 32]), Err(Error::PaymentNotCompleted));
+            contract.submit_review(1, 5, [2u8; 32]).unwrap();
+            assert_eq!(contract.submit_review(1, 4, [2u8; 32]), Err(Error::AlreadyReviewed));
+            contract.submit_review(3, 4, [3u8; 32]).unwrap();
+
+            assert_eq!(contract.get_dataset_reviews(1), vec![1, 3]);
+            assert_eq!(contract.get_rating_summary(1).review_count, 2);
+            assert_eq!(contract.get_average_rating(1), Some(450));
+
+            assert_eq!(contract.respond_to_review(1, [9u8; 32]), Err(Error::NotOwner));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            contract.respond_to_review(1, [9u8; 32]).unwrap();
+            assert_eq!(contract.get_review(1).unwrap().response_hash, Some([9u8; 32]));
+            assert_eq!(contract.respond_to_review(1, [9u8; 32]), Err(Error::ResponseAlreadyPosted));
+        }
+
+        #[ink::test]
+        fn test_query_counters_require_payment_manager() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = DatasetRegistry::new(1000, accounts.django);
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            contract.register_dataset(
+                "Test Dataset".to_string(),
+                "Description".to_string(),
+                [0u8; 32],
+                [1u8; 32],
+                100,
+                schema(),
+            ).unwrap();
+
+            // The payment manager given to the constructor counts queries from the start
+            assert_eq!(contract.increment_query_count(1), Err(Error::NotAuthorized));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            contract.increment_query_count(1).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(
+                contract.queue_change(ParameterChange::Oracle(accounts.charlie)),
+                Err(TimelockError::UnsupportedChange)
+            );
+            execute_timelocked(&mut contract, ParameterChange::PaymentManager(accounts.charlie));
+            assert_eq!(contract.get_payment_manager(), Some(accounts.charlie));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            assert_eq!(contract.increment_query_count(1), Err(Error::NotAuthorized));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            contract.increment_query_count(1).unwrap();
+            contract.increment_query_count(1).unwrap();
+            contract.record_query_settlement(1, QuerySettlement::Completed).unwrap();
+            contract.record_query_settlement(1, QuerySettlement::Released(95)).unwrap();
+            contract.record_query_settlement(1, QuerySettlement::Refunded).unwrap();
+
+            assert_eq!(contract.get_dataset(1).unwrap().total_queries, 3);
+            let stats = contract.get_query_stats(1);
+            assert_eq!(stats.completed_queries, 1);
+            assert_eq!(stats.refunded_queries, 1);
+            assert_eq!(stats.total_revenue, 95);
+        }
+
+        #[ink::test]
+        fn test_roles_and_ownership_transfer() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = DatasetRegistry::new(1000, accounts.django);
+            assert!(contract.has_role(Role::FeeManager, accounts.alice));
+
+            // Fee changes follow the FeeManager role, not ownership
+            contract.grant_role(Role::FeeManager, accounts.bob).unwrap();
+            assert_eq!(
+                contract.grant_role(Role::FeeManager, accounts.bob),
+                Err(AccessControlError::RoleAlreadyGranted)
+            );
+            contract.renounce_role(Role::FeeManager).unwrap();
+            assert_eq!(
+                contract.queue_change(ParameterChange::RegistrationFee(5)),
+                Err(TimelockError::MissingRole)
+            );
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(contract.queue_change(ParameterChange::RegistrationFee(5)), Ok(1));
+            assert_eq!(
+                contract.grant_role(Role::Arbiter, accounts.bob),
+                Err(AccessControlError::MissingRole)
+            );
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            contract.revoke_role(Role::FeeManager, accounts.bob).unwrap();
+            assert!(!contract.has_role(Role::FeeManager, accounts.bob));
+            assert_eq!(
+                contract.renounce_role(Role::Admin),
+                Err(AccessControlError::OwnerMustBeAdmin)
+            );
+
+            // Ownership moves in two steps and takes the owner's roles along
+            contract.transfer_ownership(accounts.charlie).unwrap();
+            assert_eq!(contract.owner(), accounts.alice);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(contract.accept_ownership(), Err(AccessControlError::NotPendingOwner));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            contract.accept_ownership().unwrap();
+            assert_eq!(contract.owner(), accounts.charlie);
+            for role in ALL_ROLES {
+                assert!(!contract.has_role(role, accounts.alice));
+                // Alice gave up FeeManager before handing over
+                assert_eq!(contract.has_role(role, accounts.charlie), role != Role::FeeManager);
+            }
+            contract.pause(Operation::Registration).unwrap();
+        }
+
+        #[ink::test]
+        fn test_migrate_v1_datasets() {
+            use ink::storage::traits::StorageKey;
+
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = DatasetRegistry::new(1000, accounts.django);
+            assert_eq!(contract.migrate(10), Err(Error::AlreadyMigrated));
+
+            // Storage as left behind by version 1 code: the root holds only
+            // next_dataset_id, owner and registration_fee, and none of the
+            // cells `new` wrote above
+            let root_key = <DatasetRegistry as StorageKey>::KEY;
+            ink::env::set_contract_storage(&root_key, &(3u64, accounts.alice, 1000u128));
+            ink::env::clear_contract_storage(&STORAGE_VERSION_KEY);
+            ink::env::clear_contract_storage(&PAYMENT_MANAGER_KEY);
+            for id in 1..3u64 {
+                let dataset = (
+                    id,
+                    accounts.bob,
+                    "Legacy Dataset".to_string(),
+                    "Description".to_string(),
+                    [0u8; 32],
+                    [1u8; 32],
+                    100u128,
+                    true, // is_active
+                    0u64,
+                    7u64, // total_queries
+                    Vec::<AccountId>::new(),
+                );
+                ink::env::set_contract_storage(&(DATASETS_V1_KEY, id), &dataset);
+            }
+
+            let mut contract: DatasetRegistry =
+                ink::env::get_contract_storage(&root_key).unwrap().unwrap();
+            assert_eq!(contract.get_storage_version(), 1);
+            assert_eq!(contract.owner(), accounts.alice);
+            assert_eq!(contract.get_registration_fee(), 1000);
+            assert_eq!(contract.get_payment_manager(), None);
+            assert_eq!(contract.get_timelock_delay(), DEFAULT_TIMELOCK_DELAY);
+            assert!(!contract.has_role(Role::Admin, accounts.alice));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(contract.migrate(10), Err(Error::NotAuthorized));
+
+            // Migration can be spread over several calls
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(contract.migrate(2), Ok(1));
+            assert_eq!(contract.get_storage_version(), 1);
+            assert_eq!(contract.migrate(2), Ok(1));
+            assert_eq!(contract.get_storage_version(), 2);
+            assert!(contract.legacy_datasets.get(&2).is_none());
+            assert!(contract.has_role(Role::Admin, accounts.alice));
+            assert_eq!(contract.migrate(2), Err(Error::AlreadyMigrated));
+
+            let dataset = contract.get_dataset(1).unwrap();
+            assert_eq!(dataset.status, DatasetStatus::Paused);
+            assert_eq!(dataset.total_queries, 7);
+
+            // Migrated datasets sell again once their owner sets a schema
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(contract.set_dataset_status(1, DatasetStatus::Active), Err(Error::InvalidSchema));
+            contract.set_schema(1, schema()).unwrap();
+            assert_eq!(contract.set_schema(1, schema()), Err(Error::SchemaAlreadySet));
+            contract.set_dataset_status(1, DatasetStatus::Active).unwrap();
+            assert_eq!(contract.get_query_price(1), Ok(100));
+        }
+
+        #[ink::test]
+        fn test_dataset_lifecycle() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = DatasetRegistry::new(1000, accounts.django);
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            for _ in 0..2 {
+                contract.register_dataset(
+                    "Test Dataset".to_string(),
+                    "Description".to_string(),
+                    [0u8; 32],
+                    [1u8; 32],
+                    100,
+                    schema(),
+                ).unwrap();
+            }
+            contract.set_tags(1, vec!["legal".to_string()]).unwrap();
+
+            // Paused datasets stop selling until resumed
+            contract.set_dataset_status(1, DatasetStatus::Paused).unwrap();
+            assert_eq!(contract.get_query_price(1), Err(Error::DatasetInactive));
+            contract.set_dataset_status(1, DatasetStatus::Active).unwrap();
+            assert_eq!(contract.get_query_price(1), Ok(100));
+
+            // Deprecation cannot be undone
+            contract.set_dataset_status(1, DatasetStatus::Deprecated).unwrap();
+            assert_eq!(
+                contract.set_dataset_status(1, DatasetStatus::Active),
+                Err(Error::InvalidStatusTransition)
+            );
+            contract.set_dataset_status(1, DatasetStatus::Deleted).unwrap();
+            assert!(contract.list_by_tag("legal".to_string(), 0, 10).datasets.is_empty());
+            assert_eq!(contract.set_tags(1, vec!["legal".to_string()]), Err(Error::DatasetInactive));
+            assert_eq!(contract.set_category(1, "law".to_string()), Err(Error::DatasetInactive));
+            assert!(contract.list_by_tag("legal".to_string(), 0, 10).datasets.is_empty());
+            assert_eq!(
+                contract.set_dataset_status(1, DatasetStatus::Paused),
+                Err(Error::InvalidStatusTransition)
+            );
+
+            // Only an Arbiter delists and reinstates
+            assert_eq!(contract.set_dataset_status(2, DatasetStatus::Delisted), Err(Error::NotAuthorized));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            contract.set_dataset_status(2, DatasetStatus::Delisted).unwrap();
+            assert_eq!(contract.get_query_price(2), Err(Error::DatasetInactive));
+            contract.set_dataset_status(2, DatasetStatus::Paused).unwrap();
+            assert_eq!(contract.get_dataset(2).unwrap().status, DatasetStatus::Paused);
+        }
+
+        #[ink::test]
+        fn test_timelocked_delisting() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = DatasetRegistry::new(1000, accounts.django);
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let dataset_id = contract.register_dataset(
+                "Test Dataset".to_string(),
+                "Description".to_string(),
+                [0u8; 32],
+                [1u8; 32],
+                100,
+                schema(),
+            ).unwrap();
+            contract.set_dataset_status(dataset_id, DatasetStatus::Active).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(
+                contract.queue_change(ParameterChange::Delist(dataset_id)),
+                Err(TimelockError::MissingRole)
+            );
+
+            // The dataset keeps selling until the delay has passed
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            let change_id = contract.queue_change(ParameterChange::Delist(dataset_id)).unwrap();
+            assert_eq!(contract.execute_change(change_id), Err(TimelockError::ChangeNotReady));
+            assert_eq!(contract.get_query_price(dataset_id), Ok(100));
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(DEFAULT_TIMELOCK_DELAY);
+            contract.execute_change(change_id).unwrap();
+            assert_eq!(contract.get_dataset(dataset_id).unwrap().status, DatasetStatus::Delisted);
+            assert_eq!(contract.get_query_price(dataset_id), Err(Error::DatasetInactive));
+
+            let missing = contract.queue_change(ParameterChange::Delist(9)).unwrap();
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(2 * DEFAULT_TIMELOCK_DELAY);
+            assert_eq!(contract.execute_change(missing), Err(TimelockError::ChangeNotApplicable));
+        }
+
+        #[ink::test]
+        fn test_fiat_price() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = DatasetRegistry::new(1000, accounts.django);
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let dataset_id = contract.register_dataset(
+                "Test Dataset".to_string(),
+                "Description".to_string(),
+                [0u8; 32],
+                [1u8; 32],
+                100,
+                schema(),
+            ).unwrap();
+
+            let usd = |amount| Some(FiatPrice { currency: "USD".to_string(), amount });
+            assert_eq!(contract.set_fiat_price(dataset_id, usd(0)), Err(Error::InvalidParameters));
+            let lowercase = Some(FiatPrice { currency: "usd".to_string(), amount: 1 });
+            assert_eq!(contract.set_fiat_price(dataset_id, lowercase), Err(Error::InvalidParameters));
+
+            contract.set_fiat_price(dataset_id, usd(2_500_000)).unwrap();
+            assert_eq!(contract.get_fiat_price(dataset_id), usd(2_500_000));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(contract.set_fiat_price(dataset_id, None), Err(Error::NotOwner));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            contract.set_fiat_price(dataset_id, None).unwrap();
+            assert_eq!(contract.get_fiat_price(dataset_id), None);
+        }
+
+        #[ink::test]
+        fn test_dataset_visibility() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = DatasetRegistry::new(1000, accounts.django);
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            contract.register_dataset(
+                "Test Dataset".to_string(),
+                "Description".to_string(),
+                [0u8; 32],
+                [1u8; 32],
+                100,
+                schema(),
+            ).unwrap();
+            assert!(contract.is_authorized_buyer(1, accounts.bob));
+
+            contract.set_visibility(1, Visibility::Allowlist).unwrap();
+            assert!(!contract.is_authorized_buyer(1, accounts.bob));
+            assert!(contract.is_authorized_buyer(1, accounts.alice));
+
+            contract.add_to_allowlist(1, accounts.bob).unwrap();
+            assert!(contract.is_authorized_buyer(1, accounts.bob));
+            assert_eq!(contract.add_to_allowlist(1, accounts.bob), Err(Error::AlreadyAllowlisted));
+            contract.remove_from_allowlist(1, accounts.bob).unwrap();
+            assert!(!contract.is_authorized_buyer(1, accounts.bob));
+
+            contract.set_visibility(1, Visibility::TokenGated(accounts.django)).unwrap();
+            assert!(!contract.is_authorized_buyer(1, accounts.bob));
+            assert!(contract.is_authorized_buyer(1, accounts.django));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(contract.add_to_allowlist(1, accounts.bob), Err(Error::NotOwner));
+            assert_eq!(contract.set_visibility(1, Visibility::Public), Err(Error::NotOwner));
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,750 @@
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+#[ink::contract]
+pub mod governance_voting {
+    use ink::storage::Mapping;
+    use ink::storage::traits::{SpreadLayout, PackedLayout, StorageLayout};
+    use ink::prelude::string::String;
+    use ink::env::call::{build_call, ExecutionInput, Selector};
+    use ink::env::DefaultEnvironment;
+    use crate::timelock::{ParameterChange, MAX_FEE_BPS};
+    use crate::dataset_registry::dataset_registry::DatasetRegistry;
+    use crate::payment_manager::payment_manager::PaymentManager;
+    use crate::zk_verifier::zk_verifier::{PayloadLimits, ZKVerifier};
+
+    /// Marketplace contract a proposal acts on
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub enum Target {
+        DatasetRegistry,
+        PaymentManager,
+        ZKVerifier,
+    }
+
+    /// What a proposal does once it passes
+    #[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub enum ProposalAction {
+        ChangeParameter(Target, ParameterChange), // Queued on the target's timelock
+        DelistDataset(u64),                       // Dataset ID, delisted by the registry's timelock
+        AllowCircuitType(String, PayloadLimits),  // Circuit type and limits, allowlisted on the ZK verifier
+    }
+
+    impl ProposalAction {
+        /// Change queued on the target's timelock when the proposal passes
+        fn queued_change(&self) -> (Target, ParameterChange) {
+            match self {
+                ProposalAction::ChangeParameter(target, change) => (*target, change.clone()),
+                ProposalAction::AllowCircuitType(circuit_type, limits) => (
+                    Target::ZKVerifier,
+                    ParameterChange::AllowCircuitType(circuit_type.clone(), limits.clone()),
+                ),
+                ProposalAction::DelistDataset(dataset_id) => {
+                    (Target::DatasetRegistry, ParameterChange::Delist(*dataset_id))
+                }
+            }
+        }
+    }
+
+    /// Proposal status
+    #[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub enum ProposalStatus {
+        Active,
+        Defeated,
+        Executed,
+        Cancelled,
+    }
+
+    /// Choice of one voter
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub enum VoteType {
+        For,
+        Against,
+        Abstain, // Counts towards quorum only
+    }
+
+    /// Stake held from `from` until the next checkpoint
+    #[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct Checkpoint {
+        pub from: Timestamp,
+        pub stake: Balance,
+    }
+
+    /// Proposal information
+    #[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct Proposal {
+        pub id: u64,
+        pub proposer: AccountId,
+        pub action: ProposalAction,
+        pub description_hash: [u8; 32], // Hash of the off-chain rationale
+        pub created_at: Timestamp,
+        pub voting_ends_at: Timestamp,
+        pub total_stake: Balance, // Stake held before creation, quorum is a share of it
+        pub votes_for: Balance,
+        pub votes_against: Balance,
+        pub votes_abstain: Balance,
+        pub status: ProposalStatus,
+        pub change_id: Option<u64>, // Timelock change queued on execution
+    }
+
+    /// Governance voting contract. Voting power is native tokens staked in
+    /// this contract before a proposal was created, so stake moved in after
+    /// a proposal shows up cannot sway it. To execute proposals it needs the
+    /// roles of the messages it calls: FeeManager and Admin on each target for
+    /// parameter changes, and Arbiter on the registry to queue delistings.
+    ///
+    /// Governance does not hold those roles alone. Other holders of FeeManager
+    /// or Admin on a target can still queue changes there directly, and can
+    /// cancel a change governance queued while it waits for its ETA. Both show
+    /// up as timelock events on the target before anything applies. Handing a
+    /// target over to governance entirely means revoking every other holder's
+    /// roles and transferring its ownership to an account governance controls.
+    #[ink(storage)]
+    pub struct GovernanceVoting {
+        /// Dataset registry contract address
+        dataset_registry: AccountId,
+        /// Payment manager contract address
+        payment_manager: AccountId,
+        /// ZK verifier contract address
+        zk_verifier: AccountId,
+        /// Mapping from proposal ID to proposal info
+        proposals: Mapping<u64, Proposal>,
+        /// Mapping from proposal ID and voter to their vote
+        votes: Mapping<(u64, AccountId), VoteType>,
+        /// Mapping from account to its stake
+        stakes: Mapping<AccountId, Balance>,
+        /// Stake history of each account, by account and checkpoint index
+        stake_checkpoints: Mapping<(AccountId, u32), Checkpoint>,
+        /// Number of stake checkpoints of each account
+        stake_checkpoint_counts: Mapping<AccountId, u32>,
+        /// History of the sum of all stakes, by checkpoint index
+        total_checkpoints: Mapping<u32, Checkpoint>,
+        /// Number of total stake checkpoints
+        total_checkpoint_count: u32,
+        /// Time each account's stake is locked until, the end of its latest vote
+        locked_until: Mapping<AccountId, Timestamp>,
+        /// Sum of all stakes
+        total_stake: Balance,
+        /// Next proposal ID
+        next_proposal_id: u64,
+        /// Voting period in milliseconds
+        voting_period: u64,
+        /// Share of the stake that must vote for a result to count (basis points)
+        quorum_bps: u16,
+        /// Stake needed to create a proposal
+        proposal_threshold: Balance,
+    }
+
+    /// Events
+    #[ink(event)]
+    pub struct Staked {
+        #[ink(topic)]
+        account: AccountId,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct Unstaked {
+        #[ink(topic)]
+        account: AccountId,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct ProposalCreated {
+        #[ink(topic)]
+        proposal_id: u64,
+        #[ink(topic)]
+        proposer: AccountId,
+        action: ProposalAction,
+        voting_ends_at: Timestamp,
+    }
+
+    #[ink(event)]
+    pub struct VoteCast {
+        #[ink(topic)]
+        proposal_id: u64,
+        #[ink(topic)]
+        voter: AccountId,
+        vote: VoteType,
+        weight: Balance,
+    }
+
+    #[ink(event)]
+    pub struct ProposalExecuted {
+        #[ink(topic)]
+        proposal_id: u64,
+        change_id: u64,
+    }
+
+    #[ink(event)]
+    pub struct ProposalDefeated {
+        #[ink(topic)]
+        proposal_id: u64,
+    }
+
+    #[ink(event)]
+    pub struct ProposalCancelled {
+        #[ink(topic)]
+        proposal_id: u64,
+    }
+
+    /// Errors
+    #[derive(Debug, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub enum Error {
+        InvalidAmount,
+        InsufficientStake,
+        StakeLocked,
+        TransferFailed,
+        InvalidProposal,
+        ProposalNotFound,
+        ProposalNotActive,
+        NotProposer,
+        VotingClosed,
+        VotingOpen,
+        AlreadyVoted,
+        NoVotingPower,
+        ExecutionFailed,
+    }
+
+    pub type Result<T> = core::result::Result<T, Error>;
+
+    impl GovernanceVoting {
+        /// Constructor
+        #[ink(constructor)]
+        pub fn new(
+            dataset_registry: AccountId,
+            payment_manager: AccountId,
+            zk_verifier: AccountId,
+            voting_period: u64,
+            quorum_bps: u16,
+            proposal_threshold: Balance,
+        ) -> Self {
+            assert!(quorum_bps <= MAX_FEE_BPS, "quorum above 100%");
+            Self {
+                dataset_registry,
+                payment_manager,
+                zk_verifier,
+                proposals: Mapping::default(),
+                votes: Mapping::default(),
+                stakes: Mapping::default(),
+                stake_checkpoints: Mapping::default(),
+                stake_checkpoint_counts: Mapping::default(),
+                total_checkpoints: Mapping::default(),
+                total_checkpoint_count: 0,
+                locked_until: Mapping::default(),
+                total_stake: 0,
+                next_proposal_id: 1,
+                voting_period,
+                quorum_bps,
+                proposal_threshold,
+            }
+        }
+
+        /// Add the transferred value to the caller's voting power
+        #[ink(message, payable)]
+        pub fn stake(&mut self) -> Result<()> {
+            let caller = self.env().caller();
+            let amount = self.env().transferred_value();
+
+            if amount == 0 {
+                return Err(Error::InvalidAmount);
+            }
+
+            let stake = self.stakes.get(&caller).unwrap_or(0);
+            self.stakes.insert(caller, &(stake + amount));
+            self.total_stake += amount;
+            self.checkpoint(caller, stake + amount);
+
+            self.env().emit_event(Staked {
+                account: caller,
+                amount,
+            });
+
+            Ok(())
+        }
+
+        /// Withdraw stake. Stake that voted stays locked until the vote ends,
+        /// and a vote only counts stake still held when it is cast, so voters
+        /// stay exposed to the outcome they voted for.
+        #[ink(message)]
+        pub fn unstake(&mut self, amount: Balance) -> Result<()> {
+            let caller = self.env().caller();
+            let now = self.env().block_timestamp();
+            let stake = self.stakes.get(&caller).unwrap_or(0);
+
+            if amount == 0 || amount > stake {
+                return Err(Error::InvalidAmount);
+            }
+            if now < self.locked_until.get(&caller).unwrap_or(0) {
+                return Err(Error::StakeLocked);
+            }
+
+            self.stakes.insert(caller, &(stake - amount));
+            self.total_stake -= amount;
+            self.checkpoint(caller, stake - amount);
+
+            self.env().transfer(caller, amount)
+                .map_err(|_| Error::TransferFailed)?;
+
+            self.env().emit_event(Unstaked {
+                account: caller,
+                amount,
+            });
+
+            Ok(())
+        }
+
+        /// Create a proposal (stakers above the proposal threshold only)
+        #[ink(message)]
+        pub fn propose(&mut self, action: ProposalAction, description_hash: [u8; 32]) -> Result<u64> {
+            let caller = self.env().caller();
+            let now = self.env().block_timestamp();
+
+            if self.stakes.get(&caller).unwrap_or(0) < self.proposal_threshold {
+                return Err(Error::InsufficientStake);
+            }
+
+            // Reject changes the target would refuse to queue
+            let (target, change) = action.queued_change();
+            if !Self::supports_change(target, &change) {
+                return Err(Error::InvalidProposal);
+            }
+            change.validate().map_err(|_| Error::InvalidProposal)?;
+
+            let proposal_id = self.next_proposal_id;
+            let voting_ends_at = now + self.voting_period;
+
+            let proposal = Proposal {
+                id: proposal_id,
+                proposer: caller,
+                action: action.clone(),
+                description_hash,
+                created_at: now,
+                voting_ends_at,
+                total_stake: Self::stake_before(self.total_checkpoint_count, now, |index| {
+                    self.total_checkpoints.get(&index)
+                }),
+                votes_for: 0,
+                votes_against: 0,
+                votes_abstain: 0,
+                status: ProposalStatus::Active,
+                change_id: None,
+            };
+
+            self.proposals.insert(proposal_id, &proposal);
+            self.next_proposal_id += 1;
+
+            self.env().emit_event(ProposalCreated {
+                proposal_id,
+                proposer: caller,
+                action,
+                voting_ends_at,
+            });
+
+            Ok(proposal_id)
+        }
+
+        /// Vote with the stake the caller held before the proposal was created
+        /// and still holds. Stake withdrawn since then does not vote, so every
+        /// vote is backed by stake that stays locked until voting ends.
+        #[ink(message)]
+        pub fn vote(&mut self, proposal_id: u64, vote: VoteType) -> Result<()> {
+            let caller = self.env().caller();
+            let now = self.env().block_timestamp();
+            let mut proposal = self.proposals.get(&proposal_id).ok_or(Error::ProposalNotFound)?;
+
+            if proposal.status != ProposalStatus::Active {
+                return Err(Error::ProposalNotActive);
+            }
+            if now >= proposal.voting_ends_at {
+                return Err(Error::VotingClosed);
+            }
+            if self.votes.contains(&(proposal_id, caller)) {
+                return Err(Error::AlreadyVoted);
+            }
+
+            let stake = self.stakes.get(&caller).unwrap_or(0);
+            let weight = self.get_stake_at(caller, proposal.created_at).min(stake);
+            if weight == 0 {
+                return Err(Error::NoVotingPower);
+            }
+
+            match vote {
+                VoteType::For => proposal.votes_for += weight,
+                VoteType::Against => proposal.votes_against += weight,
+                VoteType::Abstain => proposal.votes_abstain += weight,
+            }
+            self.proposals.insert(proposal_id, &proposal);
+            self.votes.insert((proposal_id, caller), &vote);
+
+            let locked_until = self.locked_until.get(&caller).unwrap_or(0);
+            self.locked_until.insert(caller, &locked_until.max(proposal.voting_ends_at));
+
+            self.env().emit_event(VoteCast {
+                proposal_id,
+                voter: caller,
+                vote,
+                weight,
+            });
+
+            Ok(())
+        }
+
+        /// Settle a proposal once voting has ended. A proposal passes when
+        /// turnout reaches the quorum and more stake voted for than against.
+        /// Its change is queued on the target's timelock and applied there
+        /// after the delay, so dataset owners see a delisting coming like any
+        /// other change. Anyone can call this, and a passed proposal whose
+        /// call fails stays active so it can be retried.
+        #[ink(message)]
+        pub fn execute(&mut self, proposal_id: u64) -> Result<()> {
+            let now = self.env().block_timestamp();
+            let mut proposal = self.proposals.get(&proposal_id).ok_or(Error::ProposalNotFound)?;
+
+            if proposal.status != ProposalStatus::Active {
+                return Err(Error::ProposalNotActive);
+            }
+            if now < proposal.voting_ends_at {
+                return Err(Error::VotingOpen);
+            }
+
+            if !self.has_passed(&proposal) {
+                proposal.status = ProposalStatus::Defeated;
+                self.proposals.insert(proposal_id, &proposal);
+
+                self.env().emit_event(ProposalDefeated { proposal_id });
+
+                return Ok(());
+            }
+
+            let (target, change) = proposal.action.queued_change();
+            let change_id = self.queue_change(self.target_address(target), change)?;
+
+            proposal.status = ProposalStatus::Executed;
+            proposal.change_id = Some(change_id);
+            self.proposals.insert(proposal_id, &proposal);
+
+            self.env().emit_event(ProposalExecuted {
+                proposal_id,
+                change_id,
+            });
+
+            Ok(())
+        }
+
+        /// Withdraw a proposal while voting is open (proposer only)
+        #[ink(message)]
+        pub fn cancel(&mut self, proposal_id: u64) -> Result<()> {
+            let caller = self.env().caller();
+            let now = self.env().block_timestamp();
+            let mut proposal = self.proposals.get(&proposal_id).ok_or(Error::ProposalNotFound)?;
+
+            if proposal.proposer != caller {
+                return Err(Error::NotProposer);
+            }
+            if proposal.status != ProposalStatus::Active {
+                return Err(Error::ProposalNotActive);
+            }
+            if now >= proposal.voting_ends_at {
+                return Err(Error::VotingClosed);
+            }
+
+            proposal.status = ProposalStatus::Cancelled;
+            self.proposals.insert(proposal_id, &proposal);
+
+            self.env().emit_event(ProposalCancelled { proposal_id });
+
+            Ok(())
+        }
+
+        /// Get proposal information
+        #[ink(message)]
+        pub fn get_proposal(&self, proposal_id: u64) -> Option<Proposal> {
+            self.proposals.get(&proposal_id)
+        }
+
+        /// Get the vote of an account on a proposal
+        #[ink(message)]
+        pub fn get_vote(&self, proposal_id: u64, voter: AccountId) -> Option<VoteType> {
+            self.votes.get(&(proposal_id, voter))
+        }
+
+        /// Get voting power of an account
+        #[ink(message)]
+        pub fn get_stake(&self, account: AccountId) -> Balance {
+            self.stakes.get(&account).unwrap_or(0)
+        }
+
+        /// Get the stake an account held before `time`, its voting power on
+        /// proposals created at `time`
+        #[ink(message)]
+        pub fn get_stake_at(&self, account: AccountId, time: Timestamp) -> Balance {
+            let count = self.stake_checkpoint_counts.get(&account).unwrap_or(0);
+            Self::stake_before(count, time, |index| self.stake_checkpoints.get(&(account, index)))
+        }
+
+        #[ink(message)]
+        pub fn get_total_stake(&self) -> Balance {
+            self.total_stake
+        }
+
+        #[ink(message)]
+        pub fn get_locked_until(&self, account: AccountId) -> Timestamp {
+            self.locked_until.get(&account).unwrap_or(0)
+        }
+
+        /// Internal helper functions
+        fn has_passed(&self, proposal: &Proposal) -> bool {
+            let turnout = proposal.votes_for + proposal.votes_against + proposal.votes_abstain;
+            let quorum = proposal.total_stake * self.quorum_bps as u128 / 10000;
+            turnout >= quorum && proposal.votes_for > proposal.votes_against
+        }
+
+        /// Record the account's new stake and the new total, both held from now on
+        fn checkpoint(&mut self, account: AccountId, stake: Balance) {
+            let now = self.env().block_timestamp();
+
+            let count = self.stake_checkpoint_counts.get(&account).unwrap_or(0);
+            let index = Self::checkpoint_index(count, now, |index| {
+                self.stake_checkpoints.get(&(account, index))
+            });
+            self.stake_checkpoints.insert((account, index), &Checkpoint { from: now, stake });
+            self.stake_checkpoint_counts.insert(account, &(index + 1));
+
+            let index = Self::checkpoint_index(self.total_checkpoint_count, now, |index| {
+                self.total_checkpoints.get(&index)
+            });
+            self.total_checkpoints.insert(index, &Checkpoint { from: now, stake: self.total_stake });
+            self.total_checkpoint_count = index + 1;
+        }
+
+        /// Index to write a checkpoint at; changes within one block overwrite
+        /// that block's checkpoint
+        fn checkpoint_index(
+            count: u32,
+            now: Timestamp,
+            checkpoint: impl Fn(u32) -> Option<Checkpoint>,
+        ) -> u32 {
+            match count.checked_sub(1).and_then(&checkpoint) {
+                Some(last) if last.from == now => count - 1,
+                _ => count,
+            }
+        }
+
+        /// Stake of the last checkpoint written strictly before `time`, found
+        /// by binary search. Stake added in the block a proposal is created
+        /// does not count towards it.
+        fn stake_before(
+            count: u32,
+            time: Timestamp,
+            checkpoint: impl Fn(u32) -> Option<Checkpoint>,
+        ) -> Balance {
+            let (mut low, mut high) = (0, count);
+            while low < high {
+                let mid = low + (high - low) / 2;
+                if checkpoint(mid).map_or(false, |checkpoint| checkpoint.from < time) {
+                    low = mid + 1;
+                } else {
+                    high = mid;
+                }
+            }
+            low.checked_sub(1).and_then(checkpoint).map_or(0, |checkpoint| checkpoint.stake)
+        }
+
+        /// Whether the target's timelock accepts the change
+        fn supports_change(target: Target, change: &ParameterChange) -> bool {
+            match target {
+                Target::DatasetRegistry => DatasetRegistry::supports_change(change),
+                Target::PaymentManager => PaymentManager::supports_change(change),
+                Target::ZKVerifier => ZKVerifier::supports_change(change),
+            }
+        }
+
+        fn target_address(&self, target: Target) -> AccountId {
+            match target {
+                Target::DatasetRegistry => self.dataset_registry,
+                Target::PaymentManager => self.payment_manager,
+                Target::ZKVerifier => self.zk_verifier,
+            }
+        }
+
+        /// Trait messages are selected by `Trait::message`
+        #[cfg(not(test))]
+        fn queue_change(&self, target: AccountId, change: ParameterChange) -> Result<u64> {
+            use crate::timelock::TimelockResult;
+
+            build_call::<DefaultEnvironment>()
+                .call(target)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("Timelock::queue_change")))
+                        .push_arg(change),
+                )
+                .returns::<TimelockResult<u64>>()
+                .invoke()
+                .map_err(|_| Error::ExecutionFailed)
+        }
+
+        // The off-chain test environment cannot call other contracts, so tests
+        // see every change queued as change 1
+        #[cfg(test)]
+        fn queue_change(&self, _target: AccountId, _change: ParameterChange) -> Result<u64> {
+            Ok(1)
+        }
+    }
+
+    /// Unit tests
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn contract() -> GovernanceVoting {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            GovernanceVoting::new(
+                accounts.django,
+                accounts.eve,
+                accounts.frank,
+                1000, // Voting period
+                4000, // 40% quorum
+                200,  // Proposal threshold
+            )
+        }
+
+        fn stake(contract: &mut GovernanceVoting, account: AccountId, amount: Balance) {
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(account);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(amount);
+            contract.stake().unwrap();
+        }
+
+        #[ink::test]
+        fn test_proposal_passes_and_queues_change() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = contract();
+            stake(&mut contract, accounts.alice, 1000);
+            stake(&mut contract, accounts.bob, 500);
+            stake(&mut contract, accounts.charlie, 100);
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1);
+
+            let action = ProposalAction::ChangeParameter(Target::PaymentManager, ParameterChange::PlatformFee(500));
+            assert_eq!(contract.propose(action.clone(), [0u8; 32]), Err(Error::InsufficientStake));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            let invalid = ProposalAction::ChangeParameter(Target::PaymentManager, ParameterChange::PlatformFee(20_000));
+            assert_eq!(contract.propose(invalid, [0u8; 32]), Err(Error::InvalidProposal));
+            let unsupported = ProposalAction::ChangeParameter(Target::DatasetRegistry, ParameterChange::PlatformFee(500));
+            assert_eq!(contract.propose(unsupported, [0u8; 32]), Err(Error::InvalidProposal));
+            let proposal_id = contract.propose(action, [0u8; 32]).unwrap();
+            contract.vote(proposal_id, VoteType::For).unwrap();
+            assert_eq!(contract.vote(proposal_id, VoteType::Against), Err(Error::AlreadyVoted));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            contract.vote(proposal_id, VoteType::Against).unwrap();
+            assert_eq!(contract.unstake(500), Err(Error::StakeLocked));
+            assert_eq!(contract.execute(proposal_id), Err(Error::VotingOpen));
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1001);
+            assert_eq!(contract.vote(proposal_id, VoteType::For), Err(Error::VotingClosed));
+            contract.execute(proposal_id).unwrap();
+            let proposal = contract.get_proposal(proposal_id).unwrap();
+            assert_eq!(proposal.status, ProposalStatus::Executed);
+            assert_eq!(proposal.change_id, Some(1));
+            assert_eq!(contract.execute(proposal_id), Err(Error::ProposalNotActive));
+
+            // Stake is free again once the vote is over
+            contract.unstake(500).unwrap();
+            assert_eq!(contract.get_total_stake(), 1100);
+        }
+
+        #[ink::test]
+        fn test_proposal_without_quorum_is_defeated() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = contract();
+            stake(&mut contract, accounts.alice, 300);
+            stake(&mut contract, accounts.bob, 700);
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            let delist = contract.propose(ProposalAction::DelistDataset(7), [1u8; 32]).unwrap();
+            let cancelled = contract.propose(ProposalAction::DelistDataset(8), [2u8; 32]).unwrap();
+            contract.vote(delist, VoteType::For).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(contract.cancel(cancelled), Err(Error::NotProposer));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            contract.cancel(cancelled).unwrap();
+
+            // Delisting waits on the registry's timelock like any other change
+            assert_eq!(
+                ProposalAction::DelistDataset(7).queued_change(),
+                (Target::DatasetRegistry, ParameterChange::Delist(7))
+            );
+
+            // 300 of 1000 staked voted, below the 40% quorum
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1001);
+            contract.execute(delist).unwrap();
+            assert_eq!(contract.get_proposal(delist).unwrap().status, ProposalStatus::Defeated);
+            assert_eq!(contract.execute(cancelled), Err(Error::ProposalNotActive));
+        }
+
+        #[ink::test]
+        fn test_votes_count_stake_held_before_proposal() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = contract();
+            stake(&mut contract, accounts.alice, 300);
+            stake(&mut contract, accounts.bob, 600);
+            stake(&mut contract, accounts.charlie, 50);
+            stake(&mut contract, accounts.charlie, 50);
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            let action = ProposalAction::AllowCircuitType(
+                "groth16".to_string(),
+                PayloadLimits {
+                    max_proof_size: 256,
+                    max_public_inputs_size: 1024,
+                    max_key_size: 2048,
+                },
+            );
+            let proposal_id = contract.propose(action, [0u8; 32]).unwrap();
+            assert_eq!(contract.get_proposal(proposal_id).unwrap().total_stake, 1000);
+
+            // Stake moved in once the proposal is known neither votes nor raises the quorum
+            stake(&mut contract, accounts.eve, 5000);
+            stake(&mut contract, accounts.charlie, 900);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            assert_eq!(contract.vote(proposal_id, VoteType::Against), Err(Error::NoVotingPower));
+            assert_eq!(contract.get_stake_at(accounts.charlie, 1), 100);
+            assert_eq!(contract.get_stake(accounts.charlie), 1000);
+
+            // Stake withdrawn since the proposal does not vote either
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            contract.unstake(500).unwrap();
+            contract.vote(proposal_id, VoteType::Against).unwrap();
+            assert_eq!(contract.unstake(100), Err(Error::StakeLocked));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            contract.vote(proposal_id, VoteType::For).unwrap();
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            contract.vote(proposal_id, VoteType::For).unwrap();
+
+            // 400 of the 1000 staked before the proposal voted, meeting the 40% quorum
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1001);
+            contract.execute(proposal_id).unwrap();
+            let proposal = contract.get_proposal(proposal_id).unwrap();
+            assert_eq!(proposal.votes_for, 400);
+            assert_eq!(proposal.votes_against, 100);
+            assert_eq!(proposal.status, ProposalStatus::Executed);
+        }
+    }
+}
@@ -3,7 +3,21 @@
 pub use dataset_registry::*;
 pub use payment_manager::*;
 pub use zk_verifier::*;
+pub use vector_math::*;
+pub use result_commitment::*;
+pub use reputation::*;
+pub use access_control::*;
+pub use timelock::*;
+pub use governance_voting::*;
+pub use oracle_connector::*;
 
 mod dataset_registry;
 mod payment_manager;
 mod zk_verifier;
+mod vector_math;
+mod result_commitment;
+mod reputation;
+mod access_control;
+mod timelock;
+mod governance_voting;
+mod oracle_connector;
@@ -0,0 +1,587 @@
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+#[ink::contract]
+pub mod oracle_connector {
+    use ink::storage::Mapping;
+    use ink::storage::traits::{SpreadLayout, PackedLayout, StorageLayout};
+    use ink::prelude::vec::Vec;
+    use ink::prelude::string::String;
+    use crate::access_control::{self, owner_roles, AccessControl, AccessControlResult, Role};
+    use crate::timelock::{
+        self, ParameterChange, QueuedChange, Timelock, TimelockError, TimelockResult,
+        DEFAULT_TIMELOCK_DELAY,
+    };
+
+    /// Fiat amounts are counted in millionths of the currency unit
+    pub const FIAT_UNIT: u128 = 1_000_000;
+    /// Longest currency code, e.g. "USD" or "EURC"
+    pub const MAX_CURRENCY_LENGTH: usize = 8;
+    /// Largest number of reporters
+    pub const MAX_REPORTERS: usize = 16;
+    /// Fewest fresh reports a rate can be taken from. With three or more, the
+    /// median stays between honest reports while one reporter is wrong; with
+    /// two it would be their average, which either one moves at will.
+    pub const MIN_REPORTS: u32 = 3;
+    /// Largest number of currencies one reporter can report
+    pub const MAX_CURRENCIES: usize = 32;
+
+    /// Check a currency code is uppercase ASCII letters and digits
+    pub fn is_valid_currency(currency: &str) -> bool {
+        !currency.is_empty()
+            && currency.len() <= MAX_CURRENCY_LENGTH
+            && currency.bytes().all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit())
+    }
+
+    /// Latest rate from one reporter
+    #[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct Report {
+        pub rate: Balance, // Native units per currency unit
+        pub reported_at: Timestamp,
+    }
+
+    /// Rate agreed by the reporters
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct Rate {
+        pub rate: Balance,         // Median of the fresh reports
+        pub updated_at: Timestamp, // Time of the oldest fresh report
+        pub reports: u32,          // Number of fresh reports
+    }
+
+    /// Oracle connector contract. Authorized reporters post how many native
+    /// units one unit of a reference currency is worth; the rate used is the
+    /// median of the reports younger than `max_staleness`, and there is no
+    /// rate while fewer than `min_reports`, at least `MIN_REPORTS`, are fresh.
+    /// The reporter set and both limits change only through `Timelock`, so
+    /// users see a new reporter coming before its reports can move a rate.
+    #[ink(storage)]
+    pub struct OracleConnector {
+        /// Accounts allowed to report rates
+        reporters: Vec<AccountId>,
+        /// Mapping from currency and reporter to their latest report
+        reports: Mapping<(String, AccountId), Report>,
+        /// Mapping from reporter to the currencies it has reported
+        reporter_currencies: Mapping<AccountId, Vec<String>>,
+        /// Fresh reports needed for a rate
+        min_reports: u32,
+        /// Age after which a report is ignored, in milliseconds
+        max_staleness: u64,
+        /// Contract owner
+        owner: AccountId,
+        /// Account ownership was offered to
+        pending_owner: Option<AccountId>,
+        /// Role assignments
+        roles: Mapping<(Role, AccountId), bool>,
+        /// Delay between queueing and executing a parameter change
+        timelock_delay: u64,
+        /// Parameter changes waiting for their ETA
+        queued_changes: Mapping<u64, QueuedChange>,
+        /// Next change ID
+        next_change_id: u64,
+    }
+
+    /// Events
+    #[ink(event)]
+    pub struct ReporterAdded {
+        #[ink(topic)]
+        reporter: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct ReporterRemoved {
+        #[ink(topic)]
+        reporter: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct RateReported {
+        #[ink(topic)]
+        reporter: AccountId,
+        currency: String,
+        rate: Balance,
+    }
+
+    #[ink(event)]
+    pub struct ChangeQueued {
+        #[ink(topic)]
+        change_id: u64,
+        change: ParameterChange,
+        eta: Timestamp,
+    }
+
+    #[ink(event)]
+    pub struct ChangeCancelled {
+        #[ink(topic)]
+        change_id: u64,
+    }
+
+    #[ink(event)]
+    pub struct ChangeExecuted {
+        #[ink(topic)]
+        change_id: u64,
+        change: ParameterChange,
+    }
+
+    #[ink(event)]
+    pub struct RoleGranted {
+        #[ink(topic)]
+        account: AccountId,
+        #[ink(topic)]
+        sender: AccountId,
+        role: Role,
+    }
+
+    #[ink(event)]
+    pub struct RoleRevoked {
+        #[ink(topic)]
+        account: AccountId,
+        #[ink(topic)]
+        sender: AccountId,
+        role: Role,
+    }
+
+    #[ink(event)]
+    pub struct OwnershipOffered {
+        #[ink(topic)]
+        owner: AccountId,
+        #[ink(topic)]
+        pending_owner: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct OwnershipTransferred {
+        #[ink(topic)]
+        previous_owner: AccountId,
+        #[ink(topic)]
+        new_owner: AccountId,
+    }
+
+    /// Errors
+    #[derive(Debug, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub enum Error {
+        NotAuthorized,
+        NotReporter,
+        ReporterAlreadyExists,
+        TooManyReporters,
+        InvalidCurrency,
+        InvalidRate,
+        NotEnoughReports,
+        ConversionOverflow,
+        TooManyCurrencies,
+    }
+
+    pub type Result<T> = core::result::Result<T, Error>;
+
+    impl OracleConnector {
+        /// Constructor. Later reporters are added through `Timelock`.
+        #[ink(constructor)]
+        pub fn new(reporters: Vec<AccountId>, min_reports: u32, max_staleness: u64) -> Self {
+            assert!(
+                min_reports >= MIN_REPORTS && min_reports as usize <= MAX_REPORTERS,
+                "invalid min_reports"
+            );
+            let owner = Self::env().caller();
+            let mut contract = Self {
+                reporters: Vec::new(),
+                reports: Mapping::default(),
+                reporter_currencies: Mapping::default(),
+                min_reports,
+                max_staleness,
+                owner,
+                pending_owner: None,
+                roles: owner_roles(owner),
+                timelock_delay: DEFAULT_TIMELOCK_DELAY,
+                queued_changes: Mapping::default(),
+                next_change_id: 1,
+            };
+            for reporter in reporters {
+                contract.add_reporter(reporter).expect("invalid reporters");
+            }
+            contract
+        }
+
+        /// Post the caller's rate for a currency (reporters only)
+        #[ink(message)]
+        pub fn report(&mut self, currency: String, rate: Balance) -> Result<()> {
+            let caller = self.env().caller();
+
+            if !self.reporters.contains(&caller) {
+                return Err(Error::NotReporter);
+            }
+            if !is_valid_currency(&currency) {
+                return Err(Error::InvalidCurrency);
+            }
+            if rate == 0 {
+                return Err(Error::InvalidRate);
+            }
+
+            let report = Report {
+                rate,
+                reported_at: self.env().block_timestamp(),
+            };
+            let mut currencies = self.reporter_currencies.get(&caller).unwrap_or_default();
+            if !currencies.contains(&currency) {
+                if currencies.len() >= MAX_CURRENCIES {
+                    return Err(Error::TooManyCurrencies);
+                }
+                currencies.push(currency.clone());
+                self.reporter_currencies.insert(caller, &currencies);
+            }
+            self.reports.insert((currency.clone(), caller), &report);
+
+            self.env().emit_event(RateReported {
+                reporter: caller,
+                currency,
+                rate,
+            });
+
+            Ok(())
+        }
+
+        /// Get the median rate of a currency
+        #[ink(message)]
+        pub fn get_rate(&self, currency: String) -> Result<Rate> {
+            let now = self.env().block_timestamp();
+
+            let mut fresh: Vec<Report> = self
+                .reporters
+                .iter()
+                .filter_map(|reporter| self.reports.get(&(currency.clone(), *reporter)))
+                .filter(|report| now.saturating_sub(report.reported_at) <= self.max_staleness)
+                .collect();
+            if (fresh.len() as u32) < self.min_reports {
+                return Err(Error::NotEnoughReports);
+            }
+
+            fresh.sort_unstable_by_key(|report| report.rate);
+            let middle = fresh.len() / 2;
+            let rate = if fresh.len() % 2 == 0 {
+                fresh[middle - 1].rate / 2 + fresh[middle].rate / 2
+                    + (fresh[middle - 1].rate % 2 + fresh[middle].rate % 2) / 2
+            } else {
+                fresh[middle].rate
+            };
+
+            Ok(Rate {
+                rate,
+                updated_at: fresh.iter().map(|report| report.reported_at).min().unwrap_or(now),
+                reports: fresh.len() as u32,
+            })
+        }
+
+        /// Convert a fiat amount, in millionths of `currency`, to native
+        /// units at the current rate. Rounds up so a fiat price is never
+        /// undercharged.
+        #[ink(message)]
+        pub fn convert(&self, currency: String, fiat_amount: u128) -> Result<Balance> {
+            let rate = self.get_rate(currency)?.rate;
+            let scaled = fiat_amount.checked_mul(rate).ok_or(Error::ConversionOverflow)?;
+            Ok(scaled.div_ceil(FIAT_UNIT))
+        }
+
+        /// Get the latest report of a reporter
+        #[ink(message)]
+        pub fn get_report(&self, currency: String, reporter: AccountId) -> Option<Report> {
+            self.reports.get(&(currency, reporter))
+        }
+
+        #[ink(message)]
+        pub fn get_reporters(&self) -> Vec<AccountId> {
+            self.reporters.clone()
+        }
+
+        #[ink(message)]
+        pub fn get_min_reports(&self) -> u32 {
+            self.min_reports
+        }
+
+        #[ink(message)]
+        pub fn get_max_staleness(&self) -> u64 {
+            self.max_staleness
+        }
+
+        /// Internal helper functions
+        pub(crate) fn supports_change(change: &ParameterChange) -> bool {
+            matches!(
+                change,
+                ParameterChange::AddReporter(_)
+                    | ParameterChange::RemoveReporter(_)
+                    | ParameterChange::MinReports(_)
+                    | ParameterChange::MaxStaleness(_)
+                    | ParameterChange::TimelockDelay(_)
+            )
+        }
+
+        fn apply_change(&mut self, change: ParameterChange) -> TimelockResult<()> {
+            match change {
+                ParameterChange::AddReporter(reporter) => {
+                    self.add_reporter(reporter).map_err(|_| TimelockError::ChangeNotApplicable)?;
+                }
+                ParameterChange::RemoveReporter(reporter) => {
+                    self.remove_reporter(reporter).map_err(|_| TimelockError::ChangeNotApplicable)?;
+                }
+                ParameterChange::MinReports(min_reports) => self.min_reports = min_reports,
+                ParameterChange::MaxStaleness(max_staleness) => self.max_staleness = max_staleness,
+                ParameterChange::TimelockDelay(delay) => self.timelock_delay = delay,
+                _ => {}
+            }
+            Ok(())
+        }
+
+        fn add_reporter(&mut self, reporter: AccountId) -> Result<()> {
+            if self.reporters.contains(&reporter) {
+                return Err(Error::ReporterAlreadyExists);
+            }
+            if self.reporters.len() >= MAX_REPORTERS {
+                return Err(Error::TooManyReporters);
+            }
+
+            self.reporters.push(reporter);
+
+            self.env().emit_event(ReporterAdded { reporter });
+
+            Ok(())
+        }
+
+        /// Remove a reporter and its reports, so adding it again cannot
+        /// bring old rates back
+        fn remove_reporter(&mut self, reporter: AccountId) -> Result<()> {
+            let index = self.reporters.iter().position(|account| *account == reporter)
+                .ok_or(Error::NotReporter)?;
+            self.reporters.swap_remove(index);
+
+            for currency in self.reporter_currencies.take(&reporter).unwrap_or_default() {
+                self.reports.remove((currency, reporter));
+            }
+
+            self.env().emit_event(ReporterRemoved { reporter });
+
+            Ok(())
+        }
+    }
+
+    impl Timelock for OracleConnector {
+        #[ink(message)]
+        fn queue_change(&mut self, change: ParameterChange) -> TimelockResult<u64> {
+            let change_id = self.next_change_id;
+            let supported = Self::supports_change(&change);
+            let queued = QueuedChange {
+                change,
+                queued_by: self.env().caller(),
+                eta: self.env().block_timestamp().saturating_add(self.timelock_delay),
+            };
+            timelock::queue_change(&mut self.queued_changes, &self.roles, change_id, &queued, supported)?;
+            self.next_change_id += 1;
+
+            self.env().emit_event(ChangeQueued {
+                change_id,
+                change: queued.change,
+                eta: queued.eta,
+            });
+
+            Ok(change_id)
+        }
+
+        #[ink(message)]
+        fn cancel_change(&mut self, change_id: u64) -> TimelockResult<()> {
+            let caller = self.env().caller();
+            timelock::cancel_change(&mut self.queued_changes, &self.roles, change_id, caller)?;
+
+            self.env().emit_event(ChangeCancelled { change_id });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn execute_change(&mut self, change_id: u64) -> TimelockResult<()> {
+            let now = self.env().block_timestamp();
+            let change = timelock::execute_change(&mut self.queued_changes, change_id, now)?;
+            self.apply_change(change.clone())?;
+
+            self.env().emit_event(ChangeExecuted { change_id, change });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn get_queued_change(&self, change_id: u64) -> Option<QueuedChange> {
+            self.queued_changes.get(&change_id)
+        }
+
+        #[ink(message)]
+        fn get_timelock_delay(&self) -> u64 {
+            self.timelock_delay
+        }
+    }
+
+    impl AccessControl for OracleConnector {
+        #[ink(message)]
+        fn has_role(&self, role: Role, account: AccountId) -> bool {
+            access_control::has_role(&self.roles, role, account)
+        }
+
+        #[ink(message)]
+        fn grant_role(&mut self, role: Role, account: AccountId) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            access_control::grant_role(&mut self.roles, caller, role, account)?;
+
+            self.env().emit_event(RoleGranted {
+                account,
+                sender: caller,
+                role,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn revoke_role(&mut self, role: Role, account: AccountId) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            access_control::revoke_role(&mut self.roles, self.owner, caller, role, account)?;
+
+            self.env().emit_event(RoleRevoked {
+                account,
+                sender: caller,
+                role,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn renounce_role(&mut self, role: Role) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            self.revoke_role(role, caller)
+        }
+
+        #[ink(message)]
+        fn owner(&self) -> AccountId {
+            self.owner
+        }
+
+        #[ink(message)]
+        fn transfer_ownership(&mut self, new_owner: AccountId) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            access_control::ensure_owner(self.owner, caller)?;
+
+            self.pending_owner = Some(new_owner);
+
+            self.env().emit_event(OwnershipOffered {
+                owner: caller,
+                pending_owner: new_owner,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn accept_ownership(&mut self) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            let previous_owner = self.owner;
+            access_control::accept_ownership(&mut self.roles, previous_owner, self.pending_owner, caller)?;
+
+            self.owner = caller;
+            self.pending_owner = None;
+
+            self.env().emit_event(OwnershipTransferred {
+                previous_owner,
+                new_owner: caller,
+            });
+
+            Ok(())
+        }
+    }
+
+    /// Unit tests
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[ink::test]
+        fn test_median_of_fresh_reports() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let reporters = vec![accounts.bob, accounts.charlie, accounts.django, accounts.eve];
+            let mut contract = OracleConnector::new(reporters, 3, 1000);
+
+            assert_eq!(contract.report("USD".to_string(), 100), Err(Error::NotReporter));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(contract.report("usd".to_string(), 100), Err(Error::InvalidCurrency));
+            contract.report("USD".to_string(), 100).unwrap();
+            assert_eq!(contract.get_rate("USD".to_string()), Err(Error::NotEnoughReports));
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(500);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            contract.report("USD".to_string(), 300).unwrap();
+            assert_eq!(contract.get_rate("USD".to_string()), Err(Error::NotEnoughReports));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            contract.report("USD".to_string(), 1_000_000).unwrap();
+
+            // One outlier cannot move the median
+            let rate = contract.get_rate("USD".to_string()).unwrap();
+            assert_eq!(rate, Rate { rate: 300, updated_at: 0, reports: 3 });
+
+            // 2.5 USD at 300 per USD
+            assert_eq!(contract.convert("USD".to_string(), 2_500_000), Ok(750));
+
+            // An even number of reports averages the two in the middle
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.report("USD".to_string(), 400).unwrap();
+            assert_eq!(contract.get_rate("USD".to_string()).unwrap().rate, 350);
+
+            // Bob's report goes stale, and the outlier still cannot move the median
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1001);
+            let rate = contract.get_rate("USD".to_string()).unwrap();
+            assert_eq!(rate, Rate { rate: 400, updated_at: 500, reports: 3 });
+
+        }
+
+        #[ink::test]
+        fn test_reporter_changes_wait_for_timelock() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let reporters = vec![accounts.bob, accounts.charlie, accounts.django];
+            let mut contract = OracleConnector::new(reporters, 3, DEFAULT_TIMELOCK_DELAY * 2);
+            for reporter in [accounts.bob, accounts.charlie, accounts.django] {
+                ink::env::test::set_caller::<ink::env::DefaultEnvironment>(reporter);
+                contract.report("USD".to_string(), 300).unwrap();
+            }
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(
+                contract.queue_change(ParameterChange::AddReporter(accounts.eve)),
+                Err(TimelockError::MissingRole)
+            );
+
+            // A new reporter cannot report before the delay has passed
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            let add = contract.queue_change(ParameterChange::AddReporter(accounts.eve)).unwrap();
+            let remove = contract.queue_change(ParameterChange::RemoveReporter(accounts.django)).unwrap();
+            assert_eq!(contract.execute_change(add), Err(TimelockError::ChangeNotReady));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            assert_eq!(contract.report("USD".to_string(), 900), Err(Error::NotReporter));
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(DEFAULT_TIMELOCK_DELAY);
+            contract.execute_change(add).unwrap();
+            contract.execute_change(remove).unwrap();
+            assert_eq!(contract.get_reporters(), vec![accounts.bob, accounts.charlie, accounts.eve]);
+            assert_eq!(contract.get_report("USD".to_string(), accounts.django), None);
+            assert_eq!(contract.get_rate("USD".to_string()), Err(Error::NotEnoughReports));
+
+            // Adding the reporter back does not revive its old report
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            let readd = contract.queue_change(ParameterChange::AddReporter(accounts.django)).unwrap();
+            let min_reports = contract.queue_change(ParameterChange::MinReports(4)).unwrap();
+            assert_eq!(
+                contract.queue_change(ParameterChange::MinReports(2)),
+                Err(TimelockError::ValueOutOfRange)
+            );
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(2 * DEFAULT_TIMELOCK_DELAY);
+            contract.execute_change(readd).unwrap();
+            contract.execute_change(min_reports).unwrap();
+            assert_eq!(contract.get_min_reports(), 4);
+            assert_eq!(contract.get_report("USD".to_string(), accounts.django), None);
+        }
+    }
+}
@@ -1,17 +1,65 @@
 #![cfg_attr(not(feature = "std"), no_std, no_main)]
 
 #[ink::contract]
-mod payment_manager {
-    use ink::storage::Mapping;
-    use ink::storage::traits::{SpreadLayout, PackedLayout, StorageLayout};
+pub mod payment_manager {
+    use ink::storage::{Lazy, Mapping};
+    use ink::storage::traits::{ManualKey, SpreadLayout, PackedLayout, StorageLayout};
+    use ink::primitives::KeyComposer;
     use ink::prelude::vec::Vec;
     use ink::prelude::string::String;
+    use ink::env::call::{build_call, ExecutionInput, Selector};
+    use ink::env::DefaultEnvironment;
+    use crate::result_commitment::{opening_nullifier, query_commitment, MAX_TOP_K};
+    use crate::reputation::MAX_SCORE;
+    use crate::dataset_registry::dataset_registry::{Dataset, FiatPrice, QuerySettlement};
+    use crate::timelock::{
+        self, ParameterChange, QueuedChange, Timelock, TimelockError, TimelockResult,
+        DEFAULT_TIMELOCK_DELAY,
+    };
+    use crate::access_control::{
+        self, owner_roles, AccessControl, AccessControlResult, Operation, Pausable, Role,
+    };
+
+    /// Operations a Pauser can stop in this contract
+    const PAUSABLE_OPERATIONS: [Operation; 2] = [Operation::Payments, Operation::Payouts];
+
+    /// Storage layout version written by this code
+    const STORAGE_VERSION: u32 = 2;
+    /// Key storage version 1 kept payments under
+    const PAYMENTS_V1_KEY: u32 = KeyComposer::from_str("PaymentManager::payments");
+    /// Key payments are kept under since storage version 2
+    const PAYMENTS_KEY: u32 = KeyComposer::from_str("PaymentManager::payments_v2");
+    /// Keys of the root fields added since storage version 1. Each lives in a
+    /// cell of its own so a version 1 root, which lacks them, still decodes.
+    const STORAGE_VERSION_KEY: u32 = KeyComposer::from_str("PaymentManager::storage_version");
+    const MIGRATION_CURSOR_KEY: u32 = KeyComposer::from_str("PaymentManager::migration_cursor");
+    const PENDING_OWNER_KEY: u32 = KeyComposer::from_str("PaymentManager::pending_owner");
+    const TIMELOCK_DELAY_KEY: u32 = KeyComposer::from_str("PaymentManager::timelock_delay");
+    const NEXT_CHANGE_ID_KEY: u32 = KeyComposer::from_str("PaymentManager::next_change_id");
+    const PRIVATE_POOL_KEY: u32 = KeyComposer::from_str("PaymentManager::private_pool");
+    const ORACLE_KEY: u32 = KeyComposer::from_str("PaymentManager::oracle");
     
     /// Query payment information
     #[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
     #[cfg_attr(feature = "std", derive(StorageLayout))]
     #[ink::scale_derive(Encode, Decode, TypeInfo)]
     pub struct Payment {
+        pub query_id: u64,
+        pub dataset_id: u64,
+        pub user: AccountId,
+        pub amount: Balance,
+        pub timestamp: Timestamp,
+        pub top_k: u32, // Number of ranked results purchased
+        pub status: PaymentStatus,
+        pub proof_hash: Option<[u8; 32]>,
+        pub query_commitment: Option<[u8; 32]>, // Set for private payments, see create_private_payment
+    }
+
+    /// Payment as stored by storage version 1
+    #[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct PaymentV1 {
         pub query_id: u64,
         pub dataset_id: u64,
         pub user: AccountId,
@@ -21,6 +69,24 @@ mod payment_manager {
         pub proof_hash: Option<[u8; 32]>,
     }
 
+    impl From<PaymentV1> for Payment {
+        /// Version 1 did not record how many results were bought, so migrated
+        /// payments are treated as buying the most a query can return.
+        fn from(payment: PaymentV1) -> Self {
+            Self {
+                query_id: payment.query_id,
+                dataset_id: payment.dataset_id,
+                user: payment.user,
+                amount: payment.amount,
+                timestamp: payment.timestamp,
+                top_k: MAX_TOP_K,
+                status: payment.status,
+                proof_hash: payment.proof_hash,
+                query_commitment: None,
+            }
+        }
+    }
+
     /// Payment status
     #[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
     #[cfg_attr(feature = "std", derive(StorageLayout))]
@@ -44,6 +110,37 @@ mod payment_manager {
         pub release_time: Timestamp,
     }
 
+    /// Settlement of a private payment by the proof that answered it, under a
+    /// query ID of its own. It names the dataset but neither the payment nor
+    /// its user: the proof only revealed the payment's nullifier.
+    #[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct PrivateSettlement {
+        pub nullifier: [u8; 32],
+        pub dataset_id: u64,
+        pub dataset_owner: AccountId,
+        pub top_k: u32,
+        pub amount: Balance, // Price fixed when the proof was submitted
+        pub status: PaymentStatus,
+        pub proof_hash: Option<[u8; 32]>,
+        pub release_time: Timestamp, // Set on completion
+        pub released: bool,
+    }
+
+    /// What remains of a settled payment once it is archived
+    #[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct PaymentReceipt {
+        pub query_id: u64,
+        pub dataset_id: u64,
+        pub user: AccountId,
+        pub amount: Balance,
+        pub status: PaymentStatus, // Completed or Refunded
+        pub proof_hash: Option<[u8; 32]>,
+    }
+
     /// Payment manager contract
     #[ink(storage)]
     pub struct PaymentManager {
@@ -52,19 +149,56 @@ mod payment_manager {
         /// ZK verifier contract address  
         zk_verifier: AccountId,
         /// Mapping from query ID to payment info
-        payments: Mapping<u64, Payment>,
+        payments: Mapping<u64, Payment, ManualKey<PAYMENTS_KEY>>,
+        /// Payments not yet migrated from storage version 1
+        legacy_payments: Mapping<u64, PaymentV1, ManualKey<PAYMENTS_V1_KEY>>,
         /// Mapping from user to their payments
         user_payments: Mapping<AccountId, Vec<u64>>,
         /// Escrow storage
         escrows: Mapping<u64, Escrow>,
+        /// Total held in escrow for each dataset
+        escrow_pools: Mapping<u64, Balance>,
+        /// Receipts of archived payments
+        payment_receipts: Mapping<u64, PaymentReceipt>,
         /// Next query ID
         next_query_id: u64,
         /// Contract owner
         owner: AccountId,
+        /// Account ownership was offered to
+        pending_owner: Lazy<Option<AccountId>, ManualKey<PENDING_OWNER_KEY>>,
+        /// Role assignments
+        roles: Mapping<(Role, AccountId), bool>,
+        /// Operations stopped by a Pauser
+        paused: Mapping<Operation, bool>,
+        /// Storage layout version, behind `STORAGE_VERSION` until `migrate`
+        /// finishes; unset in version 1 storage
+        storage_version: Lazy<u32, ManualKey<STORAGE_VERSION_KEY>>,
+        /// Next ID `migrate` looks at, unset until it first runs
+        migration_cursor: Lazy<u64, ManualKey<MIGRATION_CURSOR_KEY>>,
+        /// Time between queueing and executing a parameter change,
+        /// `DEFAULT_TIMELOCK_DELAY` until changed
+        timelock_delay: Lazy<u64, ManualKey<TIMELOCK_DELAY_KEY>>,
+        /// Parameter changes waiting for their ETA
+        queued_changes: Mapping<u64, QueuedChange>,
+        /// Next parameter change ID, 1 until a change is queued
+        next_change_id: Lazy<u64, ManualKey<NEXT_CHANGE_ID_KEY>>,
         /// Platform fee percentage (basis points, e.g., 250 = 2.5%)
         platform_fee_bps: u16,
         /// Escrow period in milliseconds
         escrow_period: u64,
+        /// Funds of private payments not yet paid into a dataset's escrow pool
+        private_pool: Lazy<Balance, ManualKey<PRIVATE_POOL_KEY>>,
+        /// Settlements of private payments, by their own query ID
+        private_settlements: Mapping<u64, PrivateSettlement>,
+        /// Latest settlement opened for each private payment nullifier
+        nullifier_settlements: Mapping<[u8; 32], u64>,
+        /// Nullifiers of redeemed private payments and the payment each one is
+        /// for, revealed by its user when redeeming
+        redeemed_nullifiers: Mapping<[u8; 32], u64>,
+        /// Lowest dataset owner reputation each user accepts, in basis points
+        min_reputation: Mapping<AccountId, u32>,
+        /// Oracle connector address, used to convert fiat prices
+        oracle: Lazy<AccountId, ManualKey<ORACLE_KEY>>,
     }
 
     /// Events
@@ -79,6 +213,30 @@ mod payment_manager {
         amount: Balance,
     }
 
+    #[ink(event)]
+    pub struct PrivatePaymentCreated {
+        #[ink(topic)]
+        query_id: u64,
+        #[ink(topic)]
+        user: AccountId,
+        query_commitment: [u8; 32],
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct PrivateSettlementOpened {
+        #[ink(topic)]
+        query_id: u64,
+        nullifier: [u8; 32],
+    }
+
+    #[ink(event)]
+    pub struct PrivateSettlementRefunded {
+        #[ink(topic)]
+        query_id: u64,
+        amount: Balance,
+    }
+
     #[ink(event)]
     pub struct PaymentCompleted {
         #[ink(topic)]
@@ -95,6 +253,22 @@ mod payment_manager {
         amount: Balance,
     }
 
+    #[ink(event)]
+    pub struct PaymentArchived {
+        #[ink(topic)]
+        query_id: u64,
+        #[ink(topic)]
+        user: AccountId,
+        #[ink(topic)]
+        dataset_id: u64,
+        amount: Balance,
+        timestamp: Timestamp,
+        top_k: u32,
+        status: PaymentStatus,
+        proof_hash: Option<[u8; 32]>,
+        query_commitment: Option<[u8; 32]>,
+    }
+
     #[ink(event)]
     pub struct EscrowReleased {
         #[ink(topic)]
@@ -104,6 +278,86 @@ mod payment_manager {
         amount: Balance,
     }
 
+    #[ink(event)]
+    pub struct RoleGranted {
+        #[ink(topic)]
+        account: AccountId,
+        #[ink(topic)]
+        sender: AccountId,
+        role: Role,
+    }
+
+    #[ink(event)]
+    pub struct RoleRevoked {
+        #[ink(topic)]
+        account: AccountId,
+        #[ink(topic)]
+        sender: AccountId,
+        role: Role,
+    }
+
+    #[ink(event)]
+    pub struct OwnershipOffered {
+        #[ink(topic)]
+        owner: AccountId,
+        #[ink(topic)]
+        pending_owner: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct OwnershipTransferred {
+        #[ink(topic)]
+        previous_owner: AccountId,
+        #[ink(topic)]
+        new_owner: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct ChangeQueued {
+        #[ink(topic)]
+        change_id: u64,
+        change: ParameterChange,
+        eta: Timestamp,
+    }
+
+    #[ink(event)]
+    pub struct ChangeCancelled {
+        #[ink(topic)]
+        change_id: u64,
+    }
+
+    #[ink(event)]
+    pub struct ChangeExecuted {
+        #[ink(topic)]
+        change_id: u64,
+        change: ParameterChange,
+    }
+
+    #[ink(event)]
+    pub struct Upgraded {
+        #[ink(topic)]
+        code_hash: Hash,
+    }
+
+    #[ink(event)]
+    pub struct StorageMigrated {
+        version: u32,
+    }
+
+    #[ink(event)]
+    pub struct Paused {
+        #[ink(topic)]
+        account: AccountId,
+        operation: Operation,
+    }
+
+    #[ink(event)]
+    pub struct Unpaused {
+        #[ink(topic)]
+        account: AccountId,
+        operation: Operation,
+    }
+
     /// Errors
     #[derive(Debug, PartialEq, Eq)]
     #[ink::scale_derive(Encode, Decode, TypeInfo)]
@@ -116,6 +370,23 @@ mod payment_manager {
         TransferFailed,
         InvalidProof,
         DatasetNotFound,
+        ChallengeWindowOpen,
+        InvalidTopK,
+        DatasetMismatch,
+        PaymentNotSettled,
+        PaymentAlreadyArchived,
+        BuyerNotAuthorized,
+        InvalidReputation,
+        ProviderReputationTooLow,
+        DatasetUnavailable,
+        OperationPaused,
+        UpgradeFailed,
+        AlreadyMigrated,
+        OracleNotSet,
+        PriceUnavailable,
+        PriceAlreadyFixed,
+        NullifierSpent,
+        InvalidOpening,
     }
 
     pub type Result<T> = core::result::Result<T, Error>;
@@ -129,29 +400,80 @@ mod payment_manager {
             platform_fee_bps: u16,
             escrow_period: u64,
         ) -> Self {
-            Self {
+            assert!(
+                ParameterChange::PlatformFee(platform_fee_bps).validate().is_ok()
+                    && ParameterChange::EscrowPeriod(escrow_period).validate().is_ok(),
+                "parameter out of range"
+            );
+            let owner = Self::env().caller();
+            let mut instance = Self {
                 dataset_registry,
                 zk_verifier,
                 payments: Mapping::default(),
+                legacy_payments: Mapping::default(),
                 user_payments: Mapping::default(),
                 escrows: Mapping::default(),
+                escrow_pools: Mapping::default(),
+                payment_receipts: Mapping::default(),
                 next_query_id: 1,
-                owner: Self::env().caller(),
+                owner,
+                pending_owner: Lazy::new(),
+                roles: owner_roles(owner),
+                paused: Mapping::default(),
+                storage_version: Lazy::new(),
+                migration_cursor: Lazy::new(),
+                timelock_delay: Lazy::new(),
+                queued_changes: Mapping::default(),
+                next_change_id: Lazy::new(),
                 platform_fee_bps,
                 escrow_period,
-            }
+                private_pool: Lazy::new(),
+                private_settlements: Mapping::default(),
+                nullifier_settlements: Mapping::default(),
+                redeemed_nullifiers: Mapping::default(),
+                min_reputation: Mapping::default(),
+                oracle: Lazy::new(),
+            };
+            instance.storage_version.set(&STORAGE_VERSION);
+            instance
         }
 
-        /// Create payment for query
+        /// Create payment for a query returning the `top_k` best matches.
+        /// The transferred value is the most the caller agrees to pay: a fiat
+        /// priced dataset is converted at the current oracle rate, and the
+        /// payment fails rather than charge more than was sent. Anything
+        /// above the price is refunded.
         #[ink(message, payable)]
-        pub fn create_payment(&mut self, dataset_id: u64) -> Result<u64> {
+        pub fn create_payment(&mut self, dataset_id: u64, top_k: u32) -> Result<u64> {
+            self.ensure_not_paused(Operation::Payments)?;
+
             let caller = self.env().caller();
             let value = self.env().transferred_value();
             let now = self.env().block_timestamp();
 
-            // Get dataset price from registry
-            let price = self.get_dataset_price(dataset_id)?;
-            
+            if top_k == 0 || top_k > MAX_TOP_K {
+                return Err(Error::InvalidTopK);
+            }
+
+            // Only active datasets take new buyers
+            let dataset = self.get_dataset(dataset_id).ok_or(Error::DatasetNotFound)?;
+            if !dataset.status.accepts_payments() {
+                return Err(Error::DatasetUnavailable);
+            }
+
+            // Private datasets only sell to allowlisted accounts or token holders
+            if !self.is_authorized_buyer(dataset_id, caller) {
+                return Err(Error::BuyerNotAuthorized);
+            }
+
+            // Users can refuse providers with a poor track record
+            let min_score = self.min_reputation.get(&caller).unwrap_or(0);
+            if min_score > 0 && self.get_reputation_score(dataset.owner) < min_score {
+                return Err(Error::ProviderReputationTooLow);
+            }
+
+            let price = self.query_price(&dataset)?;
+
             if value < price {
                 return Err(Error::InsufficientPayment);
             }
@@ -164,8 +486,10 @@ mod payment_manager {
                 user: caller,
                 amount: price,
                 timestamp: now,
+                top_k,
                 status: PaymentStatus::Pending,
                 proof_hash: None,
+                query_commitment: None,
             };
 
             // Store payment
@@ -176,20 +500,18 @@ mod payment_manager {
             user_list.push(query_id);
             self.user_payments.insert(&caller, &user_list);
 
-            // Get dataset owner
-            let dataset_owner = self.get_dataset_owner(dataset_id)?;
-
             // Create escrow
             let escrow = Escrow {
                 user: caller,
-                dataset_owner,
+                dataset_owner: dataset.owner,
                 amount: price,
                 created_at: now,
                 release_time: now + self.escrow_period,
             };
-            self.escrows.insert(query_id, &escrow);
+            self.open_escrow(query_id, dataset_id, &escrow);
 
             self.next_query_id += 1;
+            self.increment_query_count(dataset_id);
 
             // Refund excess payment
             if value > price {
@@ -207,11 +529,83 @@ mod payment_manager {
             Ok(query_id)
         }
 
+        /// Create a payment that does not reveal which dataset is queried.
+        /// `query_commitment` is `result_commitment::query_commitment` of the
+        /// opening over the dataset ID, top-k, query hash and a secret salt.
+        /// The funds go to a shared pool and the payment never learns its
+        /// dataset. The user sends the opening to the prover off-chain, and the
+        /// proof settles the payment through its nullifier alone (see
+        /// `open_private_settlement`), so no record or event pairs the user
+        /// with the dataset. The settlement charges the dataset's price; the
+        /// user can `redeem_private_payment` what is left, which links the two.
+        /// A private dataset's owner decides off-chain whose queries to prove,
+        /// since the contract never learns the buyer.
+        #[ink(message, payable)]
+        pub fn create_private_payment(&mut self, query_commitment: [u8; 32]) -> Result<u64> {
+            self.ensure_not_paused(Operation::Payments)?;
+
+            let caller = self.env().caller();
+            let value = self.env().transferred_value();
+            let now = self.env().block_timestamp();
+
+            if value == 0 {
+                return Err(Error::InsufficientPayment);
+            }
+
+            let query_id = self.next_query_id;
+
+            let payment = Payment {
+                query_id,
+                dataset_id: 0,
+                user: caller,
+                amount: value,
+                timestamp: now,
+                top_k: 0,
+                status: PaymentStatus::Pending,
+                proof_hash: None,
+                query_commitment: Some(query_commitment),
+            };
+
+            // Store payment
+            self.payments.insert(query_id, &payment);
+
+            // Update user payments list
+            let mut user_list = self.user_payments.get(&caller).unwrap_or_default();
+            user_list.push(query_id);
+            self.user_payments.insert(&caller, &user_list);
+
+            self.private_pool.set(&(self.get_private_pool() + value));
+            self.next_query_id += 1;
+
+            self.env().emit_event(PrivatePaymentCreated {
+                query_id,
+                user: caller,
+                query_commitment,
+                amount: value,
+            });
+
+            Ok(query_id)
+        }
+
         /// Complete payment with proof
         #[ink(message)]
         pub fn complete_payment(
             &mut self,
             query_id: u64,
+            dataset_id: u64,
+            proof_hash: [u8; 32],
+        ) -> Result<()> {
+            self.complete_payments(ink::prelude::vec![query_id], dataset_id, proof_hash)
+        }
+
+        /// Complete every payment or private settlement one proof covers. All
+        /// of them are checked before any completes, so an aggregated proof
+        /// pays for every query it covers or for none.
+        #[ink(message)]
+        pub fn complete_payments(
+            &mut self,
+            query_ids: Vec<u64>,
+            dataset_id: u64,
             proof_hash: [u8; 32],
         ) -> Result<()> {
             let caller = self.env().caller();
@@ -221,27 +615,135 @@ mod payment_manager {
                 return Err(Error::NotAuthorized);
             }
 
-            let mut payment = self.payments.get(&query_id).ok_or(Error::PaymentNotFound)?;
-            
-            if payment.status != PaymentStatus::Pending {
-                return Err(Error::PaymentAlreadyCompleted);
+            let mut payments: Vec<Payment> = Vec::new();
+            let mut settlements: Vec<(u64, PrivateSettlement)> = Vec::new();
+            for (i, &query_id) in query_ids.iter().enumerate() {
+                if query_ids[..i].contains(&query_id) {
+                    return Err(Error::PaymentAlreadyCompleted);
+                }
+
+                if let Some(settlement) = self.private_settlements.get(&query_id) {
+                    if settlement.status != PaymentStatus::Pending {
+                        return Err(Error::PaymentAlreadyCompleted);
+                    }
+                    if settlement.dataset_id != dataset_id {
+                        return Err(Error::DatasetMismatch);
+                    }
+                    settlements.push((query_id, settlement));
+                    continue;
+                }
+
+                let payment = self.payments.get(&query_id).ok_or(Error::PaymentNotFound)?;
+                if payment.status != PaymentStatus::Pending {
+                    return Err(Error::PaymentAlreadyCompleted);
+                }
+                // Private payments are only ever completed through a settlement
+                if payment.query_commitment.is_some() || payment.dataset_id != dataset_id {
+                    return Err(Error::DatasetMismatch);
+                }
+                payments.push(payment);
             }
 
-            payment.status = PaymentStatus::Completed;
-            payment.proof_hash = Some(proof_hash);
-            self.payments.insert(query_id, &payment);
+            // The proofs showed each settled payment holds its price
+            let owed: Balance = settlements.iter().map(|(_, settlement)| settlement.amount).sum();
+            if owed > self.get_private_pool() {
+                return Err(Error::InsufficientPayment);
+            }
 
-            self.env().emit_event(PaymentCompleted {
+            for mut payment in payments {
+                payment.status = PaymentStatus::Completed;
+                payment.proof_hash = Some(proof_hash);
+                self.payments.insert(payment.query_id, &payment);
+                self.record_query_settlement(payment.dataset_id, QuerySettlement::Completed);
+
+                self.env().emit_event(PaymentCompleted {
+                    query_id: payment.query_id,
+                    proof_hash,
+                });
+            }
+            for (query_id, settlement) in settlements {
+                self.complete_settlement(query_id, settlement, proof_hash);
+            }
+
+            Ok(())
+        }
+
+        /// Open the settlement of a private payment for the dataset a proof
+        /// answered it from (ZK verifier only). The proof names the payment by
+        /// its nullifier and shows in zero knowledge that the payment holds at
+        /// least the price fixed here, so an oracle rate that moves or goes
+        /// stale while the proof waits for verification cannot strand it. The
+        /// settlement gets a query ID of its own, which the proof is stored
+        /// under. A nullifier whose payment was redeemed, or which already has
+        /// a settlement that was not refunded, cannot be settled again, so the
+        /// first proof's price holds.
+        #[ink(message)]
+        pub fn open_private_settlement(
+            &mut self,
+            nullifier: [u8; 32],
+            dataset_id: u64,
+            top_k: u32,
+        ) -> Result<u64> {
+            if self.env().caller() != self.zk_verifier {
+                return Err(Error::NotAuthorized);
+            }
+            if top_k == 0 || top_k > MAX_TOP_K {
+                return Err(Error::InvalidTopK);
+            }
+
+            if self.redeemed_nullifiers.contains(&nullifier) {
+                return Err(Error::NullifierSpent);
+            }
+            if let Some(settlement_id) = self.nullifier_settlements.get(&nullifier) {
+                let refunded = self
+                    .private_settlements
+                    .get(&settlement_id)
+                    .map_or(false, |settlement| settlement.status == PaymentStatus::Refunded);
+                if !refunded {
+                    return Err(Error::PriceAlreadyFixed);
+                }
+            }
+
+            // The dataset may have stopped selling since the payment was made
+            let dataset = self.get_dataset(dataset_id).ok_or(Error::DatasetNotFound)?;
+            if !dataset.status.accepts_proofs() {
+                return Err(Error::DatasetUnavailable);
+            }
+            let price = self.query_price(&dataset)?;
+
+            let query_id = self.next_query_id;
+            let settlement = PrivateSettlement {
+                nullifier,
+                dataset_id,
+                dataset_owner: dataset.owner,
+                top_k,
+                amount: price,
+                status: PaymentStatus::Pending,
+                proof_hash: None,
+                release_time: 0,
+                released: false,
+            };
+            self.private_settlements.insert(query_id, &settlement);
+            self.nullifier_settlements.insert(nullifier, &query_id);
+            self.next_query_id += 1;
+
+            self.env().emit_event(PrivateSettlementOpened {
                 query_id,
-                proof_hash,
+                nullifier,
             });
 
-            Ok(())
+            Ok(query_id)
         }
 
         /// Release escrow to dataset owner
         #[ink(message)]
         pub fn release_escrow(&mut self, query_id: u64) -> Result<()> {
+            self.ensure_not_paused(Operation::Payouts)?;
+
+            if let Some(settlement) = self.private_settlements.get(&query_id) {
+                return self.release_settlement(query_id, settlement);
+            }
+
             let now = self.env().block_timestamp();
             let escrow = self.escrows.get(&query_id).ok_or(Error::PaymentNotFound)?;
             let payment = self.payments.get(&query_id).ok_or(Error::PaymentNotFound)?;
@@ -251,6 +753,11 @@ mod payment_manager {
                 return Err(Error::EscrowNotReady);
             }
 
+            // Funds stay locked while the proof can still be challenged
+            if !self.is_proof_final(query_id) {
+                return Err(Error::ChallengeWindowOpen);
+            }
+
             // Calculate platform fee
             let platform_fee = (escrow.amount * self.platform_fee_bps as u128) / 10000;
             let owner_amount = escrow.amount - platform_fee;
@@ -266,7 +773,8 @@ mod payment_manager {
             }
 
             // Remove escrow
-            self.escrows.remove(&query_id);
+            self.close_escrow(query_id, payment.dataset_id);
+            self.record_query_settlement(payment.dataset_id, QuerySettlement::Released(owner_amount));
 
             self.env().emit_event(EscrowReleased {
                 query_id,
@@ -282,15 +790,23 @@ mod payment_manager {
         pub fn refund_payment(&mut self, query_id: u64) -> Result<()> {
             let caller = self.env().caller();
             
-            // Only contract owner can initiate refunds
-            if caller != self.owner {
-                return Err(Error::NotAuthorized);
+            // Only an Arbiter settling a dispute or the ZK verifier can initiate refunds
+            if caller != self.zk_verifier {
+                self.ensure_role(Role::Arbiter)?;
+            }
+
+            if let Some(settlement) = self.private_settlements.get(&query_id) {
+                return self.refund_settlement(query_id, settlement);
             }
 
             let mut payment = self.payments.get(&query_id).ok_or(Error::PaymentNotFound)?;
+
+            // Private payments have no escrow: only their user can redeem them
             let escrow = self.escrows.get(&query_id).ok_or(Error::PaymentNotFound)?;
 
-            if payment.status == PaymentStatus::Completed {
+            // A completed payment is only refunded when its proof was successfully
+            // challenged, which the verifier reports before the escrow is released
+            if payment.status == PaymentStatus::Completed && caller != self.zk_verifier {
                 return Err(Error::PaymentAlreadyCompleted);
             }
 
@@ -302,7 +818,13 @@ mod payment_manager {
                 .map_err(|_| Error::TransferFailed)?;
 
             // Remove escrow
-            self.escrows.remove(&query_id);
+            self.close_escrow(query_id, payment.dataset_id);
+            self.record_query_settlement(payment.dataset_id, QuerySettlement::Refunded);
+
+            // Refunds the verifier asks for are already on the provider's record
+            if caller != self.zk_verifier {
+                self.report_dispute_refund(escrow.dataset_owner);
+            }
 
             self.env().emit_event(PaymentRefunded {
                 query_id,
@@ -313,61 +835,936 @@ mod payment_manager {
             Ok(())
         }
 
-        /// Get payment information
-        #[ink(message)]
-        pub fn get_payment(&self, query_id: u64) -> Option<Payment> {
-            self.payments.get(&query_id)
-        }
-
-        /// Get user payments
-        #[ink(message)]
-        pub fn get_user_payments(&self, user: AccountId) -> Vec<u64> {
-            self.user_payments.get(&user).unwrap_or_default()
-        }
-
-        /// Get escrow information
+        /// Withdraw a payment no proof completed. Once `escrow_period` has
+        /// passed since the payment was made, its user can take it back from
+        /// the escrow. Payments for a dataset that was delisted or deleted will
+        /// never be proven and can be withdrawn at once; completed ones were
+        /// delivered and still settle to the owner. A proof still waiting for a
+        /// reclaimed payment is rejected by the verifier instead of completing
+        /// it. Private payments are withdrawn with `redeem_private_payment`.
+        /// Withdrawals cannot be paused.
         #[ink(message)]
-        pub fn get_escrow(&self, query_id: u64) -> Option<Escrow> {
-            self.escrows.get(&query_id)
-        }
+        pub fn reclaim_payment(&mut self, query_id: u64) -> Result<()> {
+            let caller = self.env().caller();
+            let now = self.env().block_timestamp();
+            let mut payment = self.payments.get(&query_id).ok_or(Error::PaymentNotFound)?;
 
-        /// Admin functions
-        #[ink(message)]
-        pub fn set_platform_fee(&mut self, fee_bps: u16) -> Result<()> {
-            if self.env().caller() != self.owner {
+            if payment.user != caller {
                 return Err(Error::NotAuthorized);
             }
-            self.platform_fee_bps = fee_bps;
-            Ok(())
-        }
-
-        #[ink(message)]
-        pub fn set_escrow_period(&mut self, period: u64) -> Result<()> {
-            if self.env().caller() != self.owner {
-                return Err(Error::NotAuthorized);
+            if payment.status != PaymentStatus::Pending {
+                return Err(Error::PaymentAlreadyCompleted);
             }
-            self.escrow_period = period;
-            Ok(())
-        }
+            if payment.query_commitment.is_some() {
+                return Err(Error::InvalidOpening);
+            }
+            let unprovable = self
+                .get_dataset(payment.dataset_id)
+                .map_or(true, |dataset| !dataset.status.accepts_proofs());
+            if !unprovable && now < payment.timestamp.saturating_add(self.escrow_period) {
+                return Err(Error::EscrowNotReady);
+            }
+
+            payment.status = PaymentStatus::Refunded;
+            self.payments.insert(query_id, &payment);
+
+            self.close_escrow(query_id, payment.dataset_id);
+            self.record_query_settlement(payment.dataset_id, QuerySettlement::Refunded);
+
+            self.env().transfer(caller, payment.amount)
+                .map_err(|_| Error::TransferFailed)?;
+
+            self.env().emit_event(PaymentRefunded {
+                query_id,
+                user: caller,
+                amount: payment.amount,
+            });
+
+            Ok(())
+        }
+
+        /// Withdraw what a private payment has left. The user reveals the
+        /// payment's opening, from which its nullifier is derived. A payment
+        /// a proof settled returns what it holds above the settled price at
+        /// once; if a challenge later refunds the settlement, the price goes
+        /// to the user too. Any other payment is returned in full once
+        /// `escrow_period` has passed, a proof still waiting for it is
+        /// rejected, and its nullifier can no longer be settled. Redeeming
+        /// reveals which settlement was the user's, so users who want their
+        /// query to stay private should pay the price and not redeem.
+        /// Withdrawals cannot be paused.
+        #[ink(message)]
+        pub fn redeem_private_payment(&mut self, query_id: u64, opening: Vec<u8>) -> Result<()> {
+            let caller = self.env().caller();
+            let now = self.env().block_timestamp();
+            let mut payment = self.payments.get(&query_id).ok_or(Error::PaymentNotFound)?;
+
+            if payment.user != caller {
+                return Err(Error::NotAuthorized);
+            }
+            if payment.status != PaymentStatus::Pending {
+                return Err(Error::PaymentAlreadyCompleted);
+            }
+            if payment.query_commitment != Some(query_commitment(&opening)) {
+                return Err(Error::InvalidOpening);
+            }
+            let nullifier = opening_nullifier(&opening).ok_or(Error::InvalidOpening)?;
+
+            let settlement = self
+                .nullifier_settlements
+                .get(&nullifier)
+                .and_then(|settlement_id| {
+                    Some((settlement_id, self.private_settlements.get(&settlement_id)?))
+                });
+            let refund = match settlement {
+                Some((_, settlement)) if settlement.status == PaymentStatus::Completed => {
+                    payment.status = PaymentStatus::Completed;
+                    payment.amount.saturating_sub(settlement.amount)
+                }
+                settlement => {
+                    if now < payment.timestamp.saturating_add(self.escrow_period) {
+                        return Err(Error::EscrowNotReady);
+                    }
+                    if let Some((settlement_id, mut settlement)) = settlement {
+                        if settlement.status == PaymentStatus::Pending {
+                            settlement.status = PaymentStatus::Refunded;
+                            self.private_settlements.insert(settlement_id, &settlement);
+                        }
+                    }
+                    payment.status = PaymentStatus::Refunded;
+                    payment.amount
+                }
+            };
+
+            self.payments.insert(query_id, &payment);
+            self.redeemed_nullifiers.insert(nullifier, &query_id);
+            self.private_pool.set(&(self.get_private_pool() - refund));
+
+            if refund > 0 {
+                self.env().transfer(caller, refund)
+                    .map_err(|_| Error::TransferFailed)?;
+            }
+
+            self.env().emit_event(PaymentRefunded {
+                query_id,
+                user: caller,
+                amount: refund,
+            });
+
+            Ok(())
+        }
+
+        /// Archive a settled payment: a completed payment whose escrow was
+        /// released, or a refunded one. The payment is replaced by a
+        /// `PaymentReceipt`. Anyone can call this.
+        #[ink(message)]
+        pub fn archive_payment(&mut self, query_id: u64) -> Result<()> {
+            if self.payment_receipts.contains(&query_id) {
+                return Err(Error::PaymentAlreadyArchived);
+            }
+            let payment = self.payments.get(&query_id).ok_or(Error::PaymentNotFound)?;
+
+            let settled = match payment.status {
+                PaymentStatus::Completed | PaymentStatus::Refunded => !self.escrows.contains(&query_id),
+                _ => false,
+            };
+            if !settled {
+                return Err(Error::PaymentNotSettled);
+            }
+
+            let receipt = PaymentReceipt {
+                query_id,
+                dataset_id: payment.dataset_id,
+                user: payment.user,
+                amount: payment.amount,
+                status: payment.status.clone(),
+                proof_hash: payment.proof_hash,
+            };
+            self.payments.remove(&query_id);
+            self.payment_receipts.insert(query_id, &receipt);
+
+            self.env().emit_event(PaymentArchived {
+                query_id,
+                user: payment.user,
+                dataset_id: payment.dataset_id,
+                amount: payment.amount,
+                timestamp: payment.timestamp,
+                top_k: payment.top_k,
+                status: payment.status,
+                proof_hash: payment.proof_hash,
+                query_commitment: payment.query_commitment,
+            });
+
+            Ok(())
+        }
+
+        /// Get the receipt of an archived payment
+        #[ink(message)]
+        pub fn get_payment_receipt(&self, query_id: u64) -> Option<PaymentReceipt> {
+            self.payment_receipts.get(&query_id)
+        }
+
+        /// Refuse datasets whose owner's reputation score is below `min_score`
+        /// basis points; 0 accepts every dataset
+        #[ink(message)]
+        pub fn set_min_reputation(&mut self, min_score: u32) -> Result<()> {
+            let caller = self.env().caller();
+
+            if min_score > MAX_SCORE {
+                return Err(Error::InvalidReputation);
+            }
+
+            if min_score == 0 {
+                self.min_reputation.remove(&caller);
+            } else {
+                self.min_reputation.insert(caller, &min_score);
+            }
+            Ok(())
+        }
+
+        /// Get the lowest dataset owner reputation a user accepts
+        #[ink(message)]
+        pub fn get_min_reputation(&self, user: AccountId) -> u32 {
+            self.min_reputation.get(&user).unwrap_or(0)
+        }
+
+        /// Get the current price of one query against dataset in native
+        /// tokens. Callers should send a margin above a fiat price's quote in
+        /// case the rate moves before the payment is made.
+        #[ink(message)]
+        pub fn quote_price(&self, dataset_id: u64) -> Result<Balance> {
+            let dataset = self.get_dataset(dataset_id).ok_or(Error::DatasetNotFound)?;
+            self.query_price(&dataset)
+        }
+
+        /// Get the price oracle, set through an `Oracle` change
+        #[ink(message)]
+        pub fn get_oracle(&self) -> Option<AccountId> {
+            self.oracle.get()
+        }
+
+        /// Get the total held in escrow for a dataset
+        #[ink(message)]
+        pub fn get_escrow_pool(&self, dataset_id: u64) -> Balance {
+            self.escrow_pools.get(&dataset_id).unwrap_or(0)
+        }
+
+        /// Get the total held for private payments not yet paid into a
+        /// dataset's escrow pool
+        #[ink(message)]
+        pub fn get_private_pool(&self) -> Balance {
+            self.private_pool.get_or_default()
+        }
+
+        /// Get a private settlement by its query ID
+        #[ink(message)]
+        pub fn get_private_settlement(&self, query_id: u64) -> Option<PrivateSettlement> {
+            self.private_settlements.get(&query_id)
+        }
+
+        /// Get the status of a payment or private settlement
+        #[ink(message)]
+        pub fn get_query_status(&self, query_id: u64) -> Option<PaymentStatus> {
+            match self.private_settlements.get(&query_id) {
+                Some(settlement) => Some(settlement.status),
+                None => self.payments.get(&query_id).map(|payment| payment.status),
+            }
+        }
+
+        /// Get payment information
+        #[ink(message)]
+        pub fn get_payment(&self, query_id: u64) -> Option<Payment> {
+            self.payments.get(&query_id)
+        }
+
+        /// Get user payments
+        #[ink(message)]
+        pub fn get_user_payments(&self, user: AccountId) -> Vec<u64> {
+            self.user_payments.get(&user).unwrap_or_default()
+        }
+
+        /// Get escrow information
+        #[ink(message)]
+        pub fn get_escrow(&self, query_id: u64) -> Option<Escrow> {
+            self.escrows.get(&query_id)
+        }
+
+        /// Get the earliest time the escrow can be released, taking the
+        /// verifier's challenge window into account
+        #[ink(message)]
+        pub fn get_release_time(&self, query_id: u64) -> Option<Timestamp> {
+            let escrow = self.escrows.get(&query_id)?;
+            match self.get_challenge_deadline(query_id) {
+                Some(deadline) => Some(escrow.release_time.max(deadline + 1)),
+                None => Some(escrow.release_time),
+            }
+        }
+
+        /// Admin functions. The platform fee and escrow period are changed
+        /// through `Timelock`.
+        #[ink(message)]
+        pub fn get_platform_fee(&self) -> u16 {
+            self.platform_fee_bps
+        }
+
+        #[ink(message)]
+        pub fn get_escrow_period(&self) -> u64 {
+            self.escrow_period
+        }
+
+        /// Convert payments left in the storage version 1 layout (owner only,
+        /// as version 1 storage has no roles yet). Looks at up to `limit` IDs
+        /// per call so large stores can be migrated over several blocks, and
+        /// returns how many records were converted. The storage version is
+        /// bumped once every ID has been looked at.
+        #[ink(message)]
+        pub fn migrate(&mut self, limit: u32) -> Result<u32> {
+            if self.env().caller() != self.owner {
+                return Err(Error::NotAuthorized);
+            }
+            if self.get_storage_version() >= STORAGE_VERSION {
+                return Err(Error::AlreadyMigrated);
+            }
+
+            // Version 1 had no roles; the owner takes all of them, as on deployment
+            let mut id = match self.migration_cursor.get() {
+                Some(cursor) => cursor,
+                None => {
+                    access_control::grant_owner_roles(&mut self.roles, self.owner);
+                    0
+                }
+            };
+            let end = id.saturating_add(limit as u64).min(self.next_query_id);
+            let mut migrated = 0;
+            while id < end {
+                if let Some(record) = self.legacy_payments.take(&id) {
+                    // Version 1 escrows predate the per-dataset escrow pools
+                    if let Some(escrow) = self.escrows.get(&id) {
+                        let pool = self.get_escrow_pool(record.dataset_id);
+                        self.escrow_pools.insert(record.dataset_id, &(pool + escrow.amount));
+                    }
+                    self.payments.insert(id, &Payment::from(record));
+                    migrated += 1;
+                }
+                id += 1;
+            }
+            self.migration_cursor.set(&id);
+
+            if id >= self.next_query_id {
+                self.storage_version.set(&STORAGE_VERSION);
+                self.env().emit_event(StorageMigrated { version: STORAGE_VERSION });
+            }
+
+            Ok(migrated)
+        }
+
+        #[ink(message)]
+        pub fn get_storage_version(&self) -> u32 {
+            // Version 1 storage has no version cell
+            self.storage_version.get().unwrap_or(1)
+        }
+
+        pub(crate) fn supports_change(change: &ParameterChange) -> bool {
+            matches!(
+                change,
+                ParameterChange::PlatformFee(_)
+                    | ParameterChange::EscrowPeriod(_)
+                    | ParameterChange::Oracle(_)
+                    | ParameterChange::TimelockDelay(_)
+                    | ParameterChange::CodeHash(_)
+            )
+        }
+
+        fn upgrade(&mut self, code_hash: Hash) -> TimelockResult<()> {
+            self.env().set_code_hash(&code_hash).map_err(|_| TimelockError::UpgradeFailed)?;
+
+            self.env().emit_event(Upgraded { code_hash });
+
+            Ok(())
+        }
+
+        fn apply_change(&mut self, change: ParameterChange) -> TimelockResult<()> {
+            match change {
+                ParameterChange::PlatformFee(fee_bps) => self.platform_fee_bps = fee_bps,
+                ParameterChange::EscrowPeriod(period) => self.escrow_period = period,
+                ParameterChange::TimelockDelay(delay) => self.timelock_delay.set(&delay),
+                ParameterChange::CodeHash(code_hash) => self.upgrade(code_hash)?,
+                ParameterChange::Oracle(oracle) => self.oracle.set(&oracle),
+                _ => {}
+            }
+            Ok(())
+        }
+
+        fn query_price(&self, dataset: &Dataset) -> Result<Balance> {
+            match self.get_fiat_price(dataset.id) {
+                Some(fiat_price) => {
+                    let oracle = self.oracle.get().ok_or(Error::OracleNotSet)?;
+                    self.convert_fiat_price(oracle, fiat_price).ok_or(Error::PriceUnavailable)
+                }
+                None => Ok(dataset.price_per_query),
+            }
+        }
+
+        fn ensure_not_paused(&self, operation: Operation) -> Result<()> {
+            if self.is_paused(operation) {
+                return Err(Error::OperationPaused);
+            }
+            Ok(())
+        }
+
+        fn ensure_role(&self, role: Role) -> Result<()> {
+            if !self.has_role(role, self.env().caller()) {
+                return Err(Error::NotAuthorized);
+            }
+            Ok(())
+        }
+
+        /// Pay a checked private settlement's price from the pool into its
+        /// dataset's escrow pool
+        fn complete_settlement(
+            &mut self,
+            query_id: u64,
+            mut settlement: PrivateSettlement,
+            proof_hash: [u8; 32],
+        ) {
+            let now = self.env().block_timestamp();
+            let dataset_id = settlement.dataset_id;
+
+            self.private_pool.set(&(self.get_private_pool() - settlement.amount));
+            let pool = self.get_escrow_pool(dataset_id);
+            self.escrow_pools.insert(dataset_id, &(pool + settlement.amount));
+
+            settlement.status = PaymentStatus::Completed;
+            settlement.proof_hash = Some(proof_hash);
+            settlement.release_time = now + self.escrow_period;
+            self.private_settlements.insert(query_id, &settlement);
+
+            // The dataset of a private query is only known now
+            self.increment_query_count(dataset_id);
+            self.record_query_settlement(dataset_id, QuerySettlement::Completed);
+
+            self.env().emit_event(PaymentCompleted {
+                query_id,
+                proof_hash,
+            });
+        }
+
+        /// Pay a completed private settlement to the dataset owner, as
+        /// `release_escrow` does for other payments
+        fn release_settlement(&mut self, query_id: u64, mut settlement: PrivateSettlement) -> Result<()> {
+            let now = self.env().block_timestamp();
+
+            if settlement.status != PaymentStatus::Completed
+                || settlement.released
+                || now < settlement.release_time
+            {
+                return Err(Error::EscrowNotReady);
+            }
+            if !self.is_proof_final(query_id) {
+                return Err(Error::ChallengeWindowOpen);
+            }
+
+            let platform_fee = (settlement.amount * self.platform_fee_bps as u128) / 10000;
+            let owner_amount = settlement.amount - platform_fee;
+
+            self.env().transfer(settlement.dataset_owner, owner_amount)
+                .map_err(|_| Error::TransferFailed)?;
+            if platform_fee > 0 {
+                self.env().transfer(self.owner, platform_fee)
+                    .map_err(|_| Error::TransferFailed)?;
+            }
+
+            settlement.released = true;
+            self.private_settlements.insert(query_id, &settlement);
+            let pool = self.get_escrow_pool(settlement.dataset_id);
+            self.escrow_pools.insert(settlement.dataset_id, &(pool - settlement.amount));
+            self.record_query_settlement(settlement.dataset_id, QuerySettlement::Released(owner_amount));
+
+            self.env().emit_event(EscrowReleased {
+                query_id,
+                dataset_owner: settlement.dataset_owner,
+                amount: owner_amount,
+            });
+
+            Ok(())
+        }
+
+        /// Refund a private settlement, as `refund_payment` does for other
+        /// payments. A completed settlement's price returns to the private
+        /// pool, where the payment it settled can be redeemed in full or
+        /// settled again, or to the payment's user if they already redeemed it.
+        fn refund_settlement(&mut self, query_id: u64, mut settlement: PrivateSettlement) -> Result<()> {
+            let caller = self.env().caller();
+
+            let completed = match settlement.status {
+                PaymentStatus::Pending => false,
+                PaymentStatus::Completed if caller == self.zk_verifier && !settlement.released => true,
+                _ => return Err(Error::PaymentAlreadyCompleted),
+            };
+
+            settlement.status = PaymentStatus::Refunded;
+            self.private_settlements.insert(query_id, &settlement);
+
+            if completed {
+                let pool = self.get_escrow_pool(settlement.dataset_id);
+                self.escrow_pools.insert(settlement.dataset_id, &(pool - settlement.amount));
+                self.record_query_settlement(settlement.dataset_id, QuerySettlement::Refunded);
+
+                let redeemed = self
+                    .redeemed_nullifiers
+                    .get(&settlement.nullifier)
+                    .and_then(|payment_id| self.payments.get(&payment_id));
+                match redeemed {
+                    Some(payment) => {
+                        self.env().transfer(payment.user, settlement.amount)
+                            .map_err(|_| Error::TransferFailed)?;
+                    }
+                    None => self.private_pool.set(&(self.get_private_pool() + settlement.amount)),
+                }
+            }
+
+            // Refunds the verifier asks for are already on the provider's record
+            if caller != self.zk_verifier {
+                self.report_dispute_refund(settlement.dataset_owner);
+            }
+
+            self.env().emit_event(PrivateSettlementRefunded {
+                query_id,
+                amount: settlement.amount,
+            });
+
+            Ok(())
+        }
+
+        fn open_escrow(&mut self, query_id: u64, dataset_id: u64, escrow: &Escrow) {
+            self.escrows.insert(query_id, escrow);
+            let pool = self.get_escrow_pool(dataset_id);
+            self.escrow_pools.insert(dataset_id, &(pool + escrow.amount));
+        }
+
+        fn close_escrow(&mut self, query_id: u64, dataset_id: u64) {
+            if let Some(escrow) = self.escrows.take(&query_id) {
+                let pool = self.get_escrow_pool(dataset_id);
+                self.escrow_pools.insert(dataset_id, &(pool - escrow.amount));
+            }
+        }
+
+        // Cross-contract call helpers
+        #[cfg(not(test))]
+        fn is_proof_final(&self, query_id: u64) -> bool {
+            build_call::<DefaultEnvironment>()
+                .call(self.zk_verifier)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("is_proof_final")))
+                        .push_arg(query_id),
+                )
+                .returns::<bool>()
+                .invoke()
+        }
+
+        #[cfg(not(test))]
+        fn get_challenge_deadline(&self, query_id: u64) -> Option<Timestamp> {
+            build_call::<DefaultEnvironment>()
+                .call(self.zk_verifier)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("get_challenge_deadline")))
+                        .push_arg(query_id),
+                )
+                .returns::<Option<Timestamp>>()
+                .invoke()
+        }
+
+        #[cfg(not(test))]
+        fn get_dataset(&self, dataset_id: u64) -> Option<Dataset> {
+            build_call::<DefaultEnvironment>()
+                .call(self.dataset_registry)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("get_dataset")))
+                        .push_arg(dataset_id),
+                )
+                .returns::<Option<Dataset>>()
+                .invoke()
+        }
+
+        /// Best effort: usage statistics must never block a payment or refund
+        #[cfg(not(test))]
+        fn increment_query_count(&self, dataset_id: u64) {
+            use crate::dataset_registry::dataset_registry::Error as RegistryError;
+
+            let _ = build_call::<DefaultEnvironment>()
+                .call(self.dataset_registry)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("increment_query_count")))
+                        .push_arg(dataset_id),
+                )
+                .returns::<core::result::Result<(), RegistryError>>()
+                .try_invoke();
+        }
+
+        #[cfg(not(test))]
+        fn record_query_settlement(&self, dataset_id: u64, settlement: QuerySettlement) {
+            use crate::dataset_registry::dataset_registry::Error as RegistryError;
+
+            let _ = build_call::<DefaultEnvironment>()
+                .call(self.dataset_registry)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("record_query_settlement")))
+                        .push_arg(dataset_id)
+                        .push_arg(settlement),
+                )
+                .returns::<core::result::Result<(), RegistryError>>()
+                .try_invoke();
+        }
+
+        #[cfg(not(test))]
+        fn get_reputation_score(&self, account: AccountId) -> u32 {
+            build_call::<DefaultEnvironment>()
+                .call(self.zk_verifier)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("get_reputation_score")))
+                        .push_arg(account),
+                )
+                .returns::<u32>()
+                .invoke()
+        }
+
+        /// Best effort: a failing report must not block the refund
+        #[cfg(not(test))]
+        fn report_dispute_refund(&self, dataset_owner: AccountId) {
+            use crate::zk_verifier::zk_verifier::Error as VerifierError;
+
+            let _ = build_call::<DefaultEnvironment>()
+                .call(self.zk_verifier)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("report_dispute_refund")))
+                        .push_arg(dataset_owner),
+                )
+                .returns::<core::result::Result<(), VerifierError>>()
+                .try_invoke();
+        }
+
+        #[cfg(not(test))]
+        fn get_fiat_price(&self, dataset_id: u64) -> Option<FiatPrice> {
+            build_call::<DefaultEnvironment>()
+                .call(self.dataset_registry)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("get_fiat_price")))
+                        .push_arg(dataset_id),
+                )
+                .returns::<Option<FiatPrice>>()
+                .invoke()
+        }
+
+        /// `None` when the oracle has no fresh rate for the currency
+        #[cfg(not(test))]
+        fn convert_fiat_price(&self, oracle: AccountId, fiat_price: FiatPrice) -> Option<Balance> {
+            use crate::oracle_connector::oracle_connector::Error as OracleError;
+
+            build_call::<DefaultEnvironment>()
+                .call(oracle)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("convert")))
+                        .push_arg(fiat_price.currency)
+                        .push_arg(fiat_price.amount),
+                )
+                .returns::<core::result::Result<Balance, OracleError>>()
+                .invoke()
+                .ok()
+        }
+
+        #[cfg(not(test))]
+        fn is_authorized_buyer(&self, dataset_id: u64, account: AccountId) -> bool {
+            build_call::<DefaultEnvironment>()
+                .call(self.dataset_registry)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("is_authorized_buyer")))
+                        .push_arg(dataset_id)
+                        .push_arg(account),
+                )
+                .returns::<bool>()
+                .invoke()
+        }
+
+        // The off-chain test environment cannot call other contracts, so tests
+        // see every proof as final, every provider at the neutral reputation,
+        // every dataset as private to the contract owner except datasets 1 and
+        // 4, dataset 3 as deprecated unless a test sets `MOCK_DATASET_STATUS`,
+        // and dataset 4 priced at 2 USD at the rate in `MOCK_FIAT_RATE`
+        #[cfg(test)]
+        fn is_proof_final(&self, _query_id: u64) -> bool {
+            true
+        }
+
+        #[cfg(test)]
+        fn get_challenge_deadline(&self, _query_id: u64) -> Option<Timestamp> {
+            None
+        }
+
+        #[cfg(test)]
+        fn get_dataset(&self, dataset_id: u64) -> Option<Dataset> {
+            use crate::dataset_registry::dataset_registry::{
+                DatasetSchema, DatasetStatus, Modality, Quantization, Visibility,
+            };
+            use crate::vector_math::Metric;
+
+            Some(Dataset {
+                id: dataset_id,
+                owner: self.owner,
+                name: String::from("Mock Dataset"),
+                description: String::new(),
+                embedding_root: [0u8; 32],
+                metadata_hash: [0u8; 32],
+                price_per_query: 1000,
+                status: match MOCK_DATASET_STATUS.with(|status| status.get()) {
+                    Some(status) => status,
+                    None if dataset_id == 3 => DatasetStatus::Deprecated,
+                    None => DatasetStatus::Active,
+                },
+                created_at: 0,
+                total_queries: 0,
+                validator_nodes: Vec::new(),
+                allowed_verification_keys: Vec::new(),
+                visibility: Visibility::Public,
+                schema: DatasetSchema {
+                    model_id: String::from("mock-model"),
+                    dimension: 2,
+                    metric: Metric::Cosine,
+                    vector_count: 16,
+                    quantization: Quantization::Float32,
+                    modality: Modality::Text,
+                },
+                category: String::new(),
+                tags: Vec::new(),
+            })
+        }
+
+        #[cfg(test)]
+        fn get_reputation_score(&self, _account: AccountId) -> u32 {
+            crate::reputation::NEUTRAL_SCORE
+        }
+
+        #[cfg(test)]
+        fn report_dispute_refund(&self, _dataset_owner: AccountId) {}
 
-        // Helper functions (would typically be cross-contract calls)
-        fn get_dataset_price(&self, dataset_id: u64) -> Result<Balance> {
-            // This would be a cross-contract call to dataset registry
-            // For now, return a mock price
-            Ok(1000)
+        #[cfg(test)]
+        fn increment_query_count(&self, _dataset_id: u64) {}
+
+        #[cfg(test)]
+        fn record_query_settlement(&self, _dataset_id: u64, _settlement: QuerySettlement) {}
+
+        #[cfg(test)]
+        fn is_authorized_buyer(&self, dataset_id: u64, account: AccountId) -> bool {
+            dataset_id == 1 || dataset_id == 4 || account == self.owner
+        }
+
+        #[cfg(test)]
+        fn get_fiat_price(&self, dataset_id: u64) -> Option<FiatPrice> {
+            (dataset_id == 4).then(|| FiatPrice {
+                currency: String::from("USD"),
+                amount: 2_000_000,
+            })
+        }
+
+        #[cfg(test)]
+        fn convert_fiat_price(&self, _oracle: AccountId, fiat_price: FiatPrice) -> Option<Balance> {
+            use crate::oracle_connector::oracle_connector::FIAT_UNIT;
+
+            MOCK_FIAT_RATE.with(|rate| rate.get()).map(|rate| fiat_price.amount * rate / FIAT_UNIT)
+        }
+    }
+
+    impl AccessControl for PaymentManager {
+        #[ink(message)]
+        fn has_role(&self, role: Role, account: AccountId) -> bool {
+            access_control::has_role(&self.roles, role, account)
+        }
+
+        #[ink(message)]
+        fn grant_role(&mut self, role: Role, account: AccountId) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            access_control::grant_role(&mut self.roles, caller, role, account)?;
+
+            self.env().emit_event(RoleGranted {
+                account,
+                sender: caller,
+                role,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn revoke_role(&mut self, role: Role, account: AccountId) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            access_control::revoke_role(&mut self.roles, self.owner, caller, role, account)?;
+
+            self.env().emit_event(RoleRevoked {
+                account,
+                sender: caller,
+                role,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn renounce_role(&mut self, role: Role) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            self.revoke_role(role, caller)
+        }
+
+        #[ink(message)]
+        fn owner(&self) -> AccountId {
+            self.owner
+        }
+
+        #[ink(message)]
+        fn transfer_ownership(&mut self, new_owner: AccountId) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            access_control::ensure_owner(self.owner, caller)?;
+
+            self.pending_owner.set(&Some(new_owner));
+
+            self.env().emit_event(OwnershipOffered {
+                owner: caller,
+                pending_owner: new_owner,
+            });
+
+            Ok(())
         }
 
-        fn get_dataset_owner(&self, dataset_id: u64) -> Result<AccountId> {
-            // This would be a cross-contract call to dataset registry
-            // For now, return contract owner
-            Ok(self.owner)
+        #[ink(message)]
+        fn accept_ownership(&mut self) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            let previous_owner = self.owner;
+            let pending_owner = self.pending_owner.get_or_default();
+            access_control::accept_ownership(&mut self.roles, previous_owner, pending_owner, caller)?;
+
+            self.owner = caller;
+            self.pending_owner.set(&None);
+
+            self.env().emit_event(OwnershipTransferred {
+                previous_owner,
+                new_owner: caller,
+            });
+
+            Ok(())
         }
     }
 
+    impl Pausable for PaymentManager {
+        #[ink(message)]
+        fn is_paused(&self, operation: Operation) -> bool {
+            access_control::is_paused(&self.paused, operation)
+        }
+
+        #[ink(message)]
+        fn pause(&mut self, operation: Operation) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            access_control::pause(&self.roles, &mut self.paused, &PAUSABLE_OPERATIONS, caller, operation)?;
+
+            self.env().emit_event(Paused {
+                account: caller,
+                operation,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn unpause(&mut self, operation: Operation) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            access_control::unpause(&self.roles, &mut self.paused, caller, operation)?;
+
+            self.env().emit_event(Unpaused {
+                account: caller,
+                operation,
+            });
+
+            Ok(())
+        }
+    }
+
+    impl Timelock for PaymentManager {
+        #[ink(message)]
+        fn queue_change(&mut self, change: ParameterChange) -> TimelockResult<u64> {
+            let change_id = self.next_change_id.get().unwrap_or(1);
+            let supported = Self::supports_change(&change);
+            let queued = QueuedChange {
+                change,
+                queued_by: self.env().caller(),
+                eta: self.env().block_timestamp().saturating_add(self.get_timelock_delay()),
+            };
+            timelock::queue_change(&mut self.queued_changes, &self.roles, change_id, &queued, supported)?;
+            self.next_change_id.set(&(change_id + 1));
+
+            self.env().emit_event(ChangeQueued {
+                change_id,
+                change: queued.change,
+                eta: queued.eta,
+            });
+
+            Ok(change_id)
+        }
+
+        #[ink(message)]
+        fn cancel_change(&mut self, change_id: u64) -> TimelockResult<()> {
+            let caller = self.env().caller();
+            timelock::cancel_change(&mut self.queued_changes, &self.roles, change_id, caller)?;
+
+            self.env().emit_event(ChangeCancelled { change_id });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn execute_change(&mut self, change_id: u64) -> TimelockResult<()> {
+            let now = self.env().block_timestamp();
+            let change = timelock::execute_change(&mut self.queued_changes, change_id, now)?;
+            self.apply_change(change.clone())?;
+
+            self.env().emit_event(ChangeExecuted { change_id, change });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn get_queued_change(&self, change_id: u64) -> Option<QueuedChange> {
+            self.queued_changes.get(&change_id)
+        }
+
+        #[ink(message)]
+        fn get_timelock_delay(&self) -> u64 {
+            self.timelock_delay.get().unwrap_or(DEFAULT_TIMELOCK_DELAY)
+        }
+    }
+
+    // Status the mocked registry reports for every dataset, so tests can
+    // delist a dataset after payments were made, and the native units per USD
+    // the mocked oracle reports, none while its rate is stale
+    #[cfg(test)]
+    thread_local! {
+        static MOCK_DATASET_STATUS: core::cell::Cell<Option<crate::dataset_registry::dataset_registry::DatasetStatus>> =
+            core::cell::Cell::new(None);
+        static MOCK_FIAT_RATE: core::cell::Cell<Option<Balance>> = core::cell::Cell::new(Some(600));
+    }
+
     /// Unit tests
     #[cfg(test)]
     mod tests {
         use super::*;
+        use crate::access_control::AccessControlError;
+        use crate::timelock::GRACE_PERIOD;
+        use crate::dataset_registry::dataset_registry::DatasetStatus;
+        use crate::result_commitment::encode_query_opening;
+
+        /// Queue a change and execute it once the timelock delay has passed
+        fn execute_timelocked(contract: &mut PaymentManager, change: ParameterChange) {
+            let change_id = contract.queue_change(change).unwrap();
+            let now = ink::env::block_timestamp::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(now + DEFAULT_TIMELOCK_DELAY);
+            contract.execute_change(change_id).unwrap();
+        }
 
         #[ink::test]
         fn test_create_payment() {
@@ -382,8 +1779,446 @@ mod payment_manager {
             ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
             ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
 
-            let result = contract.create_payment(1);
+            let result = contract.create_payment(1, 10);
             assert!(result.is_ok());
+            assert_eq!(contract.get_payment(result.unwrap()).unwrap().top_k, 10);
+
+            assert_eq!(contract.create_payment(1, 0), Err(Error::InvalidTopK));
+            assert_eq!(contract.create_payment(1, MAX_TOP_K + 1), Err(Error::InvalidTopK));
+
+            // Charlie is not on the allowlist of a private dataset
+            assert_eq!(contract.create_payment(2, 10), Err(Error::BuyerNotAuthorized));
+
+            // Deprecated datasets take no new buyers
+            assert_eq!(contract.create_payment(3, 10), Err(Error::DatasetUnavailable));
+
+            // Charlie only accepts providers with a better than neutral record
+            assert_eq!(contract.set_min_reputation(MAX_SCORE + 1), Err(Error::InvalidReputation));
+            contract.set_min_reputation(6000).unwrap();
+            assert_eq!(contract.create_payment(1, 10), Err(Error::ProviderReputationTooLow));
+            contract.set_min_reputation(0).unwrap();
+            assert!(contract.create_payment(1, 10).is_ok());
+        }
+
+        #[ink::test]
+        fn test_release_escrow_waits_for_completion_and_escrow_period() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = PaymentManager::new(
+                accounts.alice,
+                accounts.bob,
+                250,
+                1000,
+            );
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let query_id = contract.create_payment(1, 10).unwrap();
+            assert_eq!(contract.get_release_time(query_id), Some(1000));
+
+            // Not completed yet
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(2000);
+            assert_eq!(contract.release_escrow(query_id), Err(Error::EscrowNotReady));
+
+            // Only the verifier can complete, and only the verifier can refund a completed payment
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            contract.complete_payment(query_id, 1, [7u8; 32]).unwrap();
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(contract.refund_payment(query_id), Err(Error::PaymentAlreadyCompleted));
+
+            assert_eq!(contract.get_escrow_pool(1), 1000);
+            assert!(contract.release_escrow(query_id).is_ok());
+            assert!(contract.get_escrow(query_id).is_none());
+            assert_eq!(contract.get_escrow_pool(1), 0);
+        }
+
+        #[ink::test]
+        fn test_archive_settled_payment() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = PaymentManager::new(
+                accounts.alice,
+                accounts.bob,
+                250,
+                1000,
+            );
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let query_id = contract.create_payment(1, 10).unwrap();
+            assert_eq!(contract.archive_payment(query_id), Err(Error::PaymentNotSettled));
+
+            // Completed but the escrow is still held
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            contract.complete_payment(query_id, 1, [7u8; 32]).unwrap();
+            assert_eq!(contract.archive_payment(query_id), Err(Error::PaymentNotSettled));
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1000);
+            contract.release_escrow(query_id).unwrap();
+            contract.archive_payment(query_id).unwrap();
+
+            assert!(contract.get_payment(query_id).is_none());
+            let receipt = contract.get_payment_receipt(query_id).unwrap();
+            assert_eq!(receipt.status, PaymentStatus::Completed);
+            assert_eq!(receipt.proof_hash, Some([7u8; 32]));
+            assert_eq!(contract.archive_payment(query_id), Err(Error::PaymentAlreadyArchived));
+        }
+
+        #[ink::test]
+        fn test_private_payment_settles_without_naming_user() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = PaymentManager::new(
+                accounts.alice,
+                accounts.bob,
+                250,
+                1000,
+            );
+            let opening = encode_query_opening(1, 10, [3u8; 32], [4u8; 32]);
+            let nullifier = opening_nullifier(&opening).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1500);
+            let query_id = contract.create_private_payment(query_commitment(&opening)).unwrap();
+            assert_eq!(contract.open_private_settlement(nullifier, 1, 10), Err(Error::NotAuthorized));
+
+            // The verifier settles the nullifier its proof revealed
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(contract.open_private_settlement(nullifier, 1, 0), Err(Error::InvalidTopK));
+            let settlement_id = contract.open_private_settlement(nullifier, 1, 10).unwrap();
+            assert_eq!(contract.open_private_settlement(nullifier, 1, 10), Err(Error::PriceAlreadyFixed));
+            assert_eq!(contract.complete_payment(query_id, 1, [7u8; 32]), Err(Error::DatasetMismatch));
+            assert_eq!(contract.complete_payment(settlement_id, 2, [7u8; 32]), Err(Error::DatasetMismatch));
+            contract.complete_payment(settlement_id, 1, [7u8; 32]).unwrap();
+
+            // The price moves to the dataset while the payment never learns it
+            let settlement = contract.get_private_settlement(settlement_id).unwrap();
+            assert_eq!(settlement.dataset_id, 1);
+            assert_eq!(settlement.top_k, 10);
+            assert_eq!(settlement.amount, 1000);
+            let payment = contract.get_payment(query_id).unwrap();
+            assert_eq!(payment.dataset_id, 0);
+            assert_eq!(payment.status, PaymentStatus::Pending);
+            assert!(contract.get_escrow(query_id).is_none());
+            assert_eq!(contract.get_escrow_pool(1), 1000);
+            assert_eq!(contract.get_private_pool(), 500);
+
+            // Only the user holding the opening can take the change back
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            assert_eq!(contract.reclaim_payment(query_id), Err(Error::InvalidOpening));
+            let wrong = encode_query_opening(1, 10, [3u8; 32], [5u8; 32]);
+            assert_eq!(contract.redeem_private_payment(query_id, wrong), Err(Error::InvalidOpening));
+            contract.redeem_private_payment(query_id, opening.clone()).unwrap();
+            assert_eq!(contract.get_payment(query_id).unwrap().status, PaymentStatus::Completed);
+            assert_eq!(contract.get_private_pool(), 0);
+            assert_eq!(contract.redeem_private_payment(query_id, opening), Err(Error::PaymentAlreadyCompleted));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(contract.open_private_settlement(nullifier, 1, 10), Err(Error::NullifierSpent));
+            assert_eq!(contract.release_escrow(settlement_id), Err(Error::EscrowNotReady));
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1000);
+            contract.release_escrow(settlement_id).unwrap();
+            assert_eq!(contract.release_escrow(settlement_id), Err(Error::EscrowNotReady));
+            assert_eq!(contract.get_escrow_pool(1), 0);
+        }
+
+        #[ink::test]
+        fn test_refunded_settlement_returns_to_pool() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = PaymentManager::new(
+                accounts.alice,
+                accounts.bob,
+                250,
+                1000,
+            );
+            let opening = encode_query_opening(1, 10, [3u8; 32], [4u8; 32]);
+            let nullifier = opening_nullifier(&opening).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1500);
+            let query_id = contract.create_private_payment(query_commitment(&opening)).unwrap();
+
+            // A successful challenge puts the price back in the pool
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            let settlement_id = contract.open_private_settlement(nullifier, 1, 10).unwrap();
+            contract.complete_payment(settlement_id, 1, [7u8; 32]).unwrap();
+            contract.refund_payment(settlement_id).unwrap();
+            assert_eq!(contract.get_query_status(settlement_id), Some(PaymentStatus::Refunded));
+            assert_eq!(contract.get_escrow_pool(1), 0);
+            assert_eq!(contract.get_private_pool(), 1500);
+
+            // so the payment can be settled again
+            let retry = contract.open_private_settlement(nullifier, 1, 10).unwrap();
+            assert_ne!(retry, settlement_id);
+
+            // or, once the escrow period is over, redeemed in full while its
+            // proof waits, which leaves nothing for that proof to settle
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            assert_eq!(contract.redeem_private_payment(query_id, opening.clone()), Err(Error::EscrowNotReady));
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1000);
+            contract.redeem_private_payment(query_id, opening).unwrap();
+            assert_eq!(contract.get_payment(query_id).unwrap().status, PaymentStatus::Refunded);
+            assert_eq!(contract.get_query_status(retry), Some(PaymentStatus::Refunded));
+            assert_eq!(contract.get_private_pool(), 0);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(contract.complete_payment(retry, 1, [7u8; 32]), Err(Error::PaymentAlreadyCompleted));
+            assert_eq!(contract.open_private_settlement(nullifier, 1, 10), Err(Error::NullifierSpent));
+        }
+
+        #[ink::test]
+        fn test_aggregated_completion_is_all_or_nothing() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = PaymentManager::new(
+                accounts.alice,
+                accounts.bob,
+                250,
+                1000,
+            );
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1500);
+            let first = contract.create_payment(1, 10).unwrap();
+            contract.create_private_payment([5u8; 32]).unwrap();
+            let completed = contract.create_payment(1, 10).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            let private = contract.open_private_settlement([6u8; 32], 1, 10).unwrap();
+            contract.complete_payment(completed, 1, [7u8; 32]).unwrap();
+
+            // A later query that cannot complete leaves the earlier ones pending
+            let queries = vec![first, private, completed];
+            assert_eq!(contract.complete_payments(queries, 1, [8u8; 32]), Err(Error::PaymentAlreadyCompleted));
+            let queries = vec![first, first];
+            assert_eq!(contract.complete_payments(queries, 1, [8u8; 32]), Err(Error::PaymentAlreadyCompleted));
+            assert_eq!(contract.get_query_status(first), Some(PaymentStatus::Pending));
+            assert_eq!(contract.get_query_status(private), Some(PaymentStatus::Pending));
+            assert_eq!(contract.get_private_pool(), 1500);
+
+            contract.complete_payments(vec![first, private], 1, [8u8; 32]).unwrap();
+            assert_eq!(contract.get_query_status(first), Some(PaymentStatus::Completed));
+            assert_eq!(contract.get_query_status(private), Some(PaymentStatus::Completed));
+            assert_eq!(contract.get_private_pool(), 500);
+        }
+
+        #[ink::test]
+        fn test_migrate_v1_payments() {
+            use ink::storage::traits::StorageKey;
+
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+
+            // Storage as left behind by version 1 code: the root holds only
+            // its contract addresses, next_query_id, owner, fee and escrow period
+            let root_key = <PaymentManager as StorageKey>::KEY;
+            let root = (accounts.alice, accounts.bob, 3u64, accounts.alice, 250u16, 1000u64);
+            ink::env::set_contract_storage(&root_key, &root);
+            let escrows_key = KeyComposer::from_str("PaymentManager::escrows");
+            for (query_id, amount, status) in [(1u64, 1000u128, 0u8), (2, 500, 1)] {
+                // status 0 is Pending, 1 is Completed
+                let payment = (query_id, 1u64, accounts.charlie, amount, 0u64, status, None::<[u8; 32]>);
+                ink::env::set_contract_storage(&(PAYMENTS_V1_KEY, query_id), &payment);
+                let escrow = (accounts.charlie, accounts.django, amount, 0u64, 1000u64);
+                ink::env::set_contract_storage(&(escrows_key, query_id), &escrow);
+            }
+
+            let mut contract: PaymentManager = ink::env::get_contract_storage(&root_key).unwrap().unwrap();
+            assert_eq!(contract.get_storage_version(), 1);
+            assert_eq!(contract.get_platform_fee(), 250);
+            assert_eq!(contract.get_private_pool(), 0);
+            assert_eq!(contract.get_oracle(), None);
+            assert!(contract.get_payment(1).is_none());
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(contract.migrate(10), Err(Error::NotAuthorized));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(contract.migrate(10), Ok(2));
+            assert_eq!(contract.get_storage_version(), 2);
+            assert_eq!(contract.migrate(10), Err(Error::AlreadyMigrated));
+            assert!(contract.has_role(Role::Admin, accounts.alice));
+
+            let payment = contract.get_payment(1).unwrap();
+            assert_eq!(payment.status, PaymentStatus::Pending);
+            assert_eq!(payment.top_k, MAX_TOP_K);
+            assert_eq!(contract.get_payment(2).unwrap().status, PaymentStatus::Completed);
+            assert_eq!(contract.get_escrow(2).unwrap().dataset_owner, accounts.django);
+            assert_eq!(contract.get_escrow_pool(1), 1500);
+        }
+
+        #[ink::test]
+        fn test_reclaim_payment_for_delisted_dataset() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = PaymentManager::new(
+                accounts.alice,
+                accounts.bob,
+                250,
+                1000,
+            );
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let pending = contract.create_payment(1, 10).unwrap();
+            let delivered = contract.create_payment(1, 10).unwrap();
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            contract.complete_payment(delivered, 1, [7u8; 32]).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            assert_eq!(contract.reclaim_payment(pending), Err(Error::EscrowNotReady));
+
+            // The query will never be proven, so the user need not wait
+            MOCK_DATASET_STATUS.with(|status| status.set(Some(DatasetStatus::Delisted)));
+            contract.reclaim_payment(pending).unwrap();
+            assert_eq!(contract.get_payment(pending).unwrap().status, PaymentStatus::Refunded);
+            assert_eq!(contract.reclaim_payment(delivered), Err(Error::PaymentAlreadyCompleted));
+
+            // Delivered queries still pay the owner
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1000);
+            contract.release_escrow(delivered).unwrap();
+            assert_eq!(contract.get_escrow_pool(1), 0);
+        }
+
+        #[ink::test]
+        fn test_users_can_exit_while_paused() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = PaymentManager::new(
+                accounts.alice,
+                accounts.bob,
+                250,
+                1000,
+            );
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let abandoned = contract.create_payment(1, 10).unwrap();
+            let delisted = contract.create_payment(1, 10).unwrap();
+            let opening = encode_query_opening(1, 10, [3u8; 32], [4u8; 32]);
+            let private = contract.create_private_payment(query_commitment(&opening)).unwrap();
+            assert_eq!(contract.pause(Operation::Payments), Err(AccessControlError::MissingRole));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            contract.pause(Operation::Payments).unwrap();
+            contract.pause(Operation::Payouts).unwrap();
+            assert_eq!(contract.pause(Operation::Payouts), Err(AccessControlError::AlreadyPaused));
+            assert_eq!(contract.pause(Operation::Registration), Err(AccessControlError::NotPausable));
+
+            // No new funds come in and nothing is paid out to providers
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            assert_eq!(contract.create_payment(1, 10), Err(Error::OperationPaused));
+            assert_eq!(contract.create_private_payment([6u8; 32]), Err(Error::OperationPaused));
+            assert_eq!(contract.release_escrow(abandoned), Err(Error::OperationPaused));
+
+            // Users withdraw payments for a delisted dataset at once
+            assert_eq!(contract.reclaim_payment(delisted), Err(Error::EscrowNotReady));
+            MOCK_DATASET_STATUS.with(|status| status.set(Some(DatasetStatus::Delisted)));
+            contract.reclaim_payment(delisted).unwrap();
+            assert!(contract.get_escrow(delisted).is_none());
+            MOCK_DATASET_STATUS.with(|status| status.set(None));
+
+            // and other undelivered payments once the escrow period is over
+            assert_eq!(contract.reclaim_payment(abandoned), Err(Error::EscrowNotReady));
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1000);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            assert_eq!(contract.reclaim_payment(abandoned), Err(Error::NotAuthorized));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            contract.reclaim_payment(abandoned).unwrap();
+            contract.redeem_private_payment(private, opening.clone()).unwrap();
+            assert!(contract.get_escrow(abandoned).is_none());
+            assert_eq!(contract.get_private_pool(), 0);
+            assert_eq!(contract.redeem_private_payment(private, opening), Err(Error::PaymentAlreadyCompleted));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            contract.unpause(Operation::Payments).unwrap();
+            assert_eq!(contract.unpause(Operation::Payments), Err(AccessControlError::NotPaused));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            assert!(contract.create_payment(1, 10).is_ok());
+        }
+
+        #[ink::test]
+        fn test_parameter_changes_are_timelocked() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = PaymentManager::new(
+                accounts.alice,
+                accounts.bob,
+                250,
+                1000,
+            );
+
+            assert_eq!(
+                contract.queue_change(ParameterChange::PlatformFee(10_001)),
+                Err(TimelockError::ValueOutOfRange)
+            );
+            assert_eq!(
+                contract.queue_change(ParameterChange::RegistrationFee(5)),
+                Err(TimelockError::UnsupportedChange)
+            );
+            let raise = contract.queue_change(ParameterChange::PlatformFee(10_000)).unwrap();
+            let lower = contract.queue_change(ParameterChange::PlatformFee(500)).unwrap();
+            assert_eq!(contract.get_queued_change(lower).unwrap().eta, DEFAULT_TIMELOCK_DELAY);
+
+            // Nothing changes before the delay, and a bad change can be dropped
+            assert_eq!(contract.execute_change(lower), Err(TimelockError::ChangeNotReady));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            assert_eq!(contract.cancel_change(raise), Err(TimelockError::MissingRole));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            contract.cancel_change(raise).unwrap();
+            assert_eq!(contract.get_platform_fee(), 250);
+
+            // Anyone can apply a change once it is due
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(DEFAULT_TIMELOCK_DELAY);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            assert_eq!(contract.execute_change(raise), Err(TimelockError::ChangeNotFound));
+            contract.execute_change(lower).unwrap();
+            assert_eq!(contract.get_platform_fee(), 500);
+
+            // Changes left waiting too long expire
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            let stale = contract.queue_change(ParameterChange::EscrowPeriod(2000)).unwrap();
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(
+                2 * DEFAULT_TIMELOCK_DELAY + GRACE_PERIOD + 1,
+            );
+            assert_eq!(contract.execute_change(stale), Err(TimelockError::ChangeExpired));
+            assert_eq!(contract.get_escrow_period(), 1000);
+        }
+
+        #[ink::test]
+        fn test_fiat_priced_payment() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = PaymentManager::new(
+                accounts.alice,
+                accounts.bob,
+                250,
+                1000,
+            );
+            assert_eq!(contract.quote_price(4), Err(Error::OracleNotSet));
+            execute_timelocked(&mut contract, ParameterChange::Oracle(accounts.django));
+            assert_eq!(contract.quote_price(1), Ok(1000));
+            assert_eq!(contract.quote_price(4), Ok(1200));
+
+            // The payer never pays more than they sent
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            assert_eq!(contract.create_payment(4, 10), Err(Error::InsufficientPayment));
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1300);
+            let query_id = contract.create_payment(4, 10).unwrap();
+            assert_eq!(contract.get_payment(query_id).unwrap().amount, 1200);
+            assert_eq!(contract.get_escrow(query_id).unwrap().amount, 1200);
+
+            // Private payments are converted when their proof is submitted
+            contract.create_private_payment([5u8; 32]).unwrap();
+            assert_eq!(contract.open_private_settlement([6u8; 32], 4, 10), Err(Error::NotAuthorized));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            let fixed = contract.open_private_settlement([6u8; 32], 4, 10).unwrap();
+            assert_eq!(contract.get_private_settlement(fixed).unwrap().amount, 1200);
+
+            // A later submission cannot re-price it at a lower rate
+            MOCK_FIAT_RATE.with(|rate| rate.set(Some(500)));
+            assert_eq!(contract.open_private_settlement([6u8; 32], 4, 10), Err(Error::PriceAlreadyFixed));
+            assert_eq!(contract.open_private_settlement([6u8; 32], 1, 10), Err(Error::PriceAlreadyFixed));
+
+            // and a rate that goes stale before verification leaves it alone
+            MOCK_FIAT_RATE.with(|rate| rate.set(None));
+            assert_eq!(contract.open_private_settlement([7u8; 32], 4, 10), Err(Error::PriceUnavailable));
+            contract.complete_payment(fixed, 4, [7u8; 32]).unwrap();
+            assert_eq!(contract.get_escrow_pool(4), 2400);
+            assert_eq!(contract.get_private_pool(), 100);
+            MOCK_FIAT_RATE.with(|rate| rate.set(Some(600)));
         }
     }
 }
\ No newline at end of file
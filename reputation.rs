@@ -0,0 +1,140 @@
+//! Time-decayed reputation of dataset owners and provers.
+//!
+//! Every outcome adds weight to a positive or a negative tally. Both tallies
+//! decay so recent behaviour counts more than old behaviour: time is split
+//! into `DECAY_PERIOD` buckets and the tallies halve at every bucket boundary,
+//! so an outcome weighs half as much in the next period, a quarter in the one
+//! after, and so on. Halving composes, so the result does not depend on how
+//! often a record is updated. The score is the positive share of the weight in
+//! basis points, smoothed by a neutral prior so an account without history
+//! scores `NEUTRAL_SCORE`.
+
+use ink::storage::traits::{SpreadLayout, PackedLayout, StorageLayout};
+
+/// Score of a flawless record, in basis points
+pub const MAX_SCORE: u32 = 10_000;
+/// Score of an account without history
+pub const NEUTRAL_SCORE: u32 = MAX_SCORE / 2;
+/// Length of a decay bucket, in milliseconds (30 days)
+pub const DECAY_PERIOD: u64 = 30 * 24 * 60 * 60 * 1000;
+/// Weight of one unit, large enough to keep precision while decaying
+const UNIT_WEIGHT: u128 = 1_000_000;
+/// Weight of the neutral prior, split evenly between both tallies
+const PRIOR_WEIGHT: u128 = 2 * UNIT_WEIGHT;
+
+/// Outcome that moves a reputation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+pub enum Outcome {
+    ProofVerified,
+    ProofRejected,
+    ChallengeLost,
+    DisputeRefunded,
+}
+
+impl Outcome {
+    /// Positive and negative weight, in units. Misbehaviour weighs more than
+    /// a single good proof so it cannot be cheaply diluted.
+    fn weights(self) -> (u128, u128) {
+        match self {
+            Outcome::ProofVerified => (1, 0),
+            Outcome::ProofRejected => (0, 3),
+            Outcome::ChallengeLost => (0, 5),
+            Outcome::DisputeRefunded => (0, 2),
+        }
+    }
+}
+
+/// Track record of one account
+#[derive(Debug, Clone, Default, PartialEq, Eq, SpreadLayout, PackedLayout)]
+#[cfg_attr(feature = "std", derive(StorageLayout))]
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+pub struct ReputationRecord {
+    pub positive_weight: u128, // Decayed as of updated_at
+    pub negative_weight: u128, // Decayed as of updated_at
+    pub updated_at: u64,
+    pub verified_proofs: u32,
+    pub rejected_proofs: u32,
+    pub challenges_lost: u32,
+    pub disputes_refunded: u32,
+}
+
+impl ReputationRecord {
+    /// Add an outcome observed at `now`
+    pub fn record(&mut self, outcome: Outcome, now: u64) {
+        let (positive, negative) = self.decayed_weights(now);
+        let (positive_units, negative_units) = outcome.weights();
+
+        self.positive_weight = positive.saturating_add(positive_units * UNIT_WEIGHT);
+        self.negative_weight = negative.saturating_add(negative_units * UNIT_WEIGHT);
+        self.updated_at = now;
+
+        match outcome {
+            Outcome::ProofVerified => self.verified_proofs += 1,
+            Outcome::ProofRejected => self.rejected_proofs += 1,
+            Outcome::ChallengeLost => self.challenges_lost += 1,
+            Outcome::DisputeRefunded => self.disputes_refunded += 1,
+        }
+    }
+
+    /// Score at `now`, between 0 and `MAX_SCORE`
+    pub fn score(&self, now: u64) -> u32 {
+        let (positive, negative) = self.decayed_weights(now);
+        let total = positive.saturating_add(negative).saturating_add(PRIOR_WEIGHT);
+        (positive.saturating_add(PRIOR_WEIGHT / 2) * MAX_SCORE as u128 / total) as u32
+    }
+
+    fn decayed_weights(&self, now: u64) -> (u128, u128) {
+        let halvings = (now / DECAY_PERIOD).saturating_sub(self.updated_at / DECAY_PERIOD);
+        let halvings = u32::try_from(halvings).unwrap_or(u32::MAX);
+        let decay = |weight: u128| weight.checked_shr(halvings).unwrap_or(0);
+        (decay(self.positive_weight), decay(self.negative_weight))
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_follows_outcomes_and_decays() {
+        let mut record = ReputationRecord::default();
+        assert_eq!(record.score(0), NEUTRAL_SCORE);
+
+        record.record(Outcome::ProofVerified, 0);
+        assert_eq!(record.score(0), 6666);
+
+        record.record(Outcome::ProofRejected, 0);
+        assert_eq!(record.score(0), 3333);
+        assert_eq!(record.verified_proofs, 1);
+        assert_eq!(record.rejected_proofs, 1);
+
+        // Old history fades back towards neutral
+        assert_eq!(record.score(DECAY_PERIOD - 1), 3333);
+        assert_eq!(record.score(DECAY_PERIOD), 3750);
+        assert_eq!(record.score(1000 * DECAY_PERIOD), NEUTRAL_SCORE);
+    }
+
+    #[test]
+    fn test_decay_does_not_depend_on_update_frequency() {
+        // Frequent outcomes within one period lose nothing to decay
+        let mut frequent = ReputationRecord::default();
+        for now in 0..10 {
+            frequent.record(Outcome::ProofVerified, now);
+        }
+        assert_eq!(frequent.positive_weight, 10 * UNIT_WEIGHT);
+
+        // Updating halfway through decays exactly like not updating at all
+        let mut updated = ReputationRecord::default();
+        updated.record(Outcome::ProofRejected, 0);
+        updated.record(Outcome::ProofVerified, 3 * DECAY_PERIOD / 2);
+        let mut untouched = ReputationRecord::default();
+        untouched.record(Outcome::ProofRejected, 0);
+        assert_eq!(
+            updated.decayed_weights(3 * DECAY_PERIOD).1,
+            untouched.decayed_weights(3 * DECAY_PERIOD).1,
+        );
+        assert_eq!(untouched.decayed_weights(3 * DECAY_PERIOD).1, 3 * UNIT_WEIGHT / 8);
+    }
+}
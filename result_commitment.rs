@@ -0,0 +1,202 @@
+//! Top-k result commitment carried in a proof's public inputs.
+//!
+//! A vector search answer is a ranked list of dataset entries. Provers put the
+//! SCALE encoding of a `ResultCommitment` in `ZKProof::public_inputs`, and the
+//! verifier decodes it with the same code to check it against the payment.
+//! Off-chain tools should use `encode`/`decode` from this module rather than
+//! building the bytes by hand.
+
+use ink::prelude::vec::Vec;
+use ink::scale::{DecodeAll, Encode};
+
+use crate::vector_math::Metric;
+
+/// Largest number of results a query can purchase
+pub const MAX_TOP_K: u32 = 100;
+
+/// Length in bytes of an encoded query opening
+pub const QUERY_OPENING_LEN: usize = 76;
+
+/// Domain separator of the nullifier that settles a private payment
+const PAYMENT_NULLIFIER_DOMAIN: &[u8] = b"PaymentManager/nullifier";
+
+/// Preimage of a private payment's query commitment:
+/// `dataset_id (u64 LE) || top_k (u32 LE) || query_hash || salt`.
+/// The payment stores `query_commitment` of these bytes. The proof that
+/// answers it names neither the payment nor the commitment: its
+/// `ResultCommitment` carries the dataset ID, k and query hash with the
+/// payment's nullifier (see `opening_nullifier`), and the circuit shows they
+/// come from the opening of one of the pool's commitments without saying which.
+pub fn encode_query_opening(
+    dataset_id: u64,
+    top_k: u32,
+    query_hash: [u8; 32],
+    salt: [u8; 32],
+) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(QUERY_OPENING_LEN);
+    bytes.extend_from_slice(&dataset_id.to_le_bytes());
+    bytes.extend_from_slice(&top_k.to_le_bytes());
+    bytes.extend_from_slice(&query_hash);
+    bytes.extend_from_slice(&salt);
+    bytes
+}
+
+/// Commitment a private payment stores: keccak256 of its opening
+pub fn query_commitment(opening: &[u8]) -> [u8; 32] {
+    keccak256(opening)
+}
+
+/// Nullifier a proof reveals to settle the private payment with this
+/// opening: `keccak256("PaymentManager/nullifier" || salt)`. `None` if the
+/// opening is malformed.
+pub fn opening_nullifier(opening: &[u8]) -> Option<[u8; 32]> {
+    if opening.len() != QUERY_OPENING_LEN {
+        return None;
+    }
+    let mut input = PAYMENT_NULLIFIER_DOMAIN.to_vec();
+    input.extend_from_slice(&opening[QUERY_OPENING_LEN - 32..]);
+    Some(keccak256(&input))
+}
+
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    use ink::env::hash::{HashOutput, Keccak256};
+
+    let mut output = <Keccak256 as HashOutput>::Type::default();
+    ink::env::hash_bytes::<Keccak256>(data, &mut output);
+    output
+}
+
+/// Ranked answer to one query
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+pub struct ResultCommitment {
+    pub query_id: u64,                       // Payment the answer is for, 0 for a private one
+    pub query_hash: [u8; 32],                // keccak256 of the encoded query vector
+    pub k: u32,                              // Number of results, as purchased
+    pub metric: Metric,
+    pub dataset_id: u64,                     // Dataset the search ran against
+    pub leaf_indices: Vec<u64>,              // Results in rank order, as embedding tree leaves
+    pub scores: Vec<i64>,                    // Q16.16 score of each result
+    pub dataset_version: [u8; 32],           // Embedding root the search ran against
+    pub payment_nullifier: Option<[u8; 32]>, // Settles a private payment, see `opening_nullifier`
+}
+
+/// Errors
+#[derive(Debug, PartialEq, Eq)]
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+pub enum CommitmentError {
+    Malformed,
+    InvalidK,
+    LengthMismatch,
+    UnorderedScores,
+    DuplicateResult,
+}
+
+impl ResultCommitment {
+    /// Bytes to use as a proof's public inputs
+    pub fn encode(&self) -> Vec<u8> {
+        Encode::encode(self)
+    }
+
+    /// Decode and validate public inputs. Trailing bytes are rejected so a
+    /// commitment has exactly one encoding.
+    pub fn decode(bytes: &[u8]) -> Result<Self, CommitmentError> {
+        let commitment = <Self as DecodeAll>::decode_all(&mut &bytes[..])
+            .map_err(|_| CommitmentError::Malformed)?;
+        commitment.validate()?;
+        Ok(commitment)
+    }
+
+    /// Check the list is a well-formed ranking of `k` distinct results
+    pub fn validate(&self) -> Result<(), CommitmentError> {
+        if self.k == 0 || self.k > MAX_TOP_K {
+            return Err(CommitmentError::InvalidK);
+        }
+        if self.leaf_indices.len() != self.k as usize || self.scores.len() != self.k as usize {
+            return Err(CommitmentError::LengthMismatch);
+        }
+
+        // Best match first: highest similarity, or smallest distance
+        let ordered = self.scores.windows(2).all(|pair| match self.metric {
+            Metric::Cosine => pair[0] >= pair[1],
+            Metric::L2 => pair[0] <= pair[1],
+        });
+        if !ordered {
+            return Err(CommitmentError::UnorderedScores);
+        }
+
+        for (i, leaf) in self.leaf_indices.iter().enumerate() {
+            if self.leaf_indices[..i].contains(leaf) {
+                return Err(CommitmentError::DuplicateResult);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Leaf index and score of the result at `rank` (0 is the best match)
+    pub fn result(&self, rank: u32) -> Option<(u64, i64)> {
+        let rank = rank as usize;
+        Some((*self.leaf_indices.get(rank)?, *self.scores.get(rank)?))
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commitment() -> ResultCommitment {
+        ResultCommitment {
+            query_id: 1,
+            query_hash: [1u8; 32],
+            k: 3,
+            metric: Metric::Cosine,
+            dataset_id: 1,
+            leaf_indices: ink::prelude::vec![7, 2, 9],
+            scores: ink::prelude::vec![60000, 50000, 50000],
+            dataset_version: [2u8; 32],
+            payment_nullifier: None,
+        }
+    }
+
+    #[test]
+    fn test_encode_decode_roundtrip() {
+        let bytes = commitment().encode();
+        assert_eq!(ResultCommitment::decode(&bytes), Ok(commitment()));
+        assert_eq!(commitment().result(1), Some((2, 50000)));
+        assert_eq!(commitment().result(3), None);
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(ResultCommitment::decode(&trailing), Err(CommitmentError::Malformed));
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut unordered = commitment();
+        unordered.metric = Metric::L2;
+        assert_eq!(unordered.validate(), Err(CommitmentError::UnorderedScores));
+
+        let mut short = commitment();
+        short.k = 4;
+        assert_eq!(short.validate(), Err(CommitmentError::LengthMismatch));
+
+        let mut duplicate = commitment();
+        duplicate.leaf_indices[2] = 7;
+        assert_eq!(duplicate.validate(), Err(CommitmentError::DuplicateResult));
+    }
+
+    #[test]
+    fn test_opening_nullifier_depends_only_on_salt() {
+        let opening = encode_query_opening(1, 3, [1u8; 32], [4u8; 32]);
+        let other_query = encode_query_opening(2, 5, [6u8; 32], [4u8; 32]);
+        let other_salt = encode_query_opening(1, 3, [1u8; 32], [5u8; 32]);
+
+        assert_eq!(opening.len(), QUERY_OPENING_LEN);
+        assert_eq!(opening_nullifier(&opening), opening_nullifier(&other_query));
+        assert_ne!(opening_nullifier(&opening), opening_nullifier(&other_salt));
+        assert_ne!(opening_nullifier(&opening), Some(query_commitment(&opening)));
+        assert_eq!(opening_nullifier(&opening[1..]), None);
+    }
+}
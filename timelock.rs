@@ -0,0 +1,266 @@
+//! Delayed parameter changes shared by the marketplace contracts.
+//!
+//! Contract parameters, the contracts a contract calls and its code are never
+//! set directly. A change is queued by an account holding the role the
+//! parameter needs, can be cancelled while it waits, and only takes effect
+//! once its ETA has passed, so users see fee, period, address and code changes
+//! coming and can leave before they apply. A change that is not
+//! executed within `GRACE_PERIOD` after its ETA expires.
+
+use ink::primitives::{AccountId, Hash};
+use ink::prelude::string::String;
+use ink::storage::Mapping;
+use ink::storage::traits::{SpreadLayout, PackedLayout, StorageLayout};
+
+use crate::access_control::{self, Role, Roles};
+use crate::zk_verifier::zk_verifier::PayloadLimits;
+use crate::oracle_connector::oracle_connector::{MAX_REPORTERS, MIN_REPORTS};
+
+/// Delay of a newly deployed contract, in milliseconds (2 days)
+pub const DEFAULT_TIMELOCK_DELAY: u64 = 2 * 24 * 60 * 60 * 1000;
+/// Shortest delay that can be configured, in milliseconds (1 day)
+pub const MIN_TIMELOCK_DELAY: u64 = 24 * 60 * 60 * 1000;
+/// Longest delay that can be configured, in milliseconds (30 days)
+pub const MAX_TIMELOCK_DELAY: u64 = 30 * 24 * 60 * 60 * 1000;
+/// Time after its ETA a change can still be executed, in milliseconds (14 days)
+pub const GRACE_PERIOD: u64 = 14 * 24 * 60 * 60 * 1000;
+/// Largest fee in basis points (100%)
+pub const MAX_FEE_BPS: u16 = 10_000;
+/// Longest escrow or resolution period, in milliseconds (90 days)
+pub const MAX_PERIOD: u64 = 90 * 24 * 60 * 60 * 1000;
+/// Largest payload limit, in bytes
+pub const MAX_PAYLOAD_SIZE: u32 = 1 << 20;
+
+/// New value for one contract parameter
+#[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
+#[cfg_attr(feature = "std", derive(StorageLayout))]
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+pub enum ParameterChange {
+    RegistrationFee(u128),                      // DatasetRegistry
+    PlatformFee(u16),                           // PaymentManager, basis points
+    EscrowPeriod(u64),                          // PaymentManager
+    ChallengeStake(u128),                       // ZKVerifier
+    ResolutionPeriod(u64),                      // ZKVerifier
+    StorageDepositPerByte(u128),                // ZKVerifier
+    PayloadLimits(String, PayloadLimits),       // ZKVerifier, per circuit type
+    TimelockDelay(u64),                         // Any contract
+    CodeHash(Hash),                             // Any contract, replaces its code
+    PaymentManager(AccountId),                  // DatasetRegistry
+    Oracle(AccountId),                          // PaymentManager
+    AllowCircuitType(String, PayloadLimits),    // ZKVerifier, allowlists a circuit type
+    Delist(u64),                                // DatasetRegistry, by dataset ID
+    AddReporter(AccountId),                     // OracleConnector
+    RemoveReporter(AccountId),                  // OracleConnector, clears its reports
+    MinReports(u32),                            // OracleConnector
+    MaxStaleness(u64),                          // OracleConnector
+}
+
+impl ParameterChange {
+    /// Role needed to queue or cancel the change
+    pub fn role(&self) -> Role {
+        match self {
+            ParameterChange::RegistrationFee(_)
+            | ParameterChange::PlatformFee(_)
+            | ParameterChange::ChallengeStake(_)
+            | ParameterChange::StorageDepositPerByte(_) => Role::FeeManager,
+            ParameterChange::EscrowPeriod(_)
+            | ParameterChange::ResolutionPeriod(_)
+            | ParameterChange::PayloadLimits(_, _)
+            | ParameterChange::TimelockDelay(_)
+            | ParameterChange::CodeHash(_)
+            | ParameterChange::PaymentManager(_)
+            | ParameterChange::Oracle(_)
+            | ParameterChange::AllowCircuitType(_, _)
+            | ParameterChange::AddReporter(_)
+            | ParameterChange::RemoveReporter(_)
+            | ParameterChange::MinReports(_)
+            | ParameterChange::MaxStaleness(_) => Role::Admin,
+            ParameterChange::Delist(_) => Role::Arbiter,
+        }
+    }
+
+    /// Check the new value is within bounds
+    pub fn validate(&self) -> TimelockResult<()> {
+        let valid = match self {
+            ParameterChange::PlatformFee(fee_bps) => *fee_bps <= MAX_FEE_BPS,
+            ParameterChange::EscrowPeriod(period) => *period <= MAX_PERIOD,
+            ParameterChange::ResolutionPeriod(period)
+            | ParameterChange::MaxStaleness(period) => *period > 0 && *period <= MAX_PERIOD,
+            ParameterChange::PayloadLimits(_, limits)
+            | ParameterChange::AllowCircuitType(_, limits) => [
+                limits.max_proof_size,
+                limits.max_public_inputs_size,
+                limits.max_key_size,
+            ]
+            .iter()
+            .all(|size| *size > 0 && *size <= MAX_PAYLOAD_SIZE),
+            ParameterChange::TimelockDelay(delay) => {
+                *delay >= MIN_TIMELOCK_DELAY && *delay <= MAX_TIMELOCK_DELAY
+            }
+            ParameterChange::MinReports(min_reports) => {
+                *min_reports >= MIN_REPORTS && *min_reports as usize <= MAX_REPORTERS
+            }
+            ParameterChange::RegistrationFee(_)
+            | ParameterChange::ChallengeStake(_)
+            | ParameterChange::StorageDepositPerByte(_)
+            | ParameterChange::CodeHash(_)
+            | ParameterChange::PaymentManager(_)
+            | ParameterChange::Oracle(_)
+            | ParameterChange::Delist(_)
+            | ParameterChange::AddReporter(_)
+            | ParameterChange::RemoveReporter(_) => true,
+        };
+        if !valid {
+            return Err(TimelockError::ValueOutOfRange);
+        }
+        Ok(())
+    }
+}
+
+/// Change waiting for its ETA
+#[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
+#[cfg_attr(feature = "std", derive(StorageLayout))]
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+pub struct QueuedChange {
+    pub change: ParameterChange,
+    pub queued_by: AccountId,
+    pub eta: u64, // Earliest execution time
+}
+
+/// Errors
+#[derive(Debug, PartialEq, Eq)]
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+pub enum TimelockError {
+    MissingRole,
+    UnsupportedChange,
+    ValueOutOfRange,
+    ChangeNotFound,
+    ChangeNotReady,
+    ChangeExpired,
+    UpgradeFailed,
+    ChangeNotApplicable,
+}
+
+pub type TimelockResult<T> = core::result::Result<T, TimelockError>;
+
+/// Store a change under `change_id`. Whoever queues it needs the change's
+/// role, and the contract must `support` the parameter.
+pub fn queue_change(
+    queued_changes: &mut Mapping<u64, QueuedChange>,
+    roles: &Roles,
+    change_id: u64,
+    queued: &QueuedChange,
+    supported: bool,
+) -> TimelockResult<()> {
+    if !access_control::has_role(roles, queued.change.role(), queued.queued_by) {
+        return Err(TimelockError::MissingRole);
+    }
+    if !supported {
+        return Err(TimelockError::UnsupportedChange);
+    }
+    queued.change.validate()?;
+    queued_changes.insert(change_id, queued);
+    Ok(())
+}
+
+/// Drop a queued change on behalf of `sender`, who needs the change's role or Admin
+pub fn cancel_change(
+    queued_changes: &mut Mapping<u64, QueuedChange>,
+    roles: &Roles,
+    change_id: u64,
+    sender: AccountId,
+) -> TimelockResult<()> {
+    let queued = queued_changes.get(change_id).ok_or(TimelockError::ChangeNotFound)?;
+    if !access_control::has_role(roles, queued.change.role(), sender)
+        && !access_control::has_role(roles, Role::Admin, sender)
+    {
+        return Err(TimelockError::MissingRole);
+    }
+    queued_changes.remove(change_id);
+    Ok(())
+}
+
+/// Take a change out of the queue once its ETA has passed, for the contract to apply
+pub fn execute_change(
+    queued_changes: &mut Mapping<u64, QueuedChange>,
+    change_id: u64,
+    now: u64,
+) -> TimelockResult<ParameterChange> {
+    let queued = queued_changes.get(change_id).ok_or(TimelockError::ChangeNotFound)?;
+    if now < queued.eta {
+        return Err(TimelockError::ChangeNotReady);
+    }
+    if now > queued.eta.saturating_add(GRACE_PERIOD) {
+        return Err(TimelockError::ChangeExpired);
+    }
+    queued_changes.remove(change_id);
+    Ok(queued.change)
+}
+
+/// Parameter change messages every marketplace contract exposes
+#[ink::trait_definition]
+pub trait Timelock {
+    /// Queue a parameter change, executable after the timelock delay
+    /// (holder of the change's role only). Returns the change ID.
+    #[ink(message)]
+    fn queue_change(&mut self, change: ParameterChange) -> TimelockResult<u64>;
+
+    /// Drop a queued change (holder of the change's role or Admin)
+    #[ink(message)]
+    fn cancel_change(&mut self, change_id: u64) -> TimelockResult<()>;
+
+    /// Apply a queued change once its ETA has passed. Anyone can call this.
+    /// A `CodeHash` change replaces the contract code, keeping its storage;
+    /// call `migrate` afterwards if the new code changes the storage layout.
+    #[ink(message)]
+    fn execute_change(&mut self, change_id: u64) -> TimelockResult<()>;
+
+    /// Get a queued change
+    #[ink(message)]
+    fn get_queued_change(&self, change_id: u64) -> Option<QueuedChange>;
+
+    /// Get the delay between queueing and executing a change
+    #[ink(message)]
+    fn get_timelock_delay(&self) -> u64;
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_bounds() {
+        assert_eq!(ParameterChange::PlatformFee(MAX_FEE_BPS).validate(), Ok(()));
+        assert_eq!(
+            ParameterChange::PlatformFee(MAX_FEE_BPS + 1).validate(),
+            Err(TimelockError::ValueOutOfRange)
+        );
+        assert_eq!(
+            ParameterChange::ResolutionPeriod(0).validate(),
+            Err(TimelockError::ValueOutOfRange)
+        );
+        assert_eq!(
+            ParameterChange::TimelockDelay(MIN_TIMELOCK_DELAY - 1).validate(),
+            Err(TimelockError::ValueOutOfRange)
+        );
+
+        let limits = PayloadLimits {
+            max_proof_size: 1024,
+            max_public_inputs_size: 0,
+            max_key_size: 1024,
+        };
+        assert_eq!(
+            ParameterChange::PayloadLimits(String::from("groth16"), limits).validate(),
+            Err(TimelockError::ValueOutOfRange)
+        );
+        assert_eq!(
+            ParameterChange::MinReports(MIN_REPORTS - 1).validate(),
+            Err(TimelockError::ValueOutOfRange)
+        );
+        assert_eq!(ParameterChange::MaxStaleness(0).validate(), Err(TimelockError::ValueOutOfRange));
+        assert_eq!(ParameterChange::PlatformFee(250).role(), Role::FeeManager);
+        assert_eq!(ParameterChange::EscrowPeriod(0).role(), Role::Admin);
+        assert_eq!(ParameterChange::Delist(1).role(), Role::Arbiter);
+    }
+}
@@ -0,0 +1,169 @@
+//! Fixed-point vector math for on-chain similarity checks.
+//!
+//! Vector components are Q16.16 fixed-point numbers stored as `i32`
+//! (`65536` is `1.0`). Scores are returned in the same format, so a cosine
+//! similarity of `1.0` is `SCALE` and an L2 distance of `0.5` is `SCALE / 2`.
+//! All intermediate values fit in 128-bit integers for any dimension up to
+//! `MAX_DIMENSION`, so no operation can overflow.
+
+use ink::prelude::vec::Vec;
+use ink::storage::traits::{SpreadLayout, PackedLayout, StorageLayout};
+
+/// Number of fractional bits in a fixed-point value
+pub const FRACTIONAL_BITS: u32 = 16;
+/// Fixed-point representation of 1.0
+pub const SCALE: i64 = 1 << FRACTIONAL_BITS;
+/// Largest vector dimension accepted on-chain
+pub const MAX_DIMENSION: usize = 1024;
+
+/// Distance metric used to score a search result
+#[derive(Debug, Clone, Copy, PartialEq, Eq, SpreadLayout, PackedLayout)]
+#[cfg_attr(feature = "std", derive(StorageLayout))]
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+pub enum Metric {
+    Cosine,
+    L2,
+}
+
+/// Errors
+#[derive(Debug, PartialEq, Eq)]
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+pub enum MathError {
+    EmptyVector,
+    DimensionMismatch,
+    DimensionTooLarge,
+    ZeroVector,
+}
+
+pub type MathResult<T> = core::result::Result<T, MathError>;
+
+/// Score `result` against `query` with the given metric
+pub fn similarity(metric: Metric, query: &[i32], result: &[i32]) -> MathResult<i64> {
+    match metric {
+        Metric::Cosine => cosine_similarity(query, result),
+        Metric::L2 => l2_distance(query, result),
+    }
+}
+
+/// Dot product of two vectors, in Q32.32
+pub fn dot(a: &[i32], b: &[i32]) -> MathResult<i128> {
+    check_dimensions(a, b)?;
+    Ok(a.iter()
+        .zip(b)
+        .map(|(x, y)| *x as i128 * *y as i128)
+        .sum())
+}
+
+/// Cosine similarity in Q16.16, between `-SCALE` and `SCALE`
+pub fn cosine_similarity(a: &[i32], b: &[i32]) -> MathResult<i64> {
+    let dot = dot(a, b)?;
+    let norm_a = isqrt(squared_norm(a));
+    let norm_b = isqrt(squared_norm(b));
+
+    if norm_a == 0 || norm_b == 0 {
+        return Err(MathError::ZeroVector);
+    }
+
+    // Both norms are Q16.16, so their product is Q32.32 like the dot product
+    let denominator = (norm_a * norm_b) as i128;
+    Ok((dot * SCALE as i128 / denominator) as i64)
+}
+
+/// Euclidean distance in Q16.16
+pub fn l2_distance(a: &[i32], b: &[i32]) -> MathResult<i64> {
+    check_dimensions(a, b)?;
+    let squared: u128 = a.iter()
+        .zip(b)
+        .map(|(x, y)| {
+            let diff = (*x as i64 - *y as i64).unsigned_abs() as u128;
+            diff * diff
+        })
+        .sum();
+    Ok(isqrt(squared) as i64)
+}
+
+/// Canonical byte encoding of a vector: each component as little-endian `i32`
+pub fn encode_vector(vector: &[i32]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(vector.len() * 4);
+    for component in vector {
+        bytes.extend_from_slice(&component.to_le_bytes());
+    }
+    bytes
+}
+
+fn check_dimensions(a: &[i32], b: &[i32]) -> MathResult<()> {
+    if a.is_empty() || b.is_empty() {
+        return Err(MathError::EmptyVector);
+    }
+    if a.len() != b.len() {
+        return Err(MathError::DimensionMismatch);
+    }
+    if a.len() > MAX_DIMENSION {
+        return Err(MathError::DimensionTooLarge);
+    }
+    Ok(())
+}
+
+fn squared_norm(v: &[i32]) -> u128 {
+    v.iter()
+        .map(|x| {
+            let x = x.unsigned_abs() as u128;
+            x * x
+        })
+        .sum()
+}
+
+/// Integer square root (floor)
+fn isqrt(n: u128) -> u128 {
+    if n < 2 {
+        return n;
+    }
+    // Newton's method from an initial guess above the root
+    let mut x = 1u128 << ((128 - n.leading_zeros()) / 2 + 1);
+    loop {
+        let y = (x + n / x) / 2;
+        if y >= x {
+            return x;
+        }
+        x = y;
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cosine_similarity() {
+        let a = [SCALE as i32, 0];
+        let b = [SCALE as i32, SCALE as i32];
+
+        assert_eq!(cosine_similarity(&a, &a), Ok(SCALE));
+        assert_eq!(cosine_similarity(&a, &[-(SCALE as i32), 0]), Ok(-SCALE));
+        // cos(45°) ≈ 0.7071 → 46341 in Q16.16
+        assert_eq!(cosine_similarity(&a, &b), Ok(46341));
+        assert_eq!(cosine_similarity(&a, &[0, 0]), Err(MathError::ZeroVector));
+    }
+
+    #[test]
+    fn test_l2_distance() {
+        let a = [0, 0];
+        let b = [3 * SCALE as i32, 4 * SCALE as i32];
+
+        assert_eq!(l2_distance(&a, &b), Ok(5 * SCALE));
+        assert_eq!(l2_distance(&a, &[0]), Err(MathError::DimensionMismatch));
+        assert_eq!(l2_distance(&[], &[]), Err(MathError::EmptyVector));
+
+        let long = ink::prelude::vec![1; MAX_DIMENSION + 1];
+        assert_eq!(l2_distance(&long, &long), Err(MathError::DimensionTooLarge));
+    }
+
+    #[test]
+    fn test_isqrt() {
+        assert_eq!(isqrt(0), 0);
+        assert_eq!(isqrt(15), 3);
+        assert_eq!(isqrt(16), 4);
+        assert_eq!(isqrt(u128::MAX), u64::MAX as u128);
+    }
+}
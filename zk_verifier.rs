@@ -1,11 +1,65 @@
 #![cfg_attr(not(feature = "std"), no_std, no_main)]
 
 #[ink::contract]
-mod zk_verifier {
-    use ink::storage::Mapping;
-    use ink::storage::traits::{SpreadLayout, PackedLayout, StorageLayout};
+pub mod zk_verifier {
+    use ink::storage::{Lazy, Mapping};
+    use ink::storage::traits::{ManualKey, SpreadLayout, PackedLayout, StorageLayout};
+    use ink::primitives::KeyComposer;
     use ink::prelude::vec::Vec;
     use ink::prelude::string::String;
+    use ink::env::call::{build_call, ExecutionInput, Selector};
+    use ink::env::DefaultEnvironment;
+    use crate::dataset_registry::dataset_registry::Dataset;
+    use crate::payment_manager::payment_manager::{Error as PaymentError, Payment, PaymentStatus};
+    use crate::vector_math;
+    use crate::result_commitment::ResultCommitment;
+    use crate::reputation::{Outcome, ReputationRecord};
+    use crate::timelock::{
+        self, ParameterChange, QueuedChange, Timelock, TimelockError, TimelockResult,
+        DEFAULT_TIMELOCK_DELAY,
+    };
+    use crate::access_control::{
+        self, owner_roles, AccessControl, AccessControlResult, Operation, Pausable, Role,
+    };
+
+    /// Operations a Pauser can stop in this contract
+    const PAUSABLE_OPERATIONS: [Operation; 2] = [Operation::ProofSubmission, Operation::Verification];
+
+    /// Storage layout version written by this code
+    const STORAGE_VERSION: u32 = 2;
+    /// Key storage version 1 kept proofs under
+    const PROOFS_V1_KEY: u32 = KeyComposer::from_str("ZKVerifier::proofs");
+    /// Key proofs are kept under since storage version 2
+    const PROOFS_KEY: u32 = KeyComposer::from_str("ZKVerifier::proofs_v2");
+    /// Keys of the root fields added since storage version 1. Each lives in a
+    /// cell of its own so a version 1 root, which lacks them, still decodes.
+    const STORAGE_VERSION_KEY: u32 = KeyComposer::from_str("ZKVerifier::storage_version");
+    const MIGRATION_CURSOR_KEY: u32 = KeyComposer::from_str("ZKVerifier::migration_cursor");
+    const PENDING_OWNER_KEY: u32 = KeyComposer::from_str("ZKVerifier::pending_owner");
+    const TIMELOCK_DELAY_KEY: u32 = KeyComposer::from_str("ZKVerifier::timelock_delay");
+    const NEXT_CHANGE_ID_KEY: u32 = KeyComposer::from_str("ZKVerifier::next_change_id");
+    const RESOLUTION_PERIOD_KEY: u32 = KeyComposer::from_str("ZKVerifier::resolution_period");
+    const STORAGE_DEPOSIT_PER_BYTE_KEY: u32 = KeyComposer::from_str("ZKVerifier::storage_deposit_per_byte");
+
+    /// Maximum number of proofs accepted by a single verify_proofs call
+    pub const MAX_BATCH_SIZE: usize = 64;
+    /// Maximum number of queries a single aggregated proof can cover
+    pub const MAX_AGGREGATION_SIZE: usize = 128;
+    /// Allowed difference between a claimed and a recomputed score (Q16.16),
+    /// absorbing rounding in the prover's own fixed-point arithmetic
+    pub const SCORE_TOLERANCE: i64 = 16;
+    /// Deepest embedding Merkle tree a fraud proof can reference
+    const MAX_MERKLE_DEPTH: usize = 64;
+    /// Domain separator for proof nullifiers
+    const NULLIFIER_DOMAIN: &[u8] = b"ZKVerifier/nullifier";
+    /// Circuit types accepted without a governance change
+    const BUILTIN_CIRCUIT_TYPES: [&str; 1] = ["halo2"];
+    /// Size limits for circuit types without their own configuration
+    pub const DEFAULT_PAYLOAD_LIMITS: PayloadLimits = PayloadLimits {
+        max_proof_size: 16 * 1024,
+        max_public_inputs_size: 4 * 1024,
+        max_key_size: 64 * 1024,
+    };
     
     /// ZK Proof structure
     #[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
@@ -20,8 +74,53 @@ mod zk_verifier {
         pub public_inputs: Vec<u8>, // Public inputs for verification
         pub verification_key_hash: [u8; 32],
         pub created_at: Timestamp,
+        pub verified_at: Option<Timestamp>, // Start of the challenge window
         pub status: ProofStatus,
         pub challenge_hash: [u8; 32],
+        pub storage_deposit: Balance, // Refunded to the prover when the proof is pruned
+    }
+
+    /// Proof as stored by storage version 1
+    #[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct ZKProofV1 {
+        pub proof_id: u64,
+        pub query_id: u64,
+        pub dataset_id: u64,
+        pub prover: AccountId,
+        pub proof_data: Vec<u8>,
+        pub public_inputs: Vec<u8>,
+        pub verification_key_hash: [u8; 32],
+        pub created_at: Timestamp,
+        pub status: ProofStatus,
+        pub challenge_hash: [u8; 32],
+    }
+
+    impl From<ZKProofV1> for ZKProof {
+        /// Version 1 had no challenge window and took no deposits: verified
+        /// proofs count as verified when submitted and refund nothing when
+        /// pruned.
+        fn from(proof: ZKProofV1) -> Self {
+            let verified_at = match proof.status {
+                ProofStatus::Verified => Some(proof.created_at),
+                _ => None,
+            };
+            Self {
+                proof_id: proof.proof_id,
+                query_id: proof.query_id,
+                dataset_id: proof.dataset_id,
+                prover: proof.prover,
+                proof_data: proof.proof_data,
+                public_inputs: proof.public_inputs,
+                verification_key_hash: proof.verification_key_hash,
+                created_at: proof.created_at,
+                verified_at,
+                status: proof.status,
+                challenge_hash: proof.challenge_hash,
+                storage_deposit: 0,
+            }
+        }
     }
 
     /// Proof status
@@ -35,6 +134,17 @@ mod zk_verifier {
         Challenged,
     }
 
+    /// Queries settled by one aggregated proof. The proof itself is stored as
+    /// a regular `ZKProof` whose public inputs are `inputs_commitment`.
+    #[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct ProofAggregation {
+        pub proof_id: u64,
+        pub query_ids: Vec<u64>,
+        pub inputs_commitment: [u8; 32], // Commitment to every query's public inputs
+    }
+
     /// Verification key information
     #[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
     #[cfg_attr(feature = "std", derive(StorageLayout))]
@@ -47,6 +157,29 @@ mod zk_verifier {
         pub is_active: bool,
     }
 
+    /// What remains of a proof once it is archived
+    #[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct ProofReceipt {
+        pub proof_id: u64,
+        pub dataset_id: u64,
+        pub prover: AccountId,
+        pub proof_hash: [u8; 32], // Hash the payment manager recorded on completion
+        pub status: ProofStatus,  // Verified or Rejected
+        pub verified_at: Option<Timestamp>,
+    }
+
+    /// Largest payloads, in bytes, accepted for one circuit type
+    #[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(StorageLayout))]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct PayloadLimits {
+        pub max_proof_size: u32,
+        pub max_public_inputs_size: u32, // Per query for aggregated proofs
+        pub max_key_size: u32,
+    }
+
     /// Challenge information
     #[derive(Debug, Clone, PartialEq, Eq, SpreadLayout, PackedLayout)]
     #[cfg_attr(feature = "std", derive(StorageLayout))]
@@ -70,6 +203,7 @@ mod zk_verifier {
         Active,
         Resolved,
         Dismissed,
+        Expired, // Not resolved before its deadline, stake returned
     }
 
     /// ZK proof verifier contract
@@ -80,9 +214,17 @@ mod zk_verifier {
         /// Dataset registry contract address
         dataset_registry: AccountId,
         /// Mapping from proof ID to proof data
-        proofs: Mapping<u64, ZKProof>,
-        /// Mapping from query ID to proof ID
+        proofs: Mapping<u64, ZKProof, ManualKey<PROOFS_KEY>>,
+        /// Proofs not yet migrated from storage version 1
+        legacy_proofs: Mapping<u64, ZKProofV1, ManualKey<PROOFS_V1_KEY>>,
+        /// Mapping from query ID to proof ID (aggregated proofs included)
         query_proofs: Mapping<u64, u64>,
+        /// Mapping from aggregated proof ID to the queries it covers
+        aggregations: Mapping<u64, ProofAggregation>,
+        /// Receipts of archived proofs
+        proof_receipts: Mapping<u64, ProofReceipt>,
+        /// Spent nullifiers and the proof that used each one
+        nullifiers: Mapping<[u8; 32], u64>,
         /// Verification keys storage
         verification_keys: Mapping<[u8; 32], VerificationKey>,
         /// Challenges storage
@@ -95,12 +237,43 @@ mod zk_verifier {
         next_challenge_id: u64,
         /// Contract owner
         owner: AccountId,
+        /// Account ownership was offered to
+        pending_owner: Lazy<Option<AccountId>, ManualKey<PENDING_OWNER_KEY>>,
+        /// Role assignments
+        roles: Mapping<(Role, AccountId), bool>,
+        /// Operations stopped by a Pauser
+        paused: Mapping<Operation, bool>,
+        /// Storage layout version, behind `STORAGE_VERSION` until `migrate`
+        /// finishes; unset in version 1 storage
+        storage_version: Lazy<u32, ManualKey<STORAGE_VERSION_KEY>>,
+        /// Next ID `migrate` looks at, unset until it first runs
+        migration_cursor: Lazy<u64, ManualKey<MIGRATION_CURSOR_KEY>>,
+        /// Time between queueing and executing a parameter change,
+        /// `DEFAULT_TIMELOCK_DELAY` until changed
+        timelock_delay: Lazy<u64, ManualKey<TIMELOCK_DELAY_KEY>>,
+        /// Parameter changes waiting for their ETA
+        queued_changes: Mapping<u64, QueuedChange>,
+        /// Next parameter change ID, 1 until a change is queued
+        next_change_id: Lazy<u64, ManualKey<NEXT_CHANGE_ID_KEY>>,
         /// Minimum stake for challenges
         min_challenge_stake: Balance,
         /// Challenge period in milliseconds
         challenge_period: u64,
+        /// Time the owner has to resolve a challenge, in milliseconds; the
+        /// challenge period until changed
+        resolution_period: Lazy<u64, ManualKey<RESOLUTION_PERIOD_KEY>>,
         /// Authorized validators
         validators: Mapping<AccountId, bool>,
+        /// Track record of every prover and dataset owner
+        reputations: Mapping<AccountId, ReputationRecord>,
+        /// Payload size limits per circuit type
+        payload_limits: Mapping<String, PayloadLimits>,
+        /// Circuit types allowlisted through a timelocked change, on top of the
+        /// built-in ones. The allowlist only decides which keys and proofs are
+        /// accepted; every type goes through the same `verify_proof_internal`.
+        allowed_circuit_types: Mapping<String, bool>,
+        /// Deposit charged per byte of proof data kept in storage, none until set
+        storage_deposit_per_byte: Lazy<Balance, ManualKey<STORAGE_DEPOSIT_PER_BYTE_KEY>>,
     }
 
     /// Events
@@ -115,6 +288,27 @@ mod zk_verifier {
         dataset_id: u64,
     }
 
+    #[ink(event)]
+    pub struct PrivateProofSubmitted {
+        #[ink(topic)]
+        proof_id: u64,
+        #[ink(topic)]
+        query_id: u64,
+        #[ink(topic)]
+        prover: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct AggregatedProofSubmitted {
+        #[ink(topic)]
+        proof_id: u64,
+        #[ink(topic)]
+        prover: AccountId,
+        dataset_id: u64,
+        query_ids: Vec<u64>,
+        inputs_commitment: [u8; 32],
+    }
+
     #[ink(event)]
     pub struct ProofVerified {
         #[ink(topic)]
@@ -124,6 +318,14 @@ mod zk_verifier {
         verifier: AccountId,
     }
 
+    #[ink(event)]
+    pub struct ProofsBatchVerified {
+        #[ink(topic)]
+        verifier: AccountId,
+        proof_count: u32,
+        failed_batches: u32,
+    }
+
     #[ink(event)]
     pub struct ProofRejected {
         #[ink(topic)]
@@ -131,6 +333,14 @@ mod zk_verifier {
         reason: String,
     }
 
+    #[ink(event)]
+    pub struct ReputationUpdated {
+        #[ink(topic)]
+        account: AccountId,
+        outcome: Outcome,
+        score: u32,
+    }
+
     #[ink(event)]
     pub struct ProofChallenged {
         #[ink(topic)]
@@ -142,6 +352,44 @@ mod zk_verifier {
         stake: Balance,
     }
 
+    #[ink(event)]
+    pub struct FraudProofEvaluated {
+        #[ink(topic)]
+        challenge_id: u64,
+        #[ink(topic)]
+        proof_id: u64,
+        claimed_score: i64,
+        computed_score: i64,
+        accepted: bool,
+    }
+
+    #[ink(event)]
+    pub struct ChallengeExpired {
+        #[ink(topic)]
+        challenge_id: u64,
+        #[ink(topic)]
+        proof_id: u64,
+        challenger: AccountId,
+        stake: Balance,
+    }
+
+    #[ink(event)]
+    pub struct ProofArchived {
+        #[ink(topic)]
+        proof_id: u64,
+        #[ink(topic)]
+        prover: AccountId,
+        #[ink(topic)]
+        dataset_id: u64,
+        query_ids: Vec<u64>,
+        proof_hash: [u8; 32],
+        status: ProofStatus,
+        created_at: Timestamp,
+        verified_at: Option<Timestamp>,
+        challenge_ids: Vec<u64>,
+        deposit_refunded: Balance,
+    }
+
     #[ink(event)]
     pub struct VerificationKeyRegistered {
         #[ink(topic)]
@@ -151,6 +399,104 @@ mod zk_verifier {
         circuit_type: String,
     }
 
+    #[ink(event)]
+    pub struct VerificationKeyDeactivated {
+        #[ink(topic)]
+        key_hash: [u8; 32],
+        #[ink(topic)]
+        owner: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct VerificationKeyRotated {
+        #[ink(topic)]
+        old_key_hash: [u8; 32],
+        #[ink(topic)]
+        new_key_hash: [u8; 32],
+        #[ink(topic)]
+        owner: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct RoleGranted {
+        #[ink(topic)]
+        account: AccountId,
+        #[ink(topic)]
+        sender: AccountId,
+        role: Role,
+    }
+
+    #[ink(event)]
+    pub struct RoleRevoked {
+        #[ink(topic)]
+        account: AccountId,
+        #[ink(topic)]
+        sender: AccountId,
+        role: Role,
+    }
+
+    #[ink(event)]
+    pub struct OwnershipOffered {
+        #[ink(topic)]
+        owner: AccountId,
+        #[ink(topic)]
+        pending_owner: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct OwnershipTransferred {
+        #[ink(topic)]
+        previous_owner: AccountId,
+        #[ink(topic)]
+        new_owner: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct ChangeQueued {
+        #[ink(topic)]
+        change_id: u64,
+        change: ParameterChange,
+        eta: Timestamp,
+    }
+
+    #[ink(event)]
+    pub struct ChangeCancelled {
+        #[ink(topic)]
+        change_id: u64,
+    }
+
+    #[ink(event)]
+    pub struct ChangeExecuted {
+        #[ink(topic)]
+        change_id: u64,
+        change: ParameterChange,
+    }
+
+    #[ink(event)]
+    pub struct Upgraded {
+        #[ink(topic)]
+        code_hash: Hash,
+    }
+
+    #[ink(event)]
+    pub struct StorageMigrated {
+        version: u32,
+    }
+
+    #[ink(event)]
+    pub struct Paused {
+        #[ink(topic)]
+        account: AccountId,
+        operation: Operation,
+    }
+
+    #[ink(event)]
+    pub struct Unpaused {
+        #[ink(topic)]
+        account: AccountId,
+        operation: Operation,
+    }
+
     /// Errors
     #[derive(Debug, PartialEq, Eq)]
     #[ink::scale_derive(Encode, Decode, TypeInfo)]
@@ -165,6 +511,38 @@ mod zk_verifier {
         ChallengePeriodExpired,
         InvalidChallenge,
         TransferFailed,
+        PaymentNotFound,
+        PaymentNotPending,
+        DatasetNotFound,
+        DatasetMismatch,
+        PaymentUpdateFailed,
+        VerificationKeyAlreadyExists,
+        VerificationKeyInactive,
+        VerificationKeyNotAllowed,
+        ResolutionDeadlinePassed,
+        ChallengeNotExpired,
+        InvalidBatch,
+        InvalidAggregation,
+        InvalidFraudProof,
+        InvalidResultCommitment,
+        ResultCountMismatch,
+        DatasetVersionMismatch,
+        QueryCommitmentMismatch,
+        NullifierAlreadyUsed,
+        ProofTooLarge,
+        PublicInputsTooLarge,
+        VerificationKeyTooLarge,
+        InsufficientDeposit,
+        ProofNotFinal,
+        ProofAlreadyPruned,
+        MetricMismatch,
+        ResultOutOfRange,
+        DimensionMismatch,
+        DatasetUnavailable,
+        OperationPaused,
+        UpgradeFailed,
+        AlreadyMigrated,
+        UnsupportedCircuitType,
     }
 
     pub type Result<T> = core::result::Result<T, Error>;
@@ -178,21 +556,41 @@ mod zk_verifier {
             min_challenge_stake: Balance,
             challenge_period: u64,
         ) -> Self {
-            Self {
+            let owner = Self::env().caller();
+            let mut instance = Self {
                 payment_manager,
                 dataset_registry,
                 proofs: Mapping::default(),
+                legacy_proofs: Mapping::default(),
                 query_proofs: Mapping::default(),
+                aggregations: Mapping::default(),
+                proof_receipts: Mapping::default(),
+                nullifiers: Mapping::default(),
                 verification_keys: Mapping::default(),
                 challenges: Mapping::default(),
                 proof_challenges: Mapping::default(),
                 next_proof_id: 1,
                 next_challenge_id: 1,
-                owner: Self::env().caller(),
+                owner,
+                pending_owner: Lazy::new(),
+                roles: owner_roles(owner),
+                paused: Mapping::default(),
+                storage_version: Lazy::new(),
+                migration_cursor: Lazy::new(),
+                timelock_delay: Lazy::new(),
+                queued_changes: Mapping::default(),
+                next_change_id: Lazy::new(),
                 min_challenge_stake,
                 challenge_period,
+                resolution_period: Lazy::new(),
                 validators: Mapping::default(),
-            }
+                reputations: Mapping::default(),
+                payload_limits: Mapping::default(),
+                allowed_circuit_types: Mapping::default(),
+                storage_deposit_per_byte: Lazy::new(),
+            };
+            instance.storage_version.set(&STORAGE_VERSION);
+            instance
         }
 
         /// Register verification key
@@ -203,31 +601,76 @@ mod zk_verifier {
             circuit_type: String,
         ) -> Result<[u8; 32]> {
             let caller = self.env().caller();
-            
-            // Calculate key hash
-            let key_hash = self.hash_data(&key_data);
-            
-            let vk = VerificationKey {
-                key_hash,
-                key_data,
-                circuit_type: circuit_type.clone(),
-                owner: caller,
-                is_active: true,
-            };
+            self.insert_verification_key(caller, key_data, circuit_type)
+        }
+
+        /// Deactivate verification key (key owner only)
+        #[ink(message)]
+        pub fn deactivate_verification_key(&mut self, key_hash: [u8; 32]) -> Result<()> {
+            let caller = self.env().caller();
+            let mut vk = self.verification_keys.get(&key_hash)
+                .ok_or(Error::VerificationKeyNotFound)?;
+
+            if vk.owner != caller {
+                return Err(Error::NotAuthorized);
+            }
+
+            if !vk.is_active {
+                return Err(Error::VerificationKeyInactive);
+            }
 
+            vk.is_active = false;
             self.verification_keys.insert(key_hash, &vk);
 
-            self.env().emit_event(VerificationKeyRegistered {
+            self.env().emit_event(VerificationKeyDeactivated {
                 key_hash,
                 owner: caller,
-                circuit_type,
             });
 
-            Ok(key_hash)
+            Ok(())
         }
 
-        /// Submit ZK proof
+        /// Replace an active verification key with a new one for the same circuit.
+        /// Datasets must allow the new key hash before proofs can use it.
         #[ink(message)]
+        pub fn rotate_verification_key(
+            &mut self,
+            old_key_hash: [u8; 32],
+            new_key_data: Vec<u8>,
+        ) -> Result<[u8; 32]> {
+            let caller = self.env().caller();
+            let mut old_vk = self.verification_keys.get(&old_key_hash)
+                .ok_or(Error::VerificationKeyNotFound)?;
+
+            if old_vk.owner != caller {
+                return Err(Error::NotAuthorized);
+            }
+
+            if !old_vk.is_active {
+                return Err(Error::VerificationKeyInactive);
+            }
+
+            let new_key_hash = self.insert_verification_key(
+                caller,
+                new_key_data,
+                old_vk.circuit_type.clone(),
+            )?;
+
+            old_vk.is_active = false;
+            self.verification_keys.insert(old_key_hash, &old_vk);
+
+            self.env().emit_event(VerificationKeyRotated {
+                old_key_hash,
+                new_key_hash,
+                owner: caller,
+            });
+
+            Ok(new_key_hash)
+        }
+
+        /// Submit ZK proof. The transferred value is kept as a storage deposit
+        /// and must cover `get_required_deposit` for the stored bytes.
+        #[ink(message, payable)]
         pub fn submit_proof(
             &mut self,
             query_id: u64,
@@ -237,20 +680,23 @@ mod zk_verifier {
             verification_key_hash: [u8; 32],
             challenge_hash: [u8; 32],
         ) -> Result<u64> {
+            self.ensure_not_paused(Operation::ProofSubmission)?;
+
             let caller = self.env().caller();
             let now = self.env().block_timestamp();
+            let storage_deposit = self.env().transferred_value();
 
-            // Check if verification key exists
-            if !self.verification_keys.contains(&verification_key_hash) {
-                return Err(Error::VerificationKeyNotFound);
-            }
-
-            // Check if proof already exists for this query
-            if self.query_proofs.contains(&query_id) {
-                return Err(Error::ProofAlreadyVerified);
-            }
+            let vk = self.check_verification_key(verification_key_hash)?;
+            let limits = self.get_payload_limits(vk.circuit_type);
+            Self::check_payload_size(&limits, &proof_data, &public_inputs)?;
+            self.check_storage_deposit(proof_data.len() + public_inputs.len(), storage_deposit)?;
+            let nullifier = self.check_nullifier(&public_inputs)?;
+            let payment = self.check_query(query_id, dataset_id)?;
+            let dataset = self.check_prover(caller, dataset_id, verification_key_hash)?;
+            self.check_result_commitment(&public_inputs, &payment, &dataset)?;
 
             let proof_id = self.next_proof_id;
+            self.nullifiers.insert(nullifier, &proof_id);
 
             let proof = ZKProof {
                 proof_id,
@@ -261,8 +707,10 @@ mod zk_verifier {
                 public_inputs,
                 verification_key_hash,
                 created_at: now,
+                verified_at: None,
                 status: ProofStatus::Pending,
                 challenge_hash,
+                storage_deposit,
             };
 
             self.proofs.insert(proof_id, &proof);
@@ -279,55 +727,296 @@ mod zk_verifier {
             Ok(proof_id)
         }
 
-        /// Verify ZK proof (called by authorized validators)
-        #[ink(message)]
-        pub fn verify_proof(&mut self, proof_id: u64) -> Result<()> {
+        /// Submit a proof answering a private payment. Its public inputs name
+        /// the payment's nullifier instead of its query (see
+        /// `result_commitment::encode_query_opening`), and the payment manager
+        /// opens a settlement for the nullifier under a query ID of its own,
+        /// which the proof is stored under. Nothing on-chain ties the
+        /// settlement to the payment or its user. The storage deposit works as
+        /// for `submit_proof`.
+        #[ink(message, payable)]
+        pub fn submit_private_proof(
+            &mut self,
+            dataset_id: u64,
+            proof_data: Vec<u8>,
+            public_inputs: Vec<u8>,
+            verification_key_hash: [u8; 32],
+            challenge_hash: [u8; 32],
+        ) -> Result<u64> {
+            self.ensure_not_paused(Operation::ProofSubmission)?;
+
             let caller = self.env().caller();
-            
-            // Check if caller is authorized validator
-            if !self.validators.get(&caller).unwrap_or(false) && caller != self.owner {
-                return Err(Error::NotAuthorized);
-            }
+            let now = self.env().block_timestamp();
+            let storage_deposit = self.env().transferred_value();
 
-            let mut proof = self.proofs.get(&proof_id).ok_or(Error::ProofNotFound)?;
-            
-            if proof.status != ProofStatus::Pending {
-                return Err(Error::ProofAlreadyVerified);
-            }
+            let vk = self.check_verification_key(verification_key_hash)?;
+            let limits = self.get_payload_limits(vk.circuit_type);
+            Self::check_payload_size(&limits, &proof_data, &public_inputs)?;
+            self.check_storage_deposit(proof_data.len() + public_inputs.len(), storage_deposit)?;
+            let nullifier = self.check_nullifier(&public_inputs)?;
+            let dataset = self.check_prover(caller, dataset_id, verification_key_hash)?;
+            let (commitment, payment_nullifier) = self.check_private_commitment(&public_inputs, &dataset)?;
+            let query_id = self.open_private_settlement(payment_nullifier, dataset_id, commitment.k)?;
 
-            // Get verification key
-            let vk = self.verification_keys.get(&proof.verification_key_hash)
-                .ok_or(Error::VerificationKeyNotFound)?;
+            let proof_id = self.next_proof_id;
+            self.nullifiers.insert(nullifier, &proof_id);
 
-            // Perform actual proof verification
-            let is_valid = self.verify_proof_internal(&proof, &vk)?;
+            let proof = ZKProof {
+                proof_id,
+                query_id,
+                dataset_id,
+                prover: caller,
+                proof_data,
+                public_inputs,
+                verification_key_hash,
+                created_at: now,
+                verified_at: None,
+                status: ProofStatus::Pending,
+                challenge_hash,
+                storage_deposit,
+            };
 
-            if is_valid {
-                proof.status = ProofStatus::Verified;
-                self.proofs.insert(proof_id, &proof);
+            self.proofs.insert(proof_id, &proof);
+            self.query_proofs.insert(query_id, &proof_id);
+            self.next_proof_id += 1;
 
-                // Calculate proof hash for payment completion
-                let proof_hash = self.calculate_proof_hash(&proof);
+            self.env().emit_event(PrivateProofSubmitted {
+                proof_id,
+                query_id,
+                prover: caller,
+            });
 
-                // Notify payment manager
-                self.complete_payment(proof.query_id, proof_hash)?;
+            Ok(proof_id)
+        }
 
-                self.env().emit_event(ProofVerified {
-                    proof_id,
-                    query_id: proof.query_id,
-                    verifier: caller,
-                });
-            } else {
-                proof.status = ProofStatus::Rejected;
-                self.proofs.insert(proof_id, &proof);
+        /// Submit one proof covering several queries of the same dataset.
+        /// `public_inputs[i]` is the encoded `ResultCommitment` answering
+        /// `query_ids[i]`; only their commitment
+        /// (see `compute_inputs_commitment`) is stored on-chain, so the storage
+        /// deposit covers the proof data and the 32-byte commitment. Private
+        /// payments are proven one at a time with `submit_private_proof`.
+        #[ink(message, payable)]
+        pub fn submit_aggregated_proof(
+            &mut self,
+            query_ids: Vec<u64>,
+            dataset_id: u64,
+            proof_data: Vec<u8>,
+            public_inputs: Vec<Vec<u8>>,
+            verification_key_hash: [u8; 32],
+            challenge_hash: [u8; 32],
+        ) -> Result<u64> {
+            self.ensure_not_paused(Operation::ProofSubmission)?;
 
-                self.env().emit_event(ProofRejected {
-                    proof_id,
-                    reason: "Invalid proof".to_string(),
-                });
+            let caller = self.env().caller();
+            let now = self.env().block_timestamp();
+            let storage_deposit = self.env().transferred_value();
+
+            if query_ids.is_empty()
+                || query_ids.len() > MAX_AGGREGATION_SIZE
+                || query_ids.len() != public_inputs.len()
+            {
+                return Err(Error::InvalidAggregation);
             }
 
-            Ok(())
+            let mut unique_ids = query_ids.clone();
+            unique_ids.sort_unstable();
+            unique_ids.dedup();
+            if unique_ids.len() != query_ids.len() {
+                return Err(Error::InvalidAggregation);
+            }
+
+            let vk = self.check_verification_key(verification_key_hash)?;
+            let limits = self.get_payload_limits(vk.circuit_type);
+            for inputs in &public_inputs {
+                Self::check_payload_size(&limits, &proof_data, inputs)?;
+            }
+            self.check_storage_deposit(proof_data.len() + 32, storage_deposit)?;
+            let mut payments = Vec::new();
+            for &query_id in &query_ids {
+                payments.push(self.check_query(query_id, dataset_id)?);
+            }
+            let dataset = self.check_prover(caller, dataset_id, verification_key_hash)?;
+            for (inputs, payment) in public_inputs.iter().zip(&payments) {
+                self.check_result_commitment(inputs, payment, &dataset)?;
+            }
+
+            let inputs_commitment = Self::compute_inputs_commitment(&query_ids, &public_inputs);
+            let nullifier = self.check_nullifier(&inputs_commitment)?;
+            let proof_id = self.next_proof_id;
+            self.nullifiers.insert(nullifier, &proof_id);
+
+            let proof = ZKProof {
+                proof_id,
+                query_id: query_ids[0],
+                dataset_id,
+                prover: caller,
+                proof_data,
+                public_inputs: inputs_commitment.to_vec(),
+                verification_key_hash,
+                created_at: now,
+                verified_at: None,
+                status: ProofStatus::Pending,
+                challenge_hash,
+                storage_deposit,
+            };
+
+            let aggregation = ProofAggregation {
+                proof_id,
+                query_ids: query_ids.clone(),
+                inputs_commitment,
+            };
+
+            self.proofs.insert(proof_id, &proof);
+            self.aggregations.insert(proof_id, &aggregation);
+            for query_id in &query_ids {
+                self.query_proofs.insert(query_id, &proof_id);
+            }
+            self.next_proof_id += 1;
+
+            self.env().emit_event(AggregatedProofSubmitted {
+                proof_id,
+                prover: caller,
+                dataset_id,
+                query_ids,
+                inputs_commitment,
+            });
+
+            Ok(proof_id)
+        }
+
+        /// Nullifier a proof with these public inputs would spend:
+        /// `keccak256("ZKVerifier/nullifier" || public_inputs)`. For aggregated
+        /// proofs the public inputs are the inputs commitment. Nothing the
+        /// prover picks freely goes in, and since every result commitment
+        /// names its query, identical answers to different queries do not
+        /// collide.
+        #[ink(message)]
+        pub fn compute_nullifier(&self, public_inputs: Vec<u8>) -> [u8; 32] {
+            self.derive_nullifier(&public_inputs)
+        }
+
+        /// Get the proof that spent a nullifier, if any
+        #[ink(message)]
+        pub fn get_nullifier_proof(&self, nullifier: [u8; 32]) -> Option<u64> {
+            self.nullifiers.get(&nullifier)
+        }
+
+        /// Commitment to the public inputs of an aggregated proof:
+        /// `keccak256(query_id_0 (u64 LE) || keccak256(inputs_0) || query_id_1 || ...)`
+        #[ink(message)]
+        pub fn get_inputs_commitment(
+            &self,
+            query_ids: Vec<u64>,
+            public_inputs: Vec<Vec<u8>>,
+        ) -> Result<[u8; 32]> {
+            if query_ids.len() != public_inputs.len() {
+                return Err(Error::InvalidAggregation);
+            }
+            Ok(Self::compute_inputs_commitment(&query_ids, &public_inputs))
+        }
+
+        /// Verify ZK proof (called by authorized validators)
+        #[ink(message)]
+        pub fn verify_proof(&mut self, proof_id: u64) -> Result<()> {
+            self.ensure_not_paused(Operation::Verification)?;
+
+            let caller = self.env().caller();
+            
+            // Check if caller is authorized validator
+            if !self.validators.get(&caller).unwrap_or(false) && caller != self.owner {
+                return Err(Error::NotAuthorized);
+            }
+
+            let (proof, vk) = self.get_pending_proof(proof_id)?;
+
+            // Perform actual proof verification
+            let is_valid = self.verify_proof_internal(&proof, &vk)?;
+
+            self.finish_verification(proof, is_valid, caller)
+        }
+
+        /// Verify several proofs at once (called by authorized validators).
+        /// Proofs under the same verification key are checked together with a
+        /// random linear combination; if that batch fails, each of its proofs is
+        /// checked individually. Returns the outcome for every requested proof.
+        #[ink(message)]
+        pub fn verify_proofs(&mut self, proof_ids: Vec<u64>) -> Result<Vec<(u64, Result<bool>)>> {
+            self.ensure_not_paused(Operation::Verification)?;
+
+            let caller = self.env().caller();
+            
+            // Check if caller is authorized validator
+            if !self.validators.get(&caller).unwrap_or(false) && caller != self.owner {
+                return Err(Error::NotAuthorized);
+            }
+
+            if proof_ids.is_empty() || proof_ids.len() > MAX_BATCH_SIZE {
+                return Err(Error::InvalidBatch);
+            }
+
+            let mut unique_ids = proof_ids.clone();
+            unique_ids.sort_unstable();
+            unique_ids.dedup();
+            if unique_ids.len() != proof_ids.len() {
+                return Err(Error::InvalidBatch);
+            }
+
+            // Group pending proofs by verification key
+            let mut results = Vec::new();
+            let mut batches: Vec<(VerificationKey, Vec<ZKProof>)> = Vec::new();
+            for &proof_id in &proof_ids {
+                match self.get_pending_proof(proof_id) {
+                    Ok((_, vk)) if !self.is_circuit_type_allowed(vk.circuit_type.clone()) => {
+                        results.push((proof_id, Err(Error::UnsupportedCircuitType)))
+                    }
+                    Ok((proof, vk)) => {
+                        match batches.iter_mut().find(|(key, _)| key.key_hash == vk.key_hash) {
+                            Some((_, batch)) => batch.push(proof),
+                            None => batches.push((vk, ink::prelude::vec![proof])),
+                        }
+                    }
+                    Err(error) => results.push((proof_id, Err(error))),
+                }
+            }
+
+            let mut proof_count = 0u32;
+            let mut failed_batches = 0u32;
+            for (vk, batch) in batches {
+                let outcomes = if self.batch_verify_internal(&batch, &vk)? {
+                    ink::prelude::vec![true; batch.len()]
+                } else {
+                    // Fall back to individual checks to find the invalid proofs
+                    failed_batches += 1;
+                    batch
+                        .iter()
+                        .map(|proof| self.verify_proof_internal(proof, &vk))
+                        .collect::<Result<Vec<bool>>>()?
+                };
+
+                // A proof whose payment cannot be updated stays pending and is
+                // reported on its own, without failing the rest of the batch
+                for (proof, is_valid) in batch.into_iter().zip(outcomes) {
+                    let proof_id = proof.proof_id;
+                    match self.finish_verification(proof, is_valid, caller) {
+                        Ok(()) => {
+                            results.push((proof_id, Ok(is_valid)));
+                            proof_count += 1;
+                        }
+                        Err(error) => results.push((proof_id, Err(error))),
+                    }
+                }
+            }
+
+            // Report outcomes in the order they were requested
+            results.sort_by_key(|(proof_id, _)| proof_ids.iter().position(|id| id == proof_id));
+
+            self.env().emit_event(ProofsBatchVerified {
+                verifier: caller,
+                proof_count,
+                failed_batches,
+            });
+
+            Ok(results)
         }
 
         /// Challenge a proof
@@ -339,53 +1028,69 @@ mod zk_verifier {
         ) -> Result<u64> {
             let caller = self.env().caller();
             let stake = self.env().transferred_value();
-            let now = self.env().block_timestamp();
+            self.open_challenge(proof_id, caller, stake, reason)
+        }
 
-            if stake < self.min_challenge_stake {
-                return Err(Error::InsufficientStake);
-            }
+        /// Challenge a proof with evidence the contract can check itself: the
+        /// query vector, the result at `rank` in the committed top-k list and
+        /// its Merkle path in the dataset's embedding tree. The similarity is
+        /// recomputed on-chain and the challenge is resolved immediately. Only
+        /// single-query proofs can be challenged this way.
+        #[ink(message, payable)]
+        pub fn challenge_with_fraud_proof(
+            &mut self,
+            proof_id: u64,
+            rank: u32,
+            query_vector: Vec<i32>,
+            result_vector: Vec<i32>,
+            merkle_path: Vec<[u8; 32]>,
+        ) -> Result<u64> {
+            let caller = self.env().caller();
+            let stake = self.env().transferred_value();
 
-            let mut proof = self.proofs.get(&proof_id).ok_or(Error::ProofNotFound)?;
+            let proof = self.proofs.get(&proof_id).ok_or(Error::ProofNotFound)?;
+            let commitment = ResultCommitment::decode(&proof.public_inputs)
+                .map_err(|_| Error::InvalidFraudProof)?;
+            let (leaf_index, claimed_score) = commitment.result(rank)
+                .ok_or(Error::InvalidFraudProof)?;
 
-            // Check if proof is still in challenge period
-            if now > proof.created_at + self.challenge_period {
-                return Err(Error::ChallengePeriodExpired);
+            let dataset = self.get_dataset(proof.dataset_id).ok_or(Error::DatasetNotFound)?;
+            let dimension = dataset.schema.dimension as usize;
+            if query_vector.len() != dimension || result_vector.len() != dimension {
+                return Err(Error::DimensionMismatch);
             }
 
-            if proof.status != ProofStatus::Verified {
-                return Err(Error::InvalidChallenge);
+            // The evidence must be the query that was paid for and a real dataset entry
+            if self.hash_data(&vector_math::encode_vector(&query_vector)) != commitment.query_hash {
+                return Err(Error::InvalidFraudProof);
+            }
+            if !self.verify_embedding_inclusion(
+                commitment.dataset_version,
+                leaf_index,
+                &result_vector,
+                &merkle_path,
+            ) {
+                return Err(Error::InvalidFraudProof);
             }
 
-            let challenge_id = self.next_challenge_id;
-            let challenge = Challenge {
-                challenge_id,
+            let computed_score = vector_math::similarity(commitment.metric, &query_vector, &result_vector)
+                .map_err(|_| Error::InvalidFraudProof)?;
+            let accepted = (computed_score - claimed_score).abs() > SCORE_TOLERANCE;
+
+            let challenge_id = self.open_challenge(
                 proof_id,
-                challenger: caller,
+                caller,
                 stake,
-                reason,
-                created_at: now,
-                resolution_deadline: now + self.challenge_period,
-                status: ChallengeStatus::Active,
-            };
-
-            self.challenges.insert(challenge_id, &challenge);
-
-            // Update proof status
-            proof.status = ProofStatus::Challenged;
-            self.proofs.insert(proof_id, &proof);
-
-            // Add to proof challenges list
-            let mut challenges_list = self.proof_challenges.get(&proof_id).unwrap_or_default();
-            challenges_list.push(challenge_id);
-            self.proof_challenges.insert(proof_id, &challenges_list);
-
-            self.next_challenge_id += 1;
+                String::from("Fraud proof: similarity mismatch"),
+            )?;
+            self.settle_challenge(challenge_id, accepted)?;
 
-            self.env().emit_event(ProofChallenged {
+            self.env().emit_event(FraudProofEvaluated {
                 challenge_id,
                 proof_id,
-                challenger: caller,
-                stake,
+                claimed_score,
+                computed_score,
+                accepted,
             });
 
             Ok(challenge_id)
@@ -398,12 +1103,29 @@ mod zk_verifier {
             challenge_id: u64,
             accept_challenge: bool,
         ) -> Result<()> {
-            let caller = self.env().caller();
-            
-            if caller != self.owner {
-                return Err(Error::NotAuthorized);
+            self.ensure_role(Role::Arbiter)?;
+
+            let now = self.env().block_timestamp();
+            let challenge = self.challenges.get(&challenge_id).ok_or(Error::ChallengeNotFound)?;
+
+            if challenge.status != ChallengeStatus::Active {
+                return Err(Error::InvalidChallenge);
             }
 
+            // Past the deadline only finalize_expired_challenge applies
+            if now > challenge.resolution_deadline {
+                return Err(Error::ResolutionDeadlinePassed);
+            }
+
+            self.settle_challenge(challenge_id, accept_challenge)
+        }
+
+        /// Close a challenge nobody resolved before its deadline. Anyone can call
+        /// this; the challenger gets the stake back and, once no challenges remain
+        /// open, the proof returns to verified.
+        #[ink(message)]
+        pub fn finalize_expired_challenge(&mut self, challenge_id: u64) -> Result<()> {
+            let now = self.env().block_timestamp();
             let mut challenge = self.challenges.get(&challenge_id).ok_or(Error::ChallengeNotFound)?;
             let mut proof = self.proofs.get(&challenge.proof_id).ok_or(Error::ProofNotFound)?;
 
@@ -411,33 +1133,147 @@ mod zk_verifier {
                 return Err(Error::InvalidChallenge);
             }
 
-            if accept_challenge {
-                // Challenge accepted - refund challenger and mark proof as rejected
-                challenge.status = ChallengeStatus::Resolved;
-                proof.status = ProofStatus::Rejected;
+            if now <= challenge.resolution_deadline {
+                return Err(Error::ChallengeNotExpired);
+            }
 
-                // Refund challenger
-                self.env().transfer(challenge.challenger, challenge.stake)
-                    .map_err(|_| Error::TransferFailed)?;
+            challenge.status = ChallengeStatus::Expired;
+            self.challenges.insert(challenge_id, &challenge);
 
-                // Initiate payment refund
-                self.refund_payment(proof.query_id)?;
-            } else {
-                // Challenge dismissed - forfeit challenger's stake
-                challenge.status = ChallengeStatus::Dismissed;
+            if !self.has_active_challenges(challenge.proof_id) {
                 proof.status = ProofStatus::Verified;
-                
-                // Keep the stake (transfer to contract owner or burn)
-                self.env().transfer(self.owner, challenge.stake)
+                self.proofs.insert(challenge.proof_id, &proof);
+            }
+
+            // Return the stake to challenger
+            self.env().transfer(challenge.challenger, challenge.stake)
+                .map_err(|_| Error::TransferFailed)?;
+
+            self.env().emit_event(ChallengeExpired {
+                challenge_id,
+                proof_id: challenge.proof_id,
+                challenger: challenge.challenger,
+                stake: challenge.stake,
+            });
+
+            Ok(())
+        }
+
+        /// Archive a rejected proof, or a verified proof past its challenge
+        /// window: the proof, its aggregation and its challenges are replaced
+        /// by a `ProofReceipt` and the storage deposit is refunded to the
+        /// prover. Anyone can call this. Nullifiers are kept so the proof
+        /// cannot be replayed.
+        #[ink(message)]
+        pub fn prune_proof(&mut self, proof_id: u64) -> Result<()> {
+            let now = self.env().block_timestamp();
+            if self.proof_receipts.contains(&proof_id) {
+                return Err(Error::ProofAlreadyPruned);
+            }
+            let proof = self.proofs.get(&proof_id).ok_or(Error::ProofNotFound)?;
+
+            let is_final = match proof.status {
+                ProofStatus::Rejected => true,
+                ProofStatus::Verified => self
+                    .challenge_deadline(&proof)
+                    .map_or(false, |deadline| now > deadline),
+                _ => false,
+            };
+            if !is_final {
+                return Err(Error::ProofNotFinal);
+            }
+
+            let receipt = ProofReceipt {
+                proof_id,
+                dataset_id: proof.dataset_id,
+                prover: proof.prover,
+                proof_hash: self.calculate_proof_hash(&proof),
+                status: proof.status.clone(),
+                verified_at: proof.verified_at,
+            };
+            let query_ids = self.covered_queries(&proof);
+
+            // Challenges of a final proof are all closed
+            let challenge_ids = self.proof_challenges.get(&proof_id).unwrap_or_default();
+            for challenge_id in &challenge_ids {
+                self.challenges.remove(challenge_id);
+            }
+            self.proof_challenges.remove(&proof_id);
+            self.aggregations.remove(&proof_id);
+            self.proofs.remove(&proof_id);
+            self.proof_receipts.insert(proof_id, &receipt);
+
+            if proof.storage_deposit > 0 {
+                self.env().transfer(proof.prover, proof.storage_deposit)
                     .map_err(|_| Error::TransferFailed)?;
             }
 
-            self.challenges.insert(challenge_id, &challenge);
-            self.proofs.insert(challenge.proof_id, &proof);
+            self.env().emit_event(ProofArchived {
+                proof_id,
+                prover: proof.prover,
+                dataset_id: proof.dataset_id,
+                query_ids,
+                proof_hash: receipt.proof_hash,
+                status: receipt.status,
+                created_at: proof.created_at,
+                verified_at: proof.verified_at,
+                challenge_ids,
+                deposit_refunded: proof.storage_deposit,
+            });
+
+            Ok(())
+        }
 
+        /// Record a refund the payment manager granted to settle a dispute
+        /// against a dataset owner (payment manager only)
+        #[ink(message)]
+        pub fn report_dispute_refund(&mut self, dataset_owner: AccountId) -> Result<()> {
+            if self.env().caller() != self.payment_manager {
+                return Err(Error::NotAuthorized);
+            }
+            self.record_outcome(dataset_owner, Outcome::DisputeRefunded);
             Ok(())
         }
 
+        /// Get the track record of a prover or dataset owner
+        #[ink(message)]
+        pub fn get_reputation(&self, account: AccountId) -> ReputationRecord {
+            self.reputations.get(&account).unwrap_or_default()
+        }
+
+        /// Get the current reputation score of an account, in basis points
+        /// (see `reputation::NEUTRAL_SCORE` for accounts without history)
+        #[ink(message)]
+        pub fn get_reputation_score(&self, account: AccountId) -> u32 {
+            let now = self.env().block_timestamp();
+            self.get_reputation(account).score(now)
+        }
+
+        /// Get the receipt of an archived proof
+        #[ink(message)]
+        pub fn get_proof_receipt(&self, proof_id: u64) -> Option<ProofReceipt> {
+            self.proof_receipts.get(&proof_id)
+        }
+
+        /// Get the payload limits that apply to a circuit type
+        #[ink(message)]
+        pub fn get_payload_limits(&self, circuit_type: String) -> PayloadLimits {
+            self.payload_limits.get(&circuit_type).unwrap_or(DEFAULT_PAYLOAD_LIMITS)
+        }
+
+        /// Check if keys and proofs of a circuit type are accepted
+        #[ink(message)]
+        pub fn is_circuit_type_allowed(&self, circuit_type: String) -> bool {
+            BUILTIN_CIRCUIT_TYPES.contains(&circuit_type.as_str())
+                || self.allowed_circuit_types.get(&circuit_type).unwrap_or(false)
+        }
+
+        /// Get the storage deposit required to store `stored_bytes` of proof data
+        #[ink(message)]
+        pub fn get_required_deposit(&self, stored_bytes: u32) -> Balance {
+            self.storage_deposit_per_byte.get_or_default().saturating_mul(stored_bytes as Balance)
+        }
+
         /// Get proof information
         #[ink(message)]
         pub fn get_proof(&self, proof_id: u64) -> Option<ZKProof> {
@@ -454,6 +1290,44 @@ mod zk_verifier {
             }
         }
 
+        /// Get the queries covered by an aggregated proof
+        #[ink(message)]
+        pub fn get_aggregation(&self, proof_id: u64) -> Option<ProofAggregation> {
+            self.aggregations.get(&proof_id)
+        }
+
+        /// Get the last moment the proof for a query can be challenged
+        #[ink(message)]
+        pub fn get_challenge_deadline(&self, query_id: u64) -> Option<Timestamp> {
+            let proof_id = self.query_proofs.get(&query_id)?;
+            let verified_at = match self.proofs.get(&proof_id) {
+                Some(proof) => proof.verified_at,
+                None => self.proof_receipts.get(&proof_id)?.verified_at,
+            };
+            verified_at.map(|verified_at| verified_at + self.challenge_period)
+        }
+
+        /// Check if the proof for a query is verified and can no longer be challenged
+        #[ink(message)]
+        pub fn is_proof_final(&self, query_id: u64) -> bool {
+            let now = self.env().block_timestamp();
+            let proof_id = match self.query_proofs.get(&query_id) {
+                Some(proof_id) => proof_id,
+                None => return false,
+            };
+            match self.proofs.get(&proof_id) {
+                Some(proof) if proof.status == ProofStatus::Verified => self
+                    .challenge_deadline(&proof)
+                    .map_or(false, |deadline| now > deadline),
+                Some(_) => false,
+                // Only final proofs are archived
+                None => self
+                    .proof_receipts
+                    .get(&proof_id)
+                    .map_or(false, |receipt| receipt.status == ProofStatus::Verified),
+            }
+        }
+
         /// Get challenge information
         #[ink(message)]
         pub fn get_challenge(&self, challenge_id: u64) -> Option<Challenge> {
@@ -466,40 +1340,456 @@ mod zk_verifier {
             self.proof_challenges.get(&proof_id).unwrap_or_default()
         }
 
-        /// Admin functions
+        /// Admin functions. Stakes, periods, deposits and payload limits are
+        /// changed through `Timelock`.
         #[ink(message)]
         pub fn add_validator(&mut self, validator: AccountId) -> Result<()> {
-            if self.env().caller() != self.owner {
-                return Err(Error::NotAuthorized);
-            }
+            self.ensure_role(Role::ValidatorManager)?;
             self.validators.insert(validator, &true);
             Ok(())
         }
 
         #[ink(message)]
         pub fn remove_validator(&mut self, validator: AccountId) -> Result<()> {
-            if self.env().caller() != self.owner {
-                return Err(Error::NotAuthorized);
-            }
+            self.ensure_role(Role::ValidatorManager)?;
             self.validators.remove(&validator);
             Ok(())
         }
 
+        /// Convert proofs left in the storage version 1 layout (owner only,
+        /// as version 1 storage has no roles yet). Looks at up to `limit` IDs
+        /// per call so large stores can be migrated over several blocks, and
+        /// returns how many records were converted. The storage version is
+        /// bumped once every ID has been looked at.
         #[ink(message)]
-        pub fn set_challenge_stake(&mut self, stake: Balance) -> Result<()> {
+        pub fn migrate(&mut self, limit: u32) -> Result<u32> {
             if self.env().caller() != self.owner {
                 return Err(Error::NotAuthorized);
             }
-            self.min_challenge_stake = stake;
-            Ok(())
+            if self.get_storage_version() >= STORAGE_VERSION {
+                return Err(Error::AlreadyMigrated);
+            }
+
+            // Version 1 had no roles; the owner takes all of them, as on deployment
+            let mut id = match self.migration_cursor.get() {
+                Some(cursor) => cursor,
+                None => {
+                    access_control::grant_owner_roles(&mut self.roles, self.owner);
+                    0
+                }
+            };
+            let end = id.saturating_add(limit as u64).min(self.next_proof_id);
+            let mut migrated = 0;
+            while id < end {
+                if let Some(record) = self.legacy_proofs.take(&id) {
+                    self.proofs.insert(id, &ZKProof::from(record));
+                    migrated += 1;
+                }
+                id += 1;
+            }
+            self.migration_cursor.set(&id);
+
+            if id >= self.next_proof_id {
+                self.storage_version.set(&STORAGE_VERSION);
+                self.env().emit_event(StorageMigrated { version: STORAGE_VERSION });
+            }
+
+            Ok(migrated)
+        }
+
+        #[ink(message)]
+        pub fn get_storage_version(&self) -> u32 {
+            // Version 1 storage has no version cell
+            self.storage_version.get().unwrap_or(1)
         }
 
         /// Internal helper functions
-        fn verify_proof_internal(
-            &self,
-            proof: &ZKProof,
+        pub(crate) fn supports_change(change: &ParameterChange) -> bool {
+            matches!(
+                change,
+                ParameterChange::ChallengeStake(_)
+                    | ParameterChange::ResolutionPeriod(_)
+                    | ParameterChange::StorageDepositPerByte(_)
+                    | ParameterChange::PayloadLimits(_, _)
+                    | ParameterChange::TimelockDelay(_)
+                    | ParameterChange::CodeHash(_)
+                    | ParameterChange::AllowCircuitType(_, _)
+            )
+        }
+
+        fn upgrade(&mut self, code_hash: Hash) -> TimelockResult<()> {
+            self.env().set_code_hash(&code_hash).map_err(|_| TimelockError::UpgradeFailed)?;
+
+            self.env().emit_event(Upgraded { code_hash });
+
+            Ok(())
+        }
+
+        fn apply_change(&mut self, change: ParameterChange) -> TimelockResult<()> {
+            match change {
+                ParameterChange::ChallengeStake(stake) => self.min_challenge_stake = stake,
+                ParameterChange::ResolutionPeriod(period) => self.resolution_period.set(&period),
+                ParameterChange::StorageDepositPerByte(deposit) => {
+                    self.storage_deposit_per_byte.set(&deposit);
+                }
+                ParameterChange::PayloadLimits(circuit_type, limits) => {
+                    self.payload_limits.insert(circuit_type, &limits);
+                }
+                ParameterChange::TimelockDelay(delay) => self.timelock_delay.set(&delay),
+                ParameterChange::CodeHash(code_hash) => self.upgrade(code_hash)?,
+                ParameterChange::AllowCircuitType(circuit_type, limits) => {
+                    self.allowed_circuit_types.insert(&circuit_type, &true);
+                    self.payload_limits.insert(circuit_type, &limits);
+                }
+                _ => {}
+            }
+            Ok(())
+        }
+
+        fn ensure_not_paused(&self, operation: Operation) -> Result<()> {
+            if self.is_paused(operation) {
+                return Err(Error::OperationPaused);
+            }
+            Ok(())
+        }
+
+        fn ensure_role(&self, role: Role) -> Result<()> {
+            if !self.has_role(role, self.env().caller()) {
+                return Err(Error::NotAuthorized);
+            }
+            Ok(())
+        }
+
+        fn check_verification_key(&self, key_hash: [u8; 32]) -> Result<VerificationKey> {
+            // Check if verification key exists and is usable
+            let vk = self.verification_keys.get(&key_hash)
+                .ok_or(Error::VerificationKeyNotFound)?;
+            if !vk.is_active {
+                return Err(Error::VerificationKeyInactive);
+            }
+            Ok(vk)
+        }
+
+        fn check_payload_size(
+            limits: &PayloadLimits,
+            proof_data: &[u8],
+            public_inputs: &[u8],
+        ) -> Result<()> {
+            if proof_data.len() > limits.max_proof_size as usize {
+                return Err(Error::ProofTooLarge);
+            }
+            if public_inputs.len() > limits.max_public_inputs_size as usize {
+                return Err(Error::PublicInputsTooLarge);
+            }
+            Ok(())
+        }
+
+        fn check_storage_deposit(&self, stored_bytes: usize, deposit: Balance) -> Result<()> {
+            let required = self.storage_deposit_per_byte
+                .get_or_default()
+                .saturating_mul(stored_bytes as Balance);
+            if deposit < required {
+                return Err(Error::InsufficientDeposit);
+            }
+            Ok(())
+        }
+
+        fn check_query(&self, query_id: u64, dataset_id: u64) -> Result<Payment> {
+            // Check if proof already exists for this query
+            if self.query_proofs.contains(&query_id) {
+                return Err(Error::ProofAlreadyVerified);
+            }
+
+            // The query must be a pending payment for this dataset. A private
+            // payment names no dataset and is only answered through
+            // `submit_private_proof`, which never names the payment.
+            let payment = self.get_payment(query_id).ok_or(Error::PaymentNotFound)?;
+            if payment.status != PaymentStatus::Pending {
+                return Err(Error::PaymentNotPending);
+            }
+            if payment.query_commitment.is_some() || payment.dataset_id != dataset_id {
+                return Err(Error::DatasetMismatch);
+            }
+            Ok(payment)
+        }
+
+        fn check_prover(
+            &self,
+            caller: AccountId,
+            dataset_id: u64,
+            key_hash: [u8; 32],
+        ) -> Result<Dataset> {
+            // Only the dataset owner or one of its validators may prove the query
+            let dataset = self.get_dataset(dataset_id).ok_or(Error::DatasetNotFound)?;
+            if !dataset.status.accepts_proofs() {
+                return Err(Error::DatasetUnavailable);
+            }
+            if caller != dataset.owner && !dataset.validator_nodes.contains(&caller) {
+                return Err(Error::NotAuthorized);
+            }
+
+            if !self.is_verification_key_allowed(dataset_id, key_hash) {
+                return Err(Error::VerificationKeyNotAllowed);
+            }
+            Ok(dataset)
+        }
+
+        /// Public inputs must commit to the answer the user paid for
+        fn check_result_commitment(
+            &self,
+            public_inputs: &[u8],
+            payment: &Payment,
+            dataset: &Dataset,
+        ) -> Result<ResultCommitment> {
+            let commitment = Self::check_answer(public_inputs, dataset)?;
+
+            // The answer must be for this query
+            if commitment.query_id != payment.query_id {
+                return Err(Error::InvalidResultCommitment);
+            }
+            if commitment.payment_nullifier.is_some() {
+                return Err(Error::QueryCommitmentMismatch);
+            }
+            if commitment.k != payment.top_k {
+                return Err(Error::ResultCountMismatch);
+            }
+            Ok(commitment)
+        }
+
+        /// Public inputs of a private answer name the payment's nullifier
+        /// instead of its query. The circuit shows the nullifier, dataset ID
+        /// and k come from the opening of a commitment in the private pool,
+        /// and that the payment holds the dataset's price.
+        fn check_private_commitment(
+            &self,
+            public_inputs: &[u8],
+            dataset: &Dataset,
+        ) -> Result<(ResultCommitment, [u8; 32])> {
+            let commitment = Self::check_answer(public_inputs, dataset)?;
+            if commitment.query_id != 0 {
+                return Err(Error::InvalidResultCommitment);
+            }
+            let payment_nullifier = commitment.payment_nullifier.ok_or(Error::QueryCommitmentMismatch)?;
+            Ok((commitment, payment_nullifier))
+        }
+
+        /// The answer must rank entries of the dataset as it stands
+        fn check_answer(public_inputs: &[u8], dataset: &Dataset) -> Result<ResultCommitment> {
+            let commitment = ResultCommitment::decode(public_inputs)
+                .map_err(|_| Error::InvalidResultCommitment)?;
+
+            if commitment.dataset_id != dataset.id {
+                return Err(Error::DatasetMismatch);
+            }
+            if commitment.dataset_version != dataset.embedding_root {
+                return Err(Error::DatasetVersionMismatch);
+            }
+
+            // The ranking must use the dataset's metric and point at real entries
+            if commitment.metric != dataset.schema.metric {
+                return Err(Error::MetricMismatch);
+            }
+            if commitment.leaf_indices.iter().any(|leaf| *leaf >= dataset.schema.vector_count) {
+                return Err(Error::ResultOutOfRange);
+            }
+            Ok(commitment)
+        }
+
+        fn derive_nullifier(&self, public_inputs: &[u8]) -> [u8; 32] {
+            let mut input = NULLIFIER_DOMAIN.to_vec();
+            input.extend_from_slice(public_inputs);
+            self.hash_data(&input)
+        }
+
+        /// Reject replayed proofs; the caller records the nullifier once the
+        /// proof is accepted
+        fn check_nullifier(&self, public_inputs: &[u8]) -> Result<[u8; 32]> {
+            let nullifier = self.derive_nullifier(public_inputs);
+            if self.nullifiers.contains(&nullifier) {
+                return Err(Error::NullifierAlreadyUsed);
+            }
+            Ok(nullifier)
+        }
+
+        fn covered_queries(&self, proof: &ZKProof) -> Vec<u64> {
+            match self.aggregations.get(&proof.proof_id) {
+                Some(aggregation) => aggregation.query_ids,
+                None => ink::prelude::vec![proof.query_id],
+            }
+        }
+
+        fn compute_inputs_commitment(query_ids: &[u64], public_inputs: &[Vec<u8>]) -> [u8; 32] {
+            use ink::env::hash::{Keccak256, HashOutput};
+
+            let mut input = Vec::new();
+            for (query_id, inputs) in query_ids.iter().zip(public_inputs) {
+                let mut inputs_hash = <Keccak256 as HashOutput>::Type::default();
+                ink::env::hash_bytes::<Keccak256>(inputs, &mut inputs_hash);
+                input.extend_from_slice(&query_id.to_le_bytes());
+                input.extend_from_slice(&inputs_hash);
+            }
+
+            let mut output = <Keccak256 as HashOutput>::Type::default();
+            ink::env::hash_bytes::<Keccak256>(&input, &mut output);
+            output.into()
+        }
+
+        fn get_pending_proof(&self, proof_id: u64) -> Result<(ZKProof, VerificationKey)> {
+            let proof = self.proofs.get(&proof_id).ok_or(Error::ProofNotFound)?;
+            
+            if proof.status != ProofStatus::Pending {
+                return Err(Error::ProofAlreadyVerified);
+            }
+
+            // Get verification key. Proofs can only be submitted under an
+            // active key, so a key deactivated or rotated since then still
+            // verifies the proofs queued before it, freeing their slot and deposit.
+            let vk = self.verification_keys.get(&proof.verification_key_hash)
+                .ok_or(Error::VerificationKeyNotFound)?;
+
+            Ok((proof, vk))
+        }
+
+        /// Store the verification outcome and notify the payment manager
+        fn finish_verification(
+            &mut self,
+            mut proof: ZKProof,
+            is_valid: bool,
+            verifier: AccountId,
+        ) -> Result<()> {
+            let proof_id = proof.proof_id;
+
+            // Users may reclaim a query while its proof waits. Only queries
+            // still pending are paid; a proof left with none is rejected
+            // without blaming the prover, so its deposit can be pruned.
+            let pending_queries: Vec<u64> = self
+                .covered_queries(&proof)
+                .into_iter()
+                .filter(|query_id| self.get_query_status(*query_id) == Some(PaymentStatus::Pending))
+                .collect();
+            if is_valid && pending_queries.is_empty() {
+                proof.status = ProofStatus::Rejected;
+                self.proofs.insert(proof_id, &proof);
+
+                self.env().emit_event(ProofRejected {
+                    proof_id,
+                    reason: "Payment no longer pending".to_string(),
+                });
+                return Ok(());
+            }
+
+            if is_valid {
+                proof.status = ProofStatus::Verified;
+                proof.verified_at = Some(self.env().block_timestamp());
+
+                // Calculate proof hash for payment completion
+                let proof_hash = self.calculate_proof_hash(&proof);
+
+                // Complete every pending query the proof covers in one call,
+                // before storing the outcome. The payment manager checks them
+                // all first, so a failed update completes none of them and
+                // leaves the proof pending.
+                self.complete_payments(pending_queries, proof.dataset_id, proof_hash)?;
+                self.proofs.insert(proof_id, &proof);
+
+                self.record_proof_outcome(&proof, Outcome::ProofVerified);
+
+                self.env().emit_event(ProofVerified {
+                    proof_id,
+                    query_id: proof.query_id,
+                    verifier,
+                });
+            } else {
+                proof.status = ProofStatus::Rejected;
+                self.proofs.insert(proof_id, &proof);
+
+                self.record_proof_outcome(&proof, Outcome::ProofRejected);
+
+                self.env().emit_event(ProofRejected {
+                    proof_id,
+                    reason: "Invalid proof".to_string(),
+                });
+            }
+
+            Ok(())
+        }
+
+        /// Credit or blame the prover and, if someone else, the dataset owner
+        fn record_proof_outcome(&mut self, proof: &ZKProof, outcome: Outcome) {
+            self.record_outcome(proof.prover, outcome);
+            if let Some(dataset) = self.get_dataset(proof.dataset_id) {
+                if dataset.owner != proof.prover {
+                    self.record_outcome(dataset.owner, outcome);
+                }
+            }
+        }
+
+        fn record_outcome(&mut self, account: AccountId, outcome: Outcome) {
+            let now = self.env().block_timestamp();
+            let mut record = self.reputations.get(&account).unwrap_or_default();
+            record.record(outcome, now);
+            self.reputations.insert(account, &record);
+
+            self.env().emit_event(ReputationUpdated {
+                account,
+                outcome,
+                score: record.score(now),
+            });
+        }
+
+        fn insert_verification_key(
+            &mut self,
+            owner: AccountId,
+            key_data: Vec<u8>,
+            circuit_type: String,
+        ) -> Result<[u8; 32]> {
+            // Proofs under a key of a type that is not allowed would be stranded
+            if !self.is_circuit_type_allowed(circuit_type.clone()) {
+                return Err(Error::UnsupportedCircuitType);
+            }
+            let limits = self.get_payload_limits(circuit_type.clone());
+            if key_data.len() > limits.max_key_size as usize {
+                return Err(Error::VerificationKeyTooLarge);
+            }
+
+            // Calculate key hash
+            let key_hash = self.hash_data(&key_data);
+
+            // Identical key bytes must not take over an existing key
+            if self.verification_keys.contains(&key_hash) {
+                return Err(Error::VerificationKeyAlreadyExists);
+            }
+
+            let vk = VerificationKey {
+                key_hash,
+                key_data,
+                circuit_type: circuit_type.clone(),
+                owner,
+                is_active: true,
+            };
+
+            self.verification_keys.insert(key_hash, &vk);
+
+            self.env().emit_event(VerificationKeyRegistered {
+                key_hash,
+                owner,
+                circuit_type,
+            });
+
+            Ok(key_hash)
+        }
+
+        fn verify_proof_internal(
+            &self,
+            proof: &ZKProof,
             vk: &VerificationKey,
         ) -> Result<bool> {
+            // Keys registered before the allowlist existed may name a circuit
+            // type that was never allowed; their proofs stay pending
+            if !self.is_circuit_type_allowed(vk.circuit_type.clone()) {
+                return Err(Error::UnsupportedCircuitType);
+            }
+
             // This is where actual ZK proof verification would happen
             // For HALO2 proofs, you would use the halo2_proofs library
             // For now, we'll do a simple validation
@@ -509,76 +1799,1015 @@ mod zk_verifier {
                 return Ok(false);
             }
 
-            // Verify the proof format and structure
-            // In a real implementation, this would use cryptographic verification
-            // For example, with HALO2:
-            // let result = verify_halo2_proof(&proof.proof_data, &proof.public_inputs, &vk.key_data);
-            
-            // Mock verification - in practice, this would be cryptographic
-            Ok(true)
+            // Verify the proof format and structure
+            // In a real implementation, this would use cryptographic verification
+            // For example, with HALO2:
+            // let result = verify_halo2_proof(&proof.proof_data, &proof.public_inputs, &vk.key_data);
+            
+            // Mock verification - in practice, this would be cryptographic
+            Ok(true)
+        }
+
+        fn batch_verify_internal(
+            &self,
+            proofs: &[ZKProof],
+            vk: &VerificationKey,
+        ) -> Result<bool> {
+            // Each proof's verification equation is scaled by its own random
+            // scalar before the equations are summed, so one multi-pairing check
+            // covers the whole batch and an invalid proof can only cancel out
+            // with negligible probability. The scalars are derived from a hash of
+            // every proof in the batch, so a prover cannot choose proofs that
+            // cancel each other out.
+            // In a real implementation, this would be a single pairing product, e.g.:
+            // let result = verify_batch(&vk.key_data, proofs, &scalars);
+
+            // Mock verification - the batch holds when every proof is well-formed
+            Ok(proofs
+                .iter()
+                .all(|proof| !proof.proof_data.is_empty() && !proof.public_inputs.is_empty()))
+        }
+
+        /// Record a new challenge against a verified proof
+        fn open_challenge(
+            &mut self,
+            proof_id: u64,
+            caller: AccountId,
+            stake: Balance,
+            reason: String,
+        ) -> Result<u64> {
+            let now = self.env().block_timestamp();
+
+            if stake < self.min_challenge_stake {
+                return Err(Error::InsufficientStake);
+            }
+
+            let mut proof = self.proofs.get(&proof_id).ok_or(Error::ProofNotFound)?;
+
+            // Further challenges may join one that is already open
+            if proof.status != ProofStatus::Verified && proof.status != ProofStatus::Challenged {
+                return Err(Error::InvalidChallenge);
+            }
+
+            // Check if proof is still in challenge period
+            let deadline = self.challenge_deadline(&proof).ok_or(Error::InvalidChallenge)?;
+            if now > deadline {
+                return Err(Error::ChallengePeriodExpired);
+            }
+
+            let challenge_id = self.next_challenge_id;
+            let challenge = Challenge {
+                challenge_id,
+                proof_id,
+                challenger: caller,
+                stake,
+                reason,
+                created_at: now,
+                resolution_deadline: now + self.resolution_period.get().unwrap_or(self.challenge_period),
+                status: ChallengeStatus::Active,
+            };
+
+            self.challenges.insert(challenge_id, &challenge);
+
+            // Update proof status
+            proof.status = ProofStatus::Challenged;
+            self.proofs.insert(proof_id, &proof);
+
+            // Add to proof challenges list
+            let mut challenges_list = self.proof_challenges.get(&proof_id).unwrap_or_default();
+            challenges_list.push(challenge_id);
+            self.proof_challenges.insert(proof_id, &challenges_list);
+
+            self.next_challenge_id += 1;
+
+            self.env().emit_event(ProofChallenged {
+                challenge_id,
+                proof_id,
+                challenger: caller,
+                stake,
+            });
+
+            Ok(challenge_id)
+        }
+
+        /// Apply the outcome of an active challenge to its proof, stakes and payments
+        fn settle_challenge(&mut self, challenge_id: u64, accept_challenge: bool) -> Result<()> {
+            let mut challenge = self.challenges.get(&challenge_id).ok_or(Error::ChallengeNotFound)?;
+            let mut proof = self.proofs.get(&challenge.proof_id).ok_or(Error::ProofNotFound)?;
+
+            if accept_challenge {
+                // Challenge accepted - every open challenge on the proof was right,
+                // so refund all of them and mark proof as rejected
+                proof.status = ProofStatus::Rejected;
+                self.proofs.insert(challenge.proof_id, &proof);
+                self.close_active_challenges(challenge.proof_id)?;
+
+                // Initiate payment refund for every query the proof covers
+                for query_id in self.covered_queries(&proof) {
+                    self.refund_payment(query_id)?;
+                }
+
+                self.record_proof_outcome(&proof, Outcome::ChallengeLost);
+            } else {
+                // Challenge dismissed - forfeit challenger's stake
+                challenge.status = ChallengeStatus::Dismissed;
+                self.challenges.insert(challenge_id, &challenge);
+
+                // The proof stays challenged while other challenges are open
+                if !self.has_active_challenges(challenge.proof_id) {
+                    proof.status = ProofStatus::Verified;
+                }
+                self.proofs.insert(challenge.proof_id, &proof);
+                
+                // Keep the stake (transfer to contract owner or burn)
+                self.env().transfer(self.owner, challenge.stake)
+                    .map_err(|_| Error::TransferFailed)?;
+            }
+
+            Ok(())
+        }
+
+        /// Leaf of the embedding tree: keccak256(leaf_index (u64 LE) || encoded vector)
+        fn embedding_leaf_hash(&self, leaf_index: u64, vector: &[i32]) -> [u8; 32] {
+            let mut leaf = leaf_index.to_le_bytes().to_vec();
+            leaf.extend_from_slice(&vector_math::encode_vector(vector));
+            self.hash_data(&leaf)
+        }
+
+        /// Check that `vector` is leaf `leaf_index` of the embedding tree with
+        /// `root`; inner nodes are keccak256(left || right)
+        fn verify_embedding_inclusion(
+            &self,
+            root: [u8; 32],
+            leaf_index: u64,
+            vector: &[i32],
+            path: &[[u8; 32]],
+        ) -> bool {
+            if path.len() > MAX_MERKLE_DEPTH {
+                return false;
+            }
+            if path.len() < MAX_MERKLE_DEPTH && leaf_index >> path.len() != 0 {
+                return false;
+            }
+
+            let mut node = self.embedding_leaf_hash(leaf_index, vector);
+            let mut index = leaf_index;
+            for sibling in path {
+                let mut input = Vec::with_capacity(64);
+                if index & 1 == 0 {
+                    input.extend_from_slice(&node);
+                    input.extend_from_slice(sibling);
+                } else {
+                    input.extend_from_slice(sibling);
+                    input.extend_from_slice(&node);
+                }
+                node = self.hash_data(&input);
+                index >>= 1;
+            }
+
+            node == root
+        }
+
+        fn has_active_challenges(&self, proof_id: u64) -> bool {
+            self.proof_challenges
+                .get(&proof_id)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|challenge_id| self.challenges.get(challenge_id))
+                .any(|challenge| challenge.status == ChallengeStatus::Active)
+        }
+
+        /// Mark every active challenge on a proof as resolved and refund its stake
+        fn close_active_challenges(&mut self, proof_id: u64) -> Result<()> {
+            for challenge_id in self.proof_challenges.get(&proof_id).unwrap_or_default() {
+                let mut challenge = match self.challenges.get(&challenge_id) {
+                    Some(challenge) if challenge.status == ChallengeStatus::Active => challenge,
+                    _ => continue,
+                };
+
+                challenge.status = ChallengeStatus::Resolved;
+                self.challenges.insert(challenge_id, &challenge);
+
+                self.env().transfer(challenge.challenger, challenge.stake)
+                    .map_err(|_| Error::TransferFailed)?;
+            }
+            Ok(())
+        }
+
+        fn challenge_deadline(&self, proof: &ZKProof) -> Option<Timestamp> {
+            proof.verified_at.map(|verified_at| verified_at + self.challenge_period)
+        }
+
+        fn calculate_proof_hash(&self, proof: &ZKProof) -> [u8; 32] {
+            // Calculate hash of proof for payment completion
+            use ink::env::hash::{Keccak256, HashOutput};
+
+            let mut input = Vec::new();
+            input.extend_from_slice(&proof.proof_data);
+            input.extend_from_slice(&proof.public_inputs);
+
+            let mut output = <Keccak256 as HashOutput>::Type::default();
+            ink::env::hash_bytes::<Keccak256>(&input, &mut output);
+            output.into()
+        }
+
+        fn hash_data(&self, data: &[u8]) -> [u8; 32] {
+            use ink::env::hash::{Keccak256, HashOutput};
+
+            let mut output = <Keccak256 as HashOutput>::Type::default();
+            ink::env::hash_bytes::<Keccak256>(data, &mut output);
+            output.into()
+        }
+
+        // Cross-contract call helpers
+        #[cfg(not(test))]
+        fn get_payment(&self, query_id: u64) -> Option<Payment> {
+            build_call::<DefaultEnvironment>()
+                .call(self.payment_manager)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("get_payment")))
+                        .push_arg(query_id),
+                )
+                .returns::<Option<Payment>>()
+                .invoke()
+        }
+
+        #[cfg(not(test))]
+        fn get_query_status(&self, query_id: u64) -> Option<PaymentStatus> {
+            build_call::<DefaultEnvironment>()
+                .call(self.payment_manager)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("get_query_status")))
+                        .push_arg(query_id),
+                )
+                .returns::<Option<PaymentStatus>>()
+                .invoke()
+        }
+
+        #[cfg(not(test))]
+        fn is_verification_key_allowed(&self, dataset_id: u64, key_hash: [u8; 32]) -> bool {
+            build_call::<DefaultEnvironment>()
+                .call(self.dataset_registry)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("is_verification_key_allowed")))
+                        .push_arg(dataset_id)
+                        .push_arg(key_hash),
+                )
+                .returns::<bool>()
+                .invoke()
+        }
+
+        #[cfg(not(test))]
+        fn get_dataset(&self, dataset_id: u64) -> Option<Dataset> {
+            build_call::<DefaultEnvironment>()
+                .call(self.dataset_registry)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("get_dataset")))
+                        .push_arg(dataset_id),
+                )
+                .returns::<Option<Dataset>>()
+                .invoke()
+        }
+
+        // The off-chain test environment cannot call other contracts, so tests
+        // see a pending top-1 payment for dataset 1 owned by the contract owner
+        // that accepts every verification key; queries from 100 on are private,
+        // private settlements are numbered 1000 plus the ID of the proof that
+        // opened them, and queries in `MOCK_REFUNDED_QUERIES` were reclaimed by
+        // the user
+        #[cfg(test)]
+        fn get_payment(&self, query_id: u64) -> Option<Payment> {
+            let refunded = MOCK_REFUNDED_QUERIES.with(|queries| queries.borrow().contains(&query_id));
+            let private = query_id >= 100;
+            Some(Payment {
+                query_id,
+                dataset_id: if private { 0 } else { 1 },
+                user: self.owner,
+                amount: 1000,
+                timestamp: 0,
+                top_k: if private { 0 } else { 1 },
+                status: if refunded { PaymentStatus::Refunded } else { PaymentStatus::Pending },
+                proof_hash: None,
+                query_commitment: if private { Some([9u8; 32]) } else { None },
+            })
+        }
+
+        #[cfg(test)]
+        fn get_query_status(&self, query_id: u64) -> Option<PaymentStatus> {
+            self.get_payment(query_id).map(|payment| payment.status)
+        }
+
+        #[cfg(test)]
+        fn get_dataset(&self, dataset_id: u64) -> Option<Dataset> {
+            use crate::dataset_registry::dataset_registry::{
+                DatasetSchema, DatasetStatus, Modality, Quantization, Visibility,
+            };
+
+            Some(Dataset {
+                id: dataset_id,
+                owner: self.owner,
+                name: String::from("Mock Dataset"),
+                description: String::new(),
+                embedding_root: MOCK_EMBEDDING_ROOT.with(|root| root.get()),
+                metadata_hash: [0u8; 32],
+                price_per_query: 1000,
+                status: DatasetStatus::Active,
+                created_at: 0,
+                total_queries: 0,
+                validator_nodes: Vec::new(),
+                allowed_verification_keys: Vec::new(),
+                visibility: Visibility::Public,
+                schema: DatasetSchema {
+                    model_id: String::from("mock-model"),
+                    dimension: 2,
+                    metric: vector_math::Metric::Cosine,
+                    vector_count: 16,
+                    quantization: Quantization::Float32,
+                    modality: Modality::Text,
+                },
+                category: String::new(),
+                tags: Vec::new(),
+            })
+        }
+
+        #[cfg(test)]
+        fn is_verification_key_allowed(&self, _dataset_id: u64, _key_hash: [u8; 32]) -> bool {
+            true
+        }
+
+        #[cfg(not(test))]
+        fn complete_payments(
+            &self,
+            query_ids: Vec<u64>,
+            dataset_id: u64,
+            proof_hash: [u8; 32],
+        ) -> Result<()> {
+            build_call::<DefaultEnvironment>()
+                .call(self.payment_manager)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("complete_payments")))
+                        .push_arg(query_ids)
+                        .push_arg(dataset_id)
+                        .push_arg(proof_hash),
+                )
+                .returns::<core::result::Result<(), PaymentError>>()
+                .invoke()
+                .map_err(|_| Error::PaymentUpdateFailed)
+        }
+
+        #[cfg(not(test))]
+        fn open_private_settlement(
+            &self,
+            payment_nullifier: [u8; 32],
+            dataset_id: u64,
+            top_k: u32,
+        ) -> Result<u64> {
+            build_call::<DefaultEnvironment>()
+                .call(self.payment_manager)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("open_private_settlement")))
+                        .push_arg(payment_nullifier)
+                        .push_arg(dataset_id)
+                        .push_arg(top_k),
+                )
+                .returns::<core::result::Result<u64, PaymentError>>()
+                .invoke()
+                .map_err(|_| Error::PaymentUpdateFailed)
+        }
+
+        #[cfg(not(test))]
+        fn refund_payment(&self, query_id: u64) -> Result<()> {
+            build_call::<DefaultEnvironment>()
+                .call(self.payment_manager)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("refund_payment")))
+                        .push_arg(query_id),
+                )
+                .returns::<core::result::Result<(), PaymentError>>()
+                .invoke()
+                .map_err(|_| Error::PaymentUpdateFailed)
+        }
+
+        #[cfg(test)]
+        fn complete_payments(
+            &self,
+            _query_ids: Vec<u64>,
+            _dataset_id: u64,
+            _proof_hash: [u8; 32],
+        ) -> Result<()> {
+            Ok(())
+        }
+
+        #[cfg(test)]
+        fn open_private_settlement(
+            &self,
+            _payment_nullifier: [u8; 32],
+            _dataset_id: u64,
+            _top_k: u32,
+        ) -> Result<u64> {
+            Ok(1000 + self.next_proof_id)
+        }
+
+        #[cfg(test)]
+        fn refund_payment(&self, _query_id: u64) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    impl AccessControl for ZKVerifier {
+        #[ink(message)]
+        fn has_role(&self, role: Role, account: AccountId) -> bool {
+            access_control::has_role(&self.roles, role, account)
+        }
+
+        #[ink(message)]
+        fn grant_role(&mut self, role: Role, account: AccountId) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            access_control::grant_role(&mut self.roles, caller, role, account)?;
+
+            self.env().emit_event(RoleGranted {
+                account,
+                sender: caller,
+                role,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn revoke_role(&mut self, role: Role, account: AccountId) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            access_control::revoke_role(&mut self.roles, self.owner, caller, role, account)?;
+
+            self.env().emit_event(RoleRevoked {
+                account,
+                sender: caller,
+                role,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn renounce_role(&mut self, role: Role) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            self.revoke_role(role, caller)
+        }
+
+        #[ink(message)]
+        fn owner(&self) -> AccountId {
+            self.owner
+        }
+
+        #[ink(message)]
+        fn transfer_ownership(&mut self, new_owner: AccountId) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            access_control::ensure_owner(self.owner, caller)?;
+
+            self.pending_owner.set(&Some(new_owner));
+
+            self.env().emit_event(OwnershipOffered {
+                owner: caller,
+                pending_owner: new_owner,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn accept_ownership(&mut self) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            let previous_owner = self.owner;
+            let pending_owner = self.pending_owner.get_or_default();
+            access_control::accept_ownership(&mut self.roles, previous_owner, pending_owner, caller)?;
+
+            self.owner = caller;
+            self.pending_owner.set(&None);
+
+            self.env().emit_event(OwnershipTransferred {
+                previous_owner,
+                new_owner: caller,
+            });
+
+            Ok(())
+        }
+    }
+
+    impl Pausable for ZKVerifier {
+        #[ink(message)]
+        fn is_paused(&self, operation: Operation) -> bool {
+            access_control::is_paused(&self.paused, operation)
+        }
+
+        #[ink(message)]
+        fn pause(&mut self, operation: Operation) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            access_control::pause(&self.roles, &mut self.paused, &PAUSABLE_OPERATIONS, caller, operation)?;
+
+            self.env().emit_event(Paused {
+                account: caller,
+                operation,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn unpause(&mut self, operation: Operation) -> AccessControlResult<()> {
+            let caller = self.env().caller();
+            access_control::unpause(&self.roles, &mut self.paused, caller, operation)?;
+
+            self.env().emit_event(Unpaused {
+                account: caller,
+                operation,
+            });
+
+            Ok(())
+        }
+    }
+
+    impl Timelock for ZKVerifier {
+        #[ink(message)]
+        fn queue_change(&mut self, change: ParameterChange) -> TimelockResult<u64> {
+            let change_id = self.next_change_id.get().unwrap_or(1);
+            let supported = Self::supports_change(&change);
+            let queued = QueuedChange {
+                change,
+                queued_by: self.env().caller(),
+                eta: self.env().block_timestamp().saturating_add(self.get_timelock_delay()),
+            };
+            timelock::queue_change(&mut self.queued_changes, &self.roles, change_id, &queued, supported)?;
+            self.next_change_id.set(&(change_id + 1));
+
+            self.env().emit_event(ChangeQueued {
+                change_id,
+                change: queued.change,
+                eta: queued.eta,
+            });
+
+            Ok(change_id)
+        }
+
+        #[ink(message)]
+        fn cancel_change(&mut self, change_id: u64) -> TimelockResult<()> {
+            let caller = self.env().caller();
+            timelock::cancel_change(&mut self.queued_changes, &self.roles, change_id, caller)?;
+
+            self.env().emit_event(ChangeCancelled { change_id });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn execute_change(&mut self, change_id: u64) -> TimelockResult<()> {
+            let now = self.env().block_timestamp();
+            let change = timelock::execute_change(&mut self.queued_changes, change_id, now)?;
+            self.apply_change(change.clone())?;
+
+            self.env().emit_event(ChangeExecuted { change_id, change });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        fn get_queued_change(&self, change_id: u64) -> Option<QueuedChange> {
+            self.queued_changes.get(&change_id)
+        }
+
+        #[ink(message)]
+        fn get_timelock_delay(&self) -> u64 {
+            self.timelock_delay.get().unwrap_or(DEFAULT_TIMELOCK_DELAY)
+        }
+    }
+
+    // Embedding root reported by the mocked registry, so tests can check
+    // fraud proofs against a real Merkle tree
+    #[cfg(test)]
+    thread_local! {
+        static MOCK_EMBEDDING_ROOT: core::cell::Cell<[u8; 32]> = core::cell::Cell::new([0u8; 32]);
+        static MOCK_REFUNDED_QUERIES: core::cell::RefCell<Vec<u64>> = core::cell::RefCell::new(Vec::new());
+    }
+
+    /// Unit tests
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::vector_math::Metric;
+
+        /// Public inputs answering a mock top-1 payment for dataset 1
+        fn result_inputs(query_id: u64) -> Vec<u8> {
+            ResultCommitment {
+                query_id,
+                query_hash: [3u8; 32],
+                k: 1,
+                metric: Metric::Cosine,
+                dataset_id: 1,
+                leaf_indices: vec![0],
+                scores: vec![65536],
+                dataset_version: [0u8; 32],
+                payment_nullifier: None,
+            }.encode()
+        }
+
+        #[ink::test]
+        fn test_register_verification_key() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ZKVerifier::new(
+                accounts.alice,
+                accounts.bob,
+                1000,
+                86400000,
+            );
+
+            let key_data = vec![1, 2, 3, 4];
+            let result = contract.register_verification_key(
+                key_data,
+                "halo2".to_string(),
+            );
+            
+            assert!(result.is_ok());
+        }
+
+        #[ink::test]
+        fn test_circuit_type_must_be_allowed() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ZKVerifier::new(
+                accounts.alice,
+                accounts.bob,
+                1000,
+                86400000,
+            );
+
+            assert!(contract.is_circuit_type_allowed("halo2".to_string()));
+            let result = contract.register_verification_key(vec![1, 2, 3, 4], "groth16".to_string());
+            assert_eq!(result, Err(Error::UnsupportedCircuitType));
+
+            let limits = PayloadLimits {
+                max_proof_size: 256,
+                max_public_inputs_size: 1024,
+                max_key_size: 2048,
+            };
+            contract.queue_change(ParameterChange::AllowCircuitType(
+                "groth16".to_string(),
+                limits.clone(),
+            )).unwrap();
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(DEFAULT_TIMELOCK_DELAY);
+            contract.execute_change(1).unwrap();
+
+            assert!(contract.is_circuit_type_allowed("groth16".to_string()));
+            assert_eq!(contract.get_payload_limits("groth16".to_string()), limits);
+            assert!(contract.register_verification_key(vec![1, 2, 3, 4], "groth16".to_string()).is_ok());
+        }
+
+        #[ink::test]
+        fn test_verification_key_lifecycle() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ZKVerifier::new(
+                accounts.alice,
+                accounts.bob,
+                1000,
+                86400000,
+            );
+
+            let key_hash = contract.register_verification_key(
+                vec![1, 2, 3, 4],
+                "halo2".to_string(),
+            ).unwrap();
+
+            // Re-registering the same bytes must not change the owner
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(
+                contract.register_verification_key(vec![1, 2, 3, 4], "halo2".to_string()),
+                Err(Error::VerificationKeyAlreadyExists)
+            );
+            assert_eq!(contract.deactivate_verification_key(key_hash), Err(Error::NotAuthorized));
+
+            // Rotation deactivates the old key for new proofs only
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            let queued = contract.submit_proof(1, 1, vec![5, 6], result_inputs(1), key_hash, [0u8; 32]).unwrap();
+            let new_key_hash = contract.rotate_verification_key(key_hash, vec![5, 6, 7, 8]).unwrap();
+            let result = contract.submit_proof(2, 1, vec![5, 6], result_inputs(2), key_hash, [1u8; 32]);
+            assert_eq!(result, Err(Error::VerificationKeyInactive));
+            contract.verify_proof(queued).unwrap();
+            assert_ne!(contract.get_proof(queued).unwrap().status, ProofStatus::Pending);
+
+            contract.deactivate_verification_key(new_key_hash).unwrap();
+            let result = contract.submit_proof(2, 1, vec![5, 6], result_inputs(2), new_key_hash, [1u8; 32]);
+            assert_eq!(result, Err(Error::VerificationKeyInactive));
+        }
+
+        #[ink::test]
+        fn test_submit_proof() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ZKVerifier::new(
+                accounts.alice,
+                accounts.bob,
+                1000,
+                86400000,
+            );
+
+            // First register a verification key
+            let key_data = vec![1, 2, 3, 4];
+            let key_hash = contract.register_verification_key(
+                key_data,
+                "halo2".to_string(),
+            ).unwrap();
+
+            // Then submit a proof
+            let result = contract.submit_proof(
+                1,
+                1,
+                vec![5, 6, 7, 8],
+                result_inputs(1),
+                key_hash,
+                [0u8; 32],
+            );
+
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), 1);
         }
 
-        fn calculate_proof_hash(&self, proof: &ZKProof) -> [u8; 32] {
-            // Calculate hash of proof for payment completion
-            use ink::env::hash::{Keccak256, HashOutput};
+        #[ink::test]
+        fn test_submit_proof_rejects_replayed_nullifier() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ZKVerifier::new(
+                accounts.alice,
+                accounts.bob,
+                1000,
+                86400000,
+            );
 
-            let mut input = Vec::new();
-            input.extend_from_slice(&proof.proof_data);
-            input.extend_from_slice(&proof.public_inputs);
+            let key_hash = contract.register_verification_key(
+                vec![1, 2, 3, 4],
+                "halo2".to_string(),
+            ).unwrap();
 
-            let mut output = <Keccak256 as HashOutput>::Type::default();
-            ink::env::hash_bytes::<Keccak256>(&input, &mut output);
-            output.into()
+            let nullifier = contract.compute_nullifier(result_inputs(1));
+            assert_eq!(contract.get_nullifier_proof(nullifier), None);
+
+            let proof_id = contract.submit_proof(1, 1, vec![5, 6], result_inputs(1), key_hash, [1u8; 32]).unwrap();
+            assert_eq!(contract.get_nullifier_proof(nullifier), Some(proof_id));
+
+            // Same inputs replayed for another query; a new challenge hash does not help
+            let result = contract.submit_proof(2, 1, vec![7, 8], result_inputs(1), key_hash, [1u8; 32]);
+            assert_eq!(result, Err(Error::NullifierAlreadyUsed));
+            let result = contract.submit_proof(2, 1, vec![7, 8], result_inputs(1), key_hash, [2u8; 32]);
+            assert_eq!(result, Err(Error::NullifierAlreadyUsed));
+
+            // The same answer to another query names that query, so it does not collide
+            assert!(contract.submit_proof(2, 1, vec![7, 8], result_inputs(2), key_hash, [1u8; 32]).is_ok());
         }
 
-        fn hash_data(&self, data: &[u8]) -> [u8; 32] {
-            use ink::env::hash::{Keccak256, HashOutput};
+        #[ink::test]
+        fn test_submit_proof_checks_result_commitment() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ZKVerifier::new(
+                accounts.alice,
+                accounts.bob,
+                1000,
+                86400000,
+            );
 
-            let mut output = <Keccak256 as HashOutput>::Type::default();
-            ink::env::hash_bytes::<Keccak256>(data, &mut output);
-            output.into()
+            let key_hash = contract.register_verification_key(
+                vec![1, 2, 3, 4],
+                "halo2".to_string(),
+            ).unwrap();
+
+            let result = contract.submit_proof(1, 1, vec![5, 6], vec![9, 10], key_hash, [0u8; 32]);
+            assert_eq!(result, Err(Error::InvalidResultCommitment));
+
+            // An answer to another query
+            let result = contract.submit_proof(1, 1, vec![5, 6], result_inputs(2), key_hash, [0u8; 32]);
+            assert_eq!(result, Err(Error::InvalidResultCommitment));
+
+            // The payment bought the top-1 result only
+            let top_two = ResultCommitment {
+                query_id: 1,
+                query_hash: [3u8; 32],
+                k: 2,
+                metric: Metric::Cosine,
+                dataset_id: 1,
+                leaf_indices: vec![0, 1],
+                scores: vec![65536, 1000],
+                dataset_version: [0u8; 32],
+                payment_nullifier: None,
+            };
+            let result = contract.submit_proof(1, 1, vec![5, 6], top_two.encode(), key_hash, [0u8; 32]);
+            assert_eq!(result, Err(Error::ResultCountMismatch));
+
+            let mut stale = ResultCommitment::decode(&result_inputs(1)).unwrap();
+            stale.dataset_version = [9u8; 32];
+            let result = contract.submit_proof(1, 1, vec![5, 6], stale.encode(), key_hash, [0u8; 32]);
+            assert_eq!(result, Err(Error::DatasetVersionMismatch));
+
+            // The mock dataset is scored by cosine similarity and has 16 entries
+            let mut wrong_metric = ResultCommitment::decode(&result_inputs(1)).unwrap();
+            wrong_metric.metric = Metric::L2;
+            let result = contract.submit_proof(1, 1, vec![5, 6], wrong_metric.encode(), key_hash, [0u8; 32]);
+            assert_eq!(result, Err(Error::MetricMismatch));
+
+            let mut out_of_range = ResultCommitment::decode(&result_inputs(1)).unwrap();
+            out_of_range.leaf_indices = vec![16];
+            let result = contract.submit_proof(1, 1, vec![5, 6], out_of_range.encode(), key_hash, [0u8; 32]);
+            assert_eq!(result, Err(Error::ResultOutOfRange));
         }
 
-        // Cross-contract call helpers (would be actual cross-contract calls)
-        fn complete_payment(&self, query_id: u64, proof_hash: [u8; 32]) -> Result<()> {
-            // This would be a cross-contract call to payment manager
-            // For now, just return Ok
-            Ok(())
+        #[ink::test]
+        fn test_private_proof_names_nullifier_not_payment() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ZKVerifier::new(
+                accounts.alice,
+                accounts.bob,
+                1000,
+                86400000,
+            );
+
+            let key_hash = contract.register_verification_key(
+                vec![1, 2, 3, 4],
+                "halo2".to_string(),
+            ).unwrap();
+
+            // Query 100 is a private payment, which no proof answers by its ID
+            let result = contract.submit_proof(100, 1, vec![5, 6], result_inputs(100), key_hash, [0u8; 32]);
+            assert_eq!(result, Err(Error::DatasetMismatch));
+
+            let result = contract.submit_private_proof(1, vec![5, 6], result_inputs(0), key_hash, [0u8; 32]);
+            assert_eq!(result, Err(Error::QueryCommitmentMismatch));
+
+            let mut answer = ResultCommitment::decode(&result_inputs(100)).unwrap();
+            answer.payment_nullifier = Some([9u8; 32]);
+            let result = contract.submit_private_proof(1, vec![5, 6], answer.encode(), key_hash, [0u8; 32]);
+            assert_eq!(result, Err(Error::InvalidResultCommitment));
+
+            answer.query_id = 0;
+            answer.dataset_id = 2;
+            let result = contract.submit_private_proof(1, vec![5, 6], answer.encode(), key_hash, [0u8; 32]);
+            assert_eq!(result, Err(Error::DatasetMismatch));
+
+            // The proof is stored under the settlement the payment manager opened
+            answer.dataset_id = 1;
+            answer.k = 2;
+            answer.leaf_indices = vec![0, 1];
+            answer.scores = vec![65536, 1000];
+            let proof_id = contract.submit_private_proof(1, vec![5, 6], answer.encode(), key_hash, [0u8; 32]).unwrap();
+            let proof = contract.get_proof(proof_id).unwrap();
+            assert_eq!(proof.query_id, 1000 + proof_id);
+            assert_eq!(contract.get_proof_by_query(proof.query_id), Some(proof));
+
+            contract.verify_proof(proof_id).unwrap();
+            assert_eq!(contract.get_proof(proof_id).unwrap().status, ProofStatus::Verified);
         }
 
-        fn refund_payment(&self, query_id: u64) -> Result<()> {
-            // This would be a cross-contract call to payment manager
-            // For now, just return Ok
-            Ok(())
+        #[ink::test]
+        fn test_submit_proof_rejects_wrong_dataset_and_prover() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ZKVerifier::new(
+                accounts.alice,
+                accounts.bob,
+                1000,
+                86400000,
+            );
+
+            let key_hash = contract.register_verification_key(
+                vec![1, 2, 3, 4],
+                "halo2".to_string(),
+            ).unwrap();
+
+            // Query 1 was paid for dataset 1, not dataset 2
+            let result = contract.submit_proof(1, 2, vec![5, 6], result_inputs(1), key_hash, [0u8; 32]);
+            assert_eq!(result, Err(Error::DatasetMismatch));
+
+            // Charlie is neither the dataset owner nor one of its validators
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            let result = contract.submit_proof(1, 1, vec![5, 6], result_inputs(1), key_hash, [0u8; 32]);
+            assert_eq!(result, Err(Error::NotAuthorized));
         }
-    }
 
-    /// Unit tests
-    #[cfg(test)]
-    mod tests {
-        use super::*;
+        #[ink::test]
+        fn test_challenge_window_starts_at_verification() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ZKVerifier::new(
+                accounts.alice,
+                accounts.bob,
+                1000,
+                1000,
+            );
+
+            let key_hash = contract.register_verification_key(
+                vec![1, 2, 3, 4],
+                "halo2".to_string(),
+            ).unwrap();
+            let proof_id = contract.submit_proof(1, 1, vec![5, 6], result_inputs(1), key_hash, [0u8; 32]).unwrap();
+
+            // A proof verified late still gets the full challenge window
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(5000);
+            contract.verify_proof(proof_id).unwrap();
+            assert_eq!(contract.get_challenge_deadline(1), Some(6000));
+            assert!(!contract.is_proof_final(1));
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(6001);
+            assert!(contract.is_proof_final(1));
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let result = contract.challenge_proof(proof_id, "late".to_string());
+            assert_eq!(result, Err(Error::ChallengePeriodExpired));
+        }
 
         #[ink::test]
-        fn test_register_verification_key() {
+        fn test_payload_limits_and_storage_deposit() {
             let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
             let mut contract = ZKVerifier::new(
                 accounts.alice,
                 accounts.bob,
                 1000,
-                86400000,
+                1000,
             );
 
-            let key_data = vec![1, 2, 3, 4];
-            let result = contract.register_verification_key(
-                key_data,
+            let limits = PayloadLimits {
+                max_proof_size: 4,
+                max_public_inputs_size: 1024,
+                max_key_size: 8,
+            };
+            contract.queue_change(ParameterChange::PayloadLimits("halo2".to_string(), limits)).unwrap();
+            contract.queue_change(ParameterChange::StorageDepositPerByte(10)).unwrap();
+            let start = DEFAULT_TIMELOCK_DELAY;
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(start);
+            contract.execute_change(1).unwrap();
+            contract.execute_change(2).unwrap();
+
+            let result = contract.register_verification_key(vec![0; 9], "halo2".to_string());
+            assert_eq!(result, Err(Error::VerificationKeyTooLarge));
+            let key_hash = contract.register_verification_key(
+                vec![1, 2, 3, 4],
                 "halo2".to_string(),
+            ).unwrap();
+
+            let result = contract.submit_proof(1, 1, vec![0; 5], result_inputs(1), key_hash, [0u8; 32]);
+            assert_eq!(result, Err(Error::ProofTooLarge));
+
+            let stored_bytes = 2 + result_inputs(1).len() as u32;
+            let deposit = contract.get_required_deposit(stored_bytes);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(deposit - 1);
+            let result = contract.submit_proof(1, 1, vec![5, 6], result_inputs(1), key_hash, [0u8; 32]);
+            assert_eq!(result, Err(Error::InsufficientDeposit));
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(deposit);
+            let proof_id = contract.submit_proof(1, 1, vec![5, 6], result_inputs(1), key_hash, [0u8; 32]).unwrap();
+            assert_eq!(contract.get_proof(proof_id).unwrap().storage_deposit, deposit);
+
+            contract.verify_proof(proof_id).unwrap();
+            assert_eq!(contract.prune_proof(proof_id), Err(Error::ProofNotFinal));
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(start + 1001);
+            contract.prune_proof(proof_id).unwrap();
+            assert!(contract.get_proof(proof_id).is_none());
+            assert_eq!(contract.prune_proof(proof_id), Err(Error::ProofAlreadyPruned));
+
+            // The receipt keeps the query settled and final
+            let receipt = contract.get_proof_receipt(proof_id).unwrap();
+            assert_eq!(receipt.status, ProofStatus::Verified);
+            assert_eq!(receipt.verified_at, Some(start));
+            assert!(contract.is_proof_final(1));
+            assert_eq!(contract.get_challenge_deadline(1), Some(start + 1000));
+        }
+
+        #[ink::test]
+        fn test_reputation_tracks_outcomes() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ZKVerifier::new(
+                accounts.alice,
+                accounts.bob,
+                1000,
+                1000,
             );
-            
-            assert!(result.is_ok());
+            assert_eq!(contract.get_reputation_score(accounts.alice), crate::reputation::NEUTRAL_SCORE);
+
+            let key_hash = contract.register_verification_key(
+                vec![1, 2, 3, 4],
+                "halo2".to_string(),
+            ).unwrap();
+            let good = contract.submit_proof(1, 1, vec![5, 6], result_inputs(1), key_hash, [1u8; 32]).unwrap();
+            let bad = contract.submit_proof(2, 1, vec![], result_inputs(2), key_hash, [2u8; 32]).unwrap();
+            contract.verify_proof(good).unwrap();
+            contract.verify_proof(bad).unwrap();
+
+            // Alice proves for her own dataset, so each outcome counts once
+            let record = contract.get_reputation(accounts.alice);
+            assert_eq!(record.verified_proofs, 1);
+            assert_eq!(record.rejected_proofs, 1);
+            assert_eq!(contract.get_reputation_score(accounts.alice), 3333);
+
+            // Only the payment manager reports dispute refunds
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(contract.report_dispute_refund(accounts.charlie), Err(Error::NotAuthorized));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            contract.report_dispute_refund(accounts.charlie).unwrap();
+            assert_eq!(contract.get_reputation(accounts.charlie).disputes_refunded, 1);
+            assert_eq!(contract.get_reputation_score(accounts.charlie), 2500);
         }
 
         #[ink::test]
-        fn test_submit_proof() {
+        fn test_aggregated_proof_covers_queries() {
             let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
             let mut contract = ZKVerifier::new(
                 accounts.alice,
@@ -587,25 +2816,360 @@ mod zk_verifier {
                 86400000,
             );
 
-            // First register a verification key
-            let key_data = vec![1, 2, 3, 4];
             let key_hash = contract.register_verification_key(
-                key_data,
+                vec![1, 2, 3, 4],
                 "halo2".to_string(),
             ).unwrap();
 
-            // Then submit a proof
-            let result = contract.submit_proof(
+            let query_ids = vec![3, 4, 5];
+            let public_inputs: Vec<Vec<u8>> = query_ids.iter().map(|id| result_inputs(*id)).collect();
+            let proof_id = contract.submit_aggregated_proof(
+                query_ids.clone(),
                 1,
+                vec![5, 6, 7, 8],
+                public_inputs.clone(),
+                key_hash,
+                [0u8; 32],
+            ).unwrap();
+
+            for query_id in &query_ids {
+                assert_eq!(contract.get_proof_by_query(*query_id).unwrap().proof_id, proof_id);
+            }
+            let commitment = contract.get_inputs_commitment(query_ids.clone(), public_inputs).unwrap();
+            assert_eq!(contract.get_aggregation(proof_id).unwrap().inputs_commitment, commitment);
+            assert_eq!(contract.get_proof(proof_id).unwrap().public_inputs, commitment.to_vec());
+
+            // Covered queries can no longer be proven on their own
+            let result = contract.submit_proof(4, 1, vec![5, 6], result_inputs(4), key_hash, [0u8; 32]);
+            assert_eq!(result, Err(Error::ProofAlreadyVerified));
+
+            contract.verify_proof(proof_id).unwrap();
+            assert_eq!(contract.get_proof_by_query(5).unwrap().status, ProofStatus::Verified);
+
+            let result = contract.submit_aggregated_proof(vec![6, 6], 1, vec![5], vec![result_inputs(6); 2], key_hash, [0u8; 32]);
+            assert_eq!(result, Err(Error::InvalidAggregation));
+        }
+
+        #[ink::test]
+        fn test_reclaimed_query_rejects_proof_without_blame() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ZKVerifier::new(
+                accounts.alice,
+                accounts.bob,
+                1000,
+                86400000,
+            );
+
+            let key_hash = contract.register_verification_key(
+                vec![1, 2, 3, 4],
+                "halo2".to_string(),
+            ).unwrap();
+            let single = contract.submit_proof(1, 1, vec![5, 6], result_inputs(1), key_hash, [0u8; 32]).unwrap();
+            let public_inputs = vec![result_inputs(3), result_inputs(4)];
+            let aggregated = contract.submit_aggregated_proof(
+                vec![3, 4],
                 1,
                 vec![5, 6, 7, 8],
-                vec![9, 10],
+                public_inputs,
                 key_hash,
                 [0u8; 32],
+            ).unwrap();
+
+            // The users reclaim queries 1 and 4 while the proofs wait
+            MOCK_REFUNDED_QUERIES.with(|queries| queries.borrow_mut().extend([1, 4]));
+            contract.verify_proof(single).unwrap();
+            assert_eq!(contract.get_proof(single).unwrap().status, ProofStatus::Rejected);
+            assert_eq!(contract.get_reputation(accounts.alice).rejected_proofs, 0);
+            contract.prune_proof(single).unwrap();
+
+            // Query 3 is still paid for
+            contract.verify_proof(aggregated).unwrap();
+            assert_eq!(contract.get_proof(aggregated).unwrap().status, ProofStatus::Verified);
+            assert_eq!(contract.get_reputation(accounts.alice).verified_proofs, 1);
+        }
+
+        #[ink::test]
+        fn test_verify_proofs_batch() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ZKVerifier::new(
+                accounts.alice,
+                accounts.bob,
+                1000,
+                86400000,
             );
 
-            assert!(result.is_ok());
-            assert_eq!(result.unwrap(), 1);
+            let key_hash = contract.register_verification_key(
+                vec![1, 2, 3, 4],
+                "halo2".to_string(),
+            ).unwrap();
+            let good = contract.submit_proof(1, 1, vec![5, 6], result_inputs(1), key_hash, [0u8; 32]).unwrap();
+            let bad = contract.submit_proof(2, 1, vec![], result_inputs(2), key_hash, [2u8; 32]).unwrap();
+
+            // The malformed proof fails the batch, the fallback isolates it
+            let results = contract.verify_proofs(vec![bad, good, 99]).unwrap();
+            assert_eq!(results, vec![
+                (bad, Ok(false)),
+                (good, Ok(true)),
+                (99, Err(Error::ProofNotFound)),
+            ]);
+            assert_eq!(contract.get_proof(good).unwrap().status, ProofStatus::Verified);
+            assert_eq!(contract.get_proof(bad).unwrap().status, ProofStatus::Rejected);
+
+            assert_eq!(contract.verify_proofs(vec![good, good]), Err(Error::InvalidBatch));
+        }
+
+        #[ink::test]
+        fn test_batch_verification_matches_individual_checks() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut batched = ZKVerifier::new(accounts.alice, accounts.bob, 1000, 86400000);
+            let mut individual = ZKVerifier::new(accounts.alice, accounts.bob, 1000, 86400000);
+
+            // The same proofs, one of them malformed, submitted to both verifiers
+            let mut proof_ids = Vec::new();
+            for contract in [&mut batched, &mut individual] {
+                let key_hash = contract.register_verification_key(
+                    vec![1, 2, 3, 4],
+                    "halo2".to_string(),
+                ).unwrap();
+                proof_ids = (1..=8u64)
+                    .map(|query_id| {
+                        let proof_data = if query_id == 5 { vec![] } else { vec![5, 6] };
+                        contract.submit_proof(query_id, 1, proof_data, result_inputs(query_id), key_hash, [query_id as u8; 32]).unwrap()
+                    })
+                    .collect();
+            }
+
+            let results = batched.verify_proofs(proof_ids.clone()).unwrap();
+            for (proof_id, outcome) in results {
+                individual.verify_proof(proof_id).unwrap();
+                let status = individual.get_proof(proof_id).unwrap().status;
+                assert_eq!(outcome, Ok(status == ProofStatus::Verified));
+                assert_eq!(batched.get_proof(proof_id).unwrap().status, status);
+            }
+            assert_eq!(individual.get_proof(proof_ids[4]).unwrap().status, ProofStatus::Rejected);
+
+            // No gas or weight saving can be measured yet: both paths run the
+            // same mock checks, and the batch only gets cheaper once a real
+            // multi-pairing verifier replaces `batch_verify_internal`
+        }
+
+        #[ink::test]
+        fn test_verify_embedding_inclusion() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let contract = ZKVerifier::new(
+                accounts.alice,
+                accounts.bob,
+                1000,
+                86400000,
+            );
+
+            let left = contract.embedding_leaf_hash(0, &[1, 2]);
+            let right = contract.embedding_leaf_hash(1, &[3, 4]);
+            let mut input = left.to_vec();
+            input.extend_from_slice(&right);
+            let root = contract.hash_data(&input);
+
+            assert!(contract.verify_embedding_inclusion(root, 1, &[3, 4], &[left]));
+            assert!(contract.verify_embedding_inclusion(root, 0, &[1, 2], &[right]));
+            assert!(!contract.verify_embedding_inclusion(root, 1, &[3, 5], &[left]));
+            assert!(!contract.verify_embedding_inclusion(root, 3, &[3, 4], &[left]));
+        }
+
+        #[ink::test]
+        fn test_fraud_proof_requires_matching_evidence() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ZKVerifier::new(
+                accounts.alice,
+                accounts.bob,
+                1000,
+                86400000,
+            );
+
+            let key_hash = contract.register_verification_key(
+                vec![1, 2, 3, 4],
+                "halo2".to_string(),
+            ).unwrap();
+            let query_vector = vec![65536, 0];
+            let commitment = ResultCommitment {
+                query_id: 1,
+                query_hash: contract.hash_data(&vector_math::encode_vector(&query_vector)),
+                k: 1,
+                metric: Metric::Cosine,
+                dataset_id: 1,
+                leaf_indices: vec![0],
+                scores: vec![65536],
+                dataset_version: [0u8; 32],
+                payment_nullifier: None,
+            };
+            let proof_id = contract.submit_proof(
+                1,
+                1,
+                vec![5, 6],
+                commitment.encode(),
+                key_hash,
+                [0u8; 32],
+            ).unwrap();
+            contract.verify_proof(proof_id).unwrap();
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+
+            // A different query vector than the one the proof answered
+            let result = contract.challenge_with_fraud_proof(proof_id, 0, vec![0, 65536], vec![0, 65536], vec![]);
+            assert_eq!(result, Err(Error::InvalidFraudProof));
+
+            // A result vector that is not in the dataset's embedding tree
+            let result = contract.challenge_with_fraud_proof(proof_id, 0, query_vector.clone(), vec![0, 65536], vec![]);
+            assert_eq!(result, Err(Error::InvalidFraudProof));
+
+            // There is no second result in a top-1 answer
+            let result = contract.challenge_with_fraud_proof(proof_id, 1, query_vector.clone(), vec![0, 65536], vec![]);
+            assert_eq!(result, Err(Error::InvalidFraudProof));
+
+            // The mock dataset holds 2-dimensional vectors
+            let result = contract.challenge_with_fraud_proof(proof_id, 0, query_vector, vec![0, 0, 65536], vec![]);
+            assert_eq!(result, Err(Error::DimensionMismatch));
+            assert!(contract.get_proof_challenges(proof_id).is_empty());
+        }
+
+        #[ink::test]
+        fn test_fraud_proof_resolves_challenge() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ZKVerifier::new(
+                accounts.alice,
+                accounts.bob,
+                1000,
+                86400000,
+            );
+
+            // A two-leaf embedding tree registered as the dataset's root
+            let first = vec![0, 65536];
+            let second = vec![65536, 0];
+            let left = contract.embedding_leaf_hash(0, &first);
+            let right = contract.embedding_leaf_hash(1, &second);
+            let mut input = left.to_vec();
+            input.extend_from_slice(&right);
+            let root = contract.hash_data(&input);
+            MOCK_EMBEDDING_ROOT.with(|cell| cell.set(root));
+
+            let key_hash = contract.register_verification_key(
+                vec![1, 2, 3, 4],
+                "halo2".to_string(),
+            ).unwrap();
+            let query_vector = vec![65536, 0];
+            let commitment = |query_id: u64, leaf_index: u64| ResultCommitment {
+                query_id,
+                query_hash: contract.hash_data(&vector_math::encode_vector(&query_vector)),
+                k: 1,
+                metric: Metric::Cosine,
+                dataset_id: 1,
+                leaf_indices: vec![leaf_index],
+                scores: vec![65536],
+                dataset_version: root,
+                payment_nullifier: None,
+            };
+            // Leaf 0 is orthogonal to the query, leaf 1 matches it exactly
+            let wrong_result = commitment(1, 0).encode();
+            let honest_result = commitment(2, 1).encode();
+            let wrong = contract.submit_proof(1, 1, vec![5, 6], wrong_result, key_hash, [0u8; 32]).unwrap();
+            let honest = contract.submit_proof(2, 1, vec![5, 6], honest_result, key_hash, [1u8; 32]).unwrap();
+            contract.verify_proof(wrong).unwrap();
+            contract.verify_proof(honest).unwrap();
+
+            // The recomputed score differs from the claimed one: the prover loses
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            let challenge_id = contract.challenge_with_fraud_proof(wrong, 0, query_vector.clone(), first, vec![right]).unwrap();
+            assert_eq!(contract.get_challenge(challenge_id).unwrap().status, ChallengeStatus::Resolved);
+            assert_eq!(contract.get_proof(wrong).unwrap().status, ProofStatus::Rejected);
+
+            // The recomputed score matches: the challenge is dismissed
+            let challenge_id = contract.challenge_with_fraud_proof(honest, 0, query_vector, second, vec![left]).unwrap();
+            assert_eq!(contract.get_challenge(challenge_id).unwrap().status, ChallengeStatus::Dismissed);
+            assert_eq!(contract.get_proof(honest).unwrap().status, ProofStatus::Verified);
+        }
+
+        #[ink::test]
+        fn test_multiple_challenges_and_expiry() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ZKVerifier::new(
+                accounts.alice,
+                accounts.bob,
+                1000,
+                1000,
+            );
+
+            let key_hash = contract.register_verification_key(
+                vec![1, 2, 3, 4],
+                "halo2".to_string(),
+            ).unwrap();
+            let proof_id = contract.submit_proof(1, 1, vec![5, 6], result_inputs(1), key_hash, [0u8; 32]).unwrap();
+            contract.verify_proof(proof_id).unwrap();
+
+            // A second challenge can join one that is already open
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            let first = contract.challenge_proof(proof_id, "wrong result".to_string()).unwrap();
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(500);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            let second = contract.challenge_proof(proof_id, "wrong root".to_string()).unwrap();
+
+            // Dismissing one leaves the proof challenged by the other
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            contract.resolve_challenge(first, false).unwrap();
+            assert_eq!(contract.get_proof(proof_id).unwrap().status, ProofStatus::Challenged);
+
+            // Nobody resolves the second one in time
+            assert_eq!(contract.finalize_expired_challenge(second), Err(Error::ChallengeNotExpired));
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(1501);
+            assert_eq!(contract.resolve_challenge(second, true), Err(Error::ResolutionDeadlinePassed));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.finalize_expired_challenge(second).unwrap();
+            assert_eq!(contract.get_challenge(second).unwrap().status, ChallengeStatus::Expired);
+            assert_eq!(contract.get_proof(proof_id).unwrap().status, ProofStatus::Verified);
+        }
+
+        #[ink::test]
+        fn test_v1_proofs_migrate_without_challenge_window() {
+            use ink::storage::traits::StorageKey;
+
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+
+            // Storage as left behind by version 1 code: the root holds only
+            // its contract addresses, counters, owner and challenge settings
+            let root_key = <ZKVerifier as StorageKey>::KEY;
+            let root = (accounts.alice, accounts.bob, 2u64, 1u64, accounts.alice, 1000u128, 86400000u64);
+            ink::env::set_contract_storage(&root_key, &root);
+            let proof = (
+                1u64,
+                4u64, // query_id
+                1u64,
+                accounts.charlie,
+                vec![1u8; 8],
+                vec![2u8; 8],
+                [3u8; 32],
+                50u64, // created_at
+                1u8,   // ProofStatus::Verified
+                [4u8; 32],
+            );
+            ink::env::set_contract_storage(&(PROOFS_V1_KEY, 1u64), &proof);
+
+            let mut contract: ZKVerifier = ink::env::get_contract_storage(&root_key).unwrap().unwrap();
+            assert_eq!(contract.get_storage_version(), 1);
+            assert_eq!(contract.owner(), accounts.alice);
+            assert_eq!(contract.get_required_deposit(100), 0);
+            assert_eq!(contract.get_timelock_delay(), DEFAULT_TIMELOCK_DELAY);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(contract.migrate(10), Err(Error::NotAuthorized));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(contract.migrate(10), Ok(1));
+            assert_eq!(contract.get_storage_version(), 2);
+            let proof = contract.get_proof(1).unwrap();
+            assert_eq!(proof.query_id, 4);
+            assert_eq!(proof.verified_at, Some(50));
+            assert_eq!(proof.storage_deposit, 0);
+            assert!(contract.has_role(Role::Admin, accounts.alice));
+            assert_eq!(contract.migrate(10), Err(Error::AlreadyMigrated));
         }
     }
 }
\ No newline at end of file